@@ -15,6 +15,72 @@ pub mod app_config_variables {
         pub client_id: String,
         #[serde(rename = "CLIENT_SUMMARY")]
         pub summary: Files,
+        /// Directory served by the TCP/HTTP servers; `~/zync-storage` when unset.
+        #[serde(
+            rename = "STORAGE_PATH",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub storage_path: Option<String>,
+        /// Largest file, in bytes, the servers accept; unlimited when unset.
+        #[serde(
+            rename = "MAX_UPLOAD_SIZE",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub max_upload_size: Option<u64>,
+        /// Byte quota given to each user the servers register; unlimited when unset.
+        #[serde(
+            rename = "USER_QUOTA",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub user_quota: Option<u64>,
+        /// How many clients the servers serve at once; 32 when unset.
+        #[serde(
+            rename = "MAX_CLIENTS",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub max_clients: Option<usize>,
+        /// How many connections a single IP address may hold; 4 when unset.
+        #[serde(
+            rename = "MAX_CLIENTS_PER_IP",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub max_clients_per_ip: Option<usize>,
+        /// Seconds a connection may wait for a free slot before it is refused; 30 when unset.
+        #[serde(
+            rename = "QUEUE_TIMEOUT_SECS",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub queue_timeout_secs: Option<u64>,
+        /// How many transfers the client runs at once; 3 when unset.
+        #[serde(
+            rename = "MAX_CONCURRENT_TRANSFERS",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub max_concurrent_transfers: Option<usize>,
+        /// How many times the client retries a failed transfer; 4 when unset.
+        #[serde(
+            rename = "TRANSFER_RETRIES",
+            default,
+            skip_serializing_if = "Option::is_none"
+        )]
+        pub transfer_retries: Option<u32>,
+        /// Port the server started from the TUI serves its HTTPS routes on when `TLS` is set, told to clients as they pair; any free port when unset.
+        #[serde(rename = "HTTP_PORT", default, skip_serializing_if = "Option::is_none")]
+        pub http_port: Option<u16>,
+        /// Serve and connect over TLS, with self-signed server certificates pinned by fingerprint; on when unset. Without it the server starts no HTTP listener, since session tokens are only accepted over HTTPS, and clients transfer everything over their TCP connection.
+        #[serde(rename = "TLS", default = "default_tls")]
+        pub tls: bool,
+    }
+
+    fn default_tls() -> bool {
+        true
     }
 
     #[derive(Debug, Serialize, Deserialize)]
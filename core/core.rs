@@ -1,11 +1,18 @@
 use rand::distr::Alphanumeric;
 use std::any::type_name;
+use std::path::PathBuf;
+use std::time::Duration;
 use std::{collections::BTreeMap, error::Error, fs};
 
 use nanoid::nanoid;
 use rand::{Rng, rng};
+use tcp_server::scheduler::scheduler::{Scheduler, SchedulerConfig};
+use tcp_server::storage::storage::Storage;
+use tcp_server::tls::tls::{ServerIdentity, default_tls_dir};
+use tcp_server::users::users::UserRegistry;
 
 use crate::config::app::app_config_variables::{App, Files};
+use crate::internal::transfer_queue::QueueConfig;
 
 pub fn check_config() -> Result<(), Box<dyn std::error::Error>> {
     log::info!("Checking config");
@@ -49,6 +56,16 @@ pub fn create_config(key: &str) -> Result<(), Box<dyn Error>> {
         summary: Files {
             data: BTreeMap::new(),
         },
+        storage_path: None,
+        max_upload_size: None,
+        user_quota: None,
+        max_clients: None,
+        max_clients_per_ip: None,
+        queue_timeout_secs: None,
+        max_concurrent_transfers: None,
+        transfer_retries: None,
+        http_port: None,
+        tls: true,
     };
 
     let serialized_config = serde_json::to_string_pretty(&app_config)?;
@@ -64,6 +81,115 @@ pub fn create_config(key: &str) -> Result<(), Box<dyn Error>> {
     log::info!("Config file created successfully");
     Ok(())
 }
+pub fn load_config() -> Result<App, Box<dyn Error>> {
+    let config_path = dirs::config_dir()
+        .ok_or("Config directory not found")?
+        .join("zync")
+        .join("config.json");
+
+    let contents = fs::read_to_string(config_path)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Picks the storage a server started from the TUI should serve.
+///
+/// An explicit `override_path` (typed next to the port) wins, then the `STORAGE_PATH` entry of the config file, then the default `~/zync-storage`. Uploads are capped at the config file's `MAX_UPLOAD_SIZE`, if set.
+pub fn configured_storage(override_path: Option<&str>) -> Storage {
+    let config = load_config().ok();
+    let configured = override_path.map(str::to_string).or_else(|| {
+        config
+            .as_ref()
+            .and_then(|config| config.storage_path.clone())
+    });
+    let max_upload_size = config.and_then(|config| config.max_upload_size);
+
+    let storage = match configured {
+        Some(path) => Storage::new(expand_home(&path)),
+        None => Storage::default(),
+    };
+    storage.with_max_upload_size(max_upload_size)
+}
+
+/// Opens the registry of users served from `storage`, giving users it registers the config file's `USER_QUOTA`, if set.
+///
+/// # Errors
+/// Returns an error if the registry exists but cannot be read.
+pub fn configured_users(storage: Storage) -> Result<UserRegistry, Box<dyn Error>> {
+    let default_quota = load_config().ok().and_then(|config| config.user_quota);
+    Ok(UserRegistry::open(storage, default_quota)?)
+}
+
+/// Builds the connection scheduler a server started from the TUI should admit clients through, using the config file's `MAX_CLIENTS`, `MAX_CLIENTS_PER_IP` and `QUEUE_TIMEOUT_SECS` where set.
+pub fn configured_scheduler() -> Scheduler {
+    let defaults = SchedulerConfig::default();
+    let Ok(config) = load_config() else {
+        return Scheduler::new(defaults);
+    };
+    Scheduler::new(SchedulerConfig {
+        max_clients: config.max_clients.unwrap_or(defaults.max_clients),
+        max_per_ip: config.max_clients_per_ip.unwrap_or(defaults.max_per_ip),
+        queue_timeout: config
+            .queue_timeout_secs
+            .map_or(defaults.queue_timeout, Duration::from_secs),
+        ..defaults
+    })
+}
+
+/// The limits of the client's transfer queue, using the config file's `MAX_CONCURRENT_TRANSFERS` and `TRANSFER_RETRIES` where set.
+pub fn configured_queue() -> QueueConfig {
+    let defaults = QueueConfig::default();
+    let Ok(config) = load_config() else {
+        return defaults;
+    };
+    QueueConfig {
+        concurrency: config
+            .max_concurrent_transfers
+            .unwrap_or(defaults.concurrency)
+            .max(1),
+        retries: config.transfer_retries.unwrap_or(defaults.retries),
+        ..defaults
+    }
+}
+
+/// The port a server started from the TUI should serve its HTTP routes on: the config file's `HTTP_PORT`, or 0 to let the system pick a free one.
+pub fn configured_http_port() -> u16 {
+    load_config()
+        .ok()
+        .and_then(|config| config.http_port)
+        .unwrap_or(0)
+}
+
+/// Whether TLS is on for the TCP and HTTP transports, which it is unless the config file turns it off.
+pub fn tls_enabled() -> bool {
+    load_config().map(|config| config.tls).unwrap_or(true)
+}
+
+/// Loads the TLS identity a server started from the TUI should use, or `None` when TLS is off.
+///
+/// The server's self-signed certificate is created under `<config dir>/zync/tls` on first use and its fingerprint is logged, so it can be compared with what clients pin.
+///
+/// # Errors
+/// Returns an error if the certificate cannot be loaded or created.
+pub fn server_identity() -> Result<Option<ServerIdentity>, Box<dyn Error>> {
+    if !tls_enabled() {
+        return Ok(None);
+    }
+    let identity = ServerIdentity::load_or_create(&default_tls_dir())?;
+    log::info!("TLS certificate fingerprint: {}", identity.fingerprint());
+    Ok(Some(identity))
+}
+
+/// Expands a leading `~` to the user's home directory.
+pub fn expand_home(path: &str) -> PathBuf {
+    match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("/"));
+            home.join(rest.trim_start_matches('/'))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
 fn create_secret(len: usize) -> String {
     let client_secret = rng();
     client_secret
@@ -1,18 +1,37 @@
 use crate::core_mod::widgets::{Item, TableWidget};
 use crate::core_mod::{self, widgets};
+use crate::events::ui_update::UIUpdate;
 use crate::init::GLOBAL_RUNTIME;
-use crate::internal::forward_port::{forward_port_igd, get_local_ip};
+use crate::internal::forward_port::{close_port_forwarding, forward_port_igd, get_local_ip};
+use crate::internal::local_paths::{complete, quote, split_paths};
+use crate::internal::remote_storage::{
+    DownloadStart, delete_remote_selection, download_remote_selection, queue_download,
+    refresh_remote,
+};
 use crate::internal::session_store;
+use crate::internal::transfer_queue::TransferQueue;
+use crate::screens::connection_progress::ConnectionProgress;
 use crate::screens::debug::DebugScreen;
+use crate::screens::file_browser::FileBrowser;
 use crate::screens::home::Home;
 use crate::screens::host_type::{HostType, HostTypePopup};
+use crate::screens::path_entry::PathEntry;
 use crate::screens::popup::{FLAG, InputBox, InputMode};
 use crate::screens::protocol_popup::{ConnectionPopup, ConnectionType};
+use crate::screens::remote::RemoteBrowser;
 use crate::state::state::{ConnectionState, ScreenState};
+use crossterm::event::KeyCode;
+use ratatui::style::Stylize;
+use ratatui::text::Line;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
-use tcp_client::app::connect_sync;
-use tcp_server::tcp::tcp::TCP;
+use tcp_client::app::{Endpoint, connect};
+use tcp_client::tls::tls::TlsSettings;
+use tcp_server::pairing::pairing::Pairing;
+use tcp_server::protocol::manifest::EntryKind;
+use tcp_server::protocol::messages::{RemoteEntry, Scheme};
+use tcp_server::tcp::tcp::{ServerHandle, TCP};
 
 pub fn handle_help_key(
     home: &mut Home,
@@ -79,7 +98,18 @@ pub fn handle_n_key(
     }
 }
 
-pub fn handle_esc_key(home: &mut Home, input_box: &mut InputBox) {
+/// Handles `Esc`, which first cancels a connection attempt still in progress.
+pub fn handle_esc_key(
+    home: &mut Home,
+    input_box: &mut InputBox,
+    progress: &Mutex<ConnectionProgress>,
+) {
+    if let Some(task) = home.connecting.take() {
+        task.abort();
+        progress.lock().unwrap().state =
+            ConnectionState::Failed("Connection cancelled".to_string());
+        return;
+    }
     match home.current_screen {
         ScreenState::TcpServer => {
             home.current_screen = ScreenState::Sessions;
@@ -186,6 +216,7 @@ pub fn handle_enter_key(
                             file.status.clone(),
                             file.destination.clone(),
                             file.time.clone(),
+                            file.digest.clone(),
                         );
                     }
                 }
@@ -224,7 +255,12 @@ pub fn handle_enter_key(
 
     if home.current_screen == ScreenState::TcpServer {
         if let Ok(user_input) = input_box.submit_message() {
-            if let Ok(port) = user_input.parse::<u16>() {
+            let (port_input, storage_input) =
+                match user_input.trim().split_once(char::is_whitespace) {
+                    Some((port, path)) => (port, Some(path.trim())),
+                    None => (user_input.trim(), None),
+                };
+            if let Ok(port) = port_input.parse::<u16>() {
                 if !(1024..=65535).contains(&port) {
                     error.set_val(
                         "Port must be between 1024 and 65535".to_string(),
@@ -239,11 +275,41 @@ pub fn handle_enter_key(
                     prog.state = ConnectionState::Connecting;
                 }
                 let progress_clone = progress.clone();
+                stop_server(&mut progress.lock().unwrap());
                 let addr = &format!("0.0.0.0:{}", port);
-                match TCP::accept_connection_sync(addr, &GLOBAL_RUNTIME) {
-                    Ok(()) => {
+                let http_addr = &format!("0.0.0.0:{}", core_mod::core::configured_http_port());
+                let storage = core_mod::core::configured_storage(storage_input);
+                log::info!("Serving storage from {:?}", storage.root());
+                let scheduler = core_mod::core::configured_scheduler();
+                let identity = core_mod::core::server_identity();
+                let pairing = match &identity {
+                    Ok(Some(identity)) => Pairing::new().with_certificate(identity.fingerprint()),
+                    _ => Pairing::new(),
+                };
+                let started = identity.and_then(|identity| {
+                    let tls = identity.map(|identity| identity.acceptor()).transpose()?;
+                    let users = core_mod::core::configured_users(storage)?;
+                    TCP::start_sync(
+                        addr,
+                        http_addr,
+                        users,
+                        tls,
+                        pairing.clone(),
+                        scheduler,
+                        &GLOBAL_RUNTIME,
+                    )
+                });
+                match started {
+                    Ok(server) => {
+                        match server.http_addr() {
+                            Some(http) => log::info!("Serving HTTPS routes on {}", http),
+                            None => log::info!("Not serving HTTP routes without TLS"),
+                        }
+                        forward_server_port(server.clone(), progress.clone());
                         let mut prog = progress_clone.lock().unwrap();
                         prog.state = ConnectionState::Connected;
+                        prog.pairing = Some(pairing);
+                        prog.server = Some(server);
                         let hostname = whoami::username();
                         let ip = get_local_ip().unwrap_or_else(|_e| "unknown".to_string());
                         let now = chrono::Utc::now().to_rfc3339();
@@ -252,6 +318,7 @@ pub fn handle_enter_key(
                             ip: ip.clone(),
                             last_transfer: "N/A".to_string(),
                             last_connection: now.clone(),
+                            transfers: Vec::new(),
                         };
                         session_store::update_session_record(new_record);
                         // Do not assign a TcpStream here because the Warp server does not yield one.
@@ -277,6 +344,7 @@ pub fn handle_enter_key(
                         format_date: now.clone(),
                     },
                     files: None,
+                    server: None,
                 };
                 let mut found = false;
                 for item in table.items.iter_mut() {
@@ -313,37 +381,52 @@ pub fn handle_enter_key(
 
     if home.current_screen == ScreenState::TcpClient {
         if let Ok(user_input) = input_box.submit_message() {
-            let address = if user_input.contains(':') {
-                user_input.clone()
+            let Some((host_input, code)) = user_input.trim().split_once(char::is_whitespace) else {
+                error.set_val(
+                    "Enter the pairing code shown on the receiving side after the address"
+                        .to_string(),
+                    &mut crate::screens::error::error_widget::ErrorType::Warning,
+                    "Ok".to_string(),
+                );
+                home.error = true;
+                return;
+            };
+            let scheme = if core_mod::core::tls_enabled() {
+                Scheme::Https
             } else {
-                format!("{}:{}", user_input, 8080)
+                Scheme::Http
+            };
+            let endpoint = match Endpoint::parse(host_input, scheme) {
+                Ok(endpoint) => endpoint,
+                Err(e) => {
+                    error.set_val(
+                        e.to_string(),
+                        &mut crate::screens::error::error_widget::ErrorType::Warning,
+                        "Ok".to_string(),
+                    );
+                    home.error = true;
+                    return;
+                }
             };
             {
                 let mut prog = progress.lock().unwrap();
                 prog.state = ConnectionState::Connecting;
             }
-            let progress_clone = progress.clone();
-            match connect_sync(&address) {
-                Ok(stream) => {
-                    let mut prog = progress_clone.lock().unwrap();
-                    prog.state = ConnectionState::Connected;
-                    let hostname = whoami::username();
-                    let ip = get_local_ip().unwrap_or_else(|_e| "unknown".to_string());
-                    let now = chrono::Utc::now().to_rfc3339();
-                    let new_record = session_store::SessionRecord {
-                        name: hostname.clone(),
-                        ip: ip.clone(),
-                        last_transfer: "N/A".to_string(),
-                        last_connection: now.clone(),
-                    };
-                    session_store::update_session_record(new_record);
-                    home.tcp_stream = Some(Arc::new(Mutex::new(stream)));
-                }
-                Err(e) => {
-                    let mut prog = progress_clone.lock().unwrap();
-                    prog.state = ConnectionState::Failed(format!("Error connecting: {}", e));
-                }
-            };
+            let tls = (endpoint.scheme == Scheme::Https).then(|| TlsSettings {
+                pinned: session_store::pinned_fingerprint(&endpoint.address()),
+            });
+            let ui_update_tx = home.ui_update_tx.clone();
+            let code = code.trim().to_string();
+            let task = GLOBAL_RUNTIME.spawn(async move {
+                let update = match connect(endpoint, tls.as_ref(), &code).await {
+                    Ok(connection) => UIUpdate::Connected(Box::new(connection)),
+                    Err(e) => UIUpdate::ConnectionFailed(format!("Error connecting: {}", e)),
+                };
+                ui_update_tx.send(update).ok();
+            });
+            if let Some(previous) = home.connecting.replace(task.abort_handle()) {
+                previous.abort();
+            }
             let hostname = whoami::username();
             let ip = get_local_ip().unwrap_or("unknown".to_string());
             let now = chrono::Utc::now().to_rfc3339();
@@ -360,6 +443,7 @@ pub fn handle_enter_key(
                     format_date: now.clone(),
                 },
                 files: None,
+                server: None,
             };
             let mut found = false;
             for item in table.items.iter_mut() {
@@ -421,6 +505,197 @@ pub fn handle_d_key(home: &mut Home, debug: &mut DebugScreen) {
     }
 }
 
+/// Opens the folder picker when `f` is pressed on the Sessions screen, uploading the picked folder with its whole tree. Without a desktop for the picker, the Files screen opens instead.
+pub fn handle_f_key(
+    home: &mut Home,
+    input_box: &InputBox,
+    browser: &mut FileBrowser,
+    state_snapshot: &crate::state::state::StateSnapshot,
+    debug: &mut DebugScreen,
+) {
+    if home.current_screen == ScreenState::Sessions
+        && input_box.input_mode != InputMode::Editing
+        && crate::internal::open_file::open_explorer_and_folder_select(state_snapshot, debug)
+    {
+        open_file_browser(home, browser);
+    }
+}
+
+/// Cancels the transfer highlighted on the Transfer screen when `c` is pressed, if it is still running, waiting for a retry or queued; a queued one is taken out of `queue` at once.
+pub fn handle_c_key(
+    home: &Home,
+    input_box: &InputBox,
+    table: &Mutex<TableWidget>,
+    queue: &TransferQueue,
+) {
+    if home.current_screen != ScreenState::Transfer || input_box.input_mode == InputMode::Editing {
+        return;
+    }
+    {
+        let mut table = table.lock().unwrap();
+        let Some(data) = table.selected_transfer() else {
+            return;
+        };
+        let Some(cancel) = data.cancel.take() else {
+            return;
+        };
+        cancel.cancel();
+        data.status = Line::from("Cancelling").dark_gray();
+    }
+    queue.dispatch();
+}
+
+/// Opens the Remote screen when `r` is pressed on the Sessions screen, listing the connected server's storage.
+pub fn handle_r_key(
+    home: &mut Home,
+    input_box: &InputBox,
+    remote: &mut RemoteBrowser,
+    debug: &mut DebugScreen,
+) {
+    if home.current_screen != ScreenState::Sessions || input_box.input_mode == InputMode::Editing {
+        return;
+    }
+    let Some(stream) = home.tcp_stream.clone() else {
+        debug.push_line("No active TCP connection available.");
+        return;
+    };
+    home.current_screen = ScreenState::Remote;
+    refresh_remote(&stream, &home.ui_update_tx, remote);
+}
+
+/// Handles every key pressed on the Remote screen.
+///
+/// Deleting takes two presses: the first only asks for confirmation, and any other key cancels it.
+pub fn handle_remote_key(
+    home: &mut Home,
+    key: KeyCode,
+    remote: &mut RemoteBrowser,
+    state_snapshot: &crate::state::state::StateSnapshot,
+    debug: &mut DebugScreen,
+) {
+    let Some(stream) = home.tcp_stream.clone() else {
+        home.current_screen = ScreenState::Sessions;
+        return;
+    };
+    if remote.confirm_delete {
+        remote.confirm_delete = false;
+        if matches!(key, KeyCode::Char('x') | KeyCode::Delete) {
+            delete_remote_selection(&stream, state_snapshot, &home.ui_update_tx, remote);
+        }
+        return;
+    }
+    match key {
+        KeyCode::Esc | KeyCode::Char('q') => home.current_screen = ScreenState::Sessions,
+        KeyCode::Char('d') => handle_d_key(home, debug),
+        KeyCode::Down => remote.next(),
+        KeyCode::Up => remote.previous(),
+        KeyCode::Char(' ') => remote.toggle_selected(),
+        KeyCode::Char('s') => remote.cycle_sort(),
+        KeyCode::Char('S') => remote.reverse_sort(),
+        KeyCode::Char('r') => refresh_remote(&stream, &home.ui_update_tx, remote),
+        KeyCode::Enter => {
+            let directory = remote
+                .highlighted()
+                .filter(|entry| entry.kind == EntryKind::Directory)
+                .map(|entry| entry.path.clone());
+            if let Some(path) = directory {
+                remote.enter(&path);
+                refresh_remote(&stream, &home.ui_update_tx, remote);
+            }
+        }
+        KeyCode::Backspace if remote.leave() => refresh_remote(&stream, &home.ui_update_tx, remote),
+        KeyCode::Char('g') => {
+            let ui_update_tx = home.ui_update_tx.clone();
+            match download_remote_selection(&stream, state_snapshot, &ui_update_tx, remote, debug) {
+                DownloadStart::Queued => home.current_screen = ScreenState::Transfer,
+                DownloadStart::PickFolder(targets) => {
+                    let mut browser = state_snapshot.file_browser.lock().unwrap();
+                    open_file_browser(home, &mut browser);
+                    browser.download = Some(targets);
+                }
+                DownloadStart::Nothing => {}
+            }
+        }
+        KeyCode::Char('x') | KeyCode::Delete => {
+            remote.confirm_delete = !remote.targets().is_empty();
+        }
+        _ => {}
+    }
+}
+
+/// Stops the server started from the TcpServer screen when `s` is pressed on the Sessions screen.
+pub fn handle_s_key(home: &mut Home, input_box: &InputBox, progress: &Mutex<ConnectionProgress>) {
+    if home.current_screen != ScreenState::Sessions || input_box.input_mode == InputMode::Editing {
+        return;
+    }
+    if let Some(server) = stop_server(&mut progress.lock().unwrap()) {
+        home.popup_message = Some(format!("Stopped the server on {}", server.addr()));
+        home.show_popup = true;
+    }
+}
+
+/// Stops the server started from the TcpServer screen, if one is running, and removes its UPnP port mappings in the background.
+///
+/// The server stops accepting connections at once; transfers in flight keep running until they finish.
+///
+/// # Returns
+/// The handle of the stopped server, which can be awaited until its transfers have drained.
+pub fn stop_server(progress: &mut ConnectionProgress) -> Option<ServerHandle> {
+    let server = progress.server.take()?;
+    server.shutdown();
+    log::info!("Stopped the server on {}", server.addr());
+    let ports = std::mem::take(&mut progress.forwarded_ports);
+    if !ports.is_empty() {
+        GLOBAL_RUNTIME.spawn_blocking(move || ports.into_iter().for_each(close_forwarded_port));
+    }
+    progress.pairing = None;
+    progress.state = ConnectionState::NoConnection;
+    Some(server)
+}
+
+/// Stops the running server when the app exits, removing its UPnP port mappings and waiting for its transfers to drain.
+pub fn stop_server_on_exit(progress: &Mutex<ConnectionProgress>) {
+    let (server, ports) = {
+        let mut prog = progress.lock().unwrap();
+        let ports = std::mem::take(&mut prog.forwarded_ports);
+        (stop_server(&mut prog), ports)
+    };
+    ports.into_iter().for_each(close_forwarded_port);
+    let drained = server.is_none_or(|server| GLOBAL_RUNTIME.block_on(server.stop()));
+    if !drained {
+        log::warn!("Exiting with transfers still in flight");
+    }
+}
+
+/// Asks the gateway to forward the server's TCP and HTTP ports via UPnP in the background, recording the mappings so stopping the server removes them.
+fn forward_server_port(server: ServerHandle, progress: Arc<Mutex<ConnectionProgress>>) {
+    GLOBAL_RUNTIME.spawn_blocking(move || {
+        let mut addrs = vec![server.addr()];
+        addrs.extend(server.http_addr().filter(|http| *http != server.addr()));
+        for addr in addrs {
+            let port = addr.port();
+            if let Err(e) = forward_port_igd(&addr.to_string()) {
+                log::warn!("Could not forward port {} via UPnP: {}", port, e);
+                continue;
+            }
+            let mut prog = progress.lock().unwrap();
+            if server.scheduler().is_draining() {
+                drop(prog);
+                close_forwarded_port(port);
+            } else {
+                prog.forwarded_ports.push(port);
+            }
+        }
+    });
+}
+
+fn close_forwarded_port(port: u16) {
+    match close_port_forwarding(port) {
+        Ok(()) => log::info!("Removed the UPnP mapping for port {}", port),
+        Err(e) => log::warn!("Could not remove the UPnP mapping for port {}: {}", port, e),
+    }
+}
+
 pub fn handle_char_key(c: char, input_box: &mut InputBox) {
     if input_box.input_mode == InputMode::Editing {
         input_box.enter_char(c);
@@ -448,12 +723,237 @@ pub fn handle_backspace_key(input_box: &mut InputBox) {
     }
 }
 
+/// Opens the file picker when `o` is pressed on the Sessions screen, queuing the upload of the picked files. Without a desktop for the picker, the Files screen opens instead.
 pub fn handle_o_key(
     home: &mut Home,
+    browser: &mut FileBrowser,
     state_snapshot: &crate::state::state::StateSnapshot,
     debug: &mut DebugScreen,
 ) {
-    if home.current_screen == ScreenState::Sessions {
-        crate::internal::open_file::open_explorer_and_file_select(state_snapshot, debug);
+    if home.current_screen == ScreenState::Sessions
+        && crate::internal::open_file::open_explorer_and_file_select(state_snapshot, debug)
+    {
+        open_file_browser(home, browser);
     }
 }
+
+/// Opens the Files screen when `b` is pressed on the Sessions screen, to pick files and folders to upload without the desktop's file dialog.
+pub fn handle_b_key(
+    home: &mut Home,
+    input_box: &InputBox,
+    browser: &mut FileBrowser,
+    state_snapshot: &crate::state::state::StateSnapshot,
+    debug: &mut DebugScreen,
+) {
+    if home.current_screen != ScreenState::Sessions || input_box.input_mode == InputMode::Editing {
+        return;
+    }
+    if crate::internal::open_file::can_send(state_snapshot, debug) {
+        open_file_browser(home, browser);
+    }
+}
+
+fn open_file_browser(home: &mut Home, browser: &mut FileBrowser) {
+    browser.download = None;
+    browser.refresh();
+    home.current_screen = ScreenState::Files;
+}
+
+/// Queues the download of `targets` into `destination`, picked without the desktop's folder dialog, and shows the Transfer screen.
+fn download_into(
+    home: &mut Home,
+    targets: Vec<RemoteEntry>,
+    destination: PathBuf,
+    state_snapshot: &crate::state::state::StateSnapshot,
+    debug: &mut DebugScreen,
+) {
+    let Some(stream) = home.tcp_stream.clone() else {
+        debug.push_line("No active TCP connection available.");
+        home.current_screen = ScreenState::Sessions;
+        return;
+    };
+    {
+        let mut remote = state_snapshot.remote.lock().unwrap();
+        remote.selected.clear();
+        remote.status = Some(format!("Downloading into {}", destination.display()));
+    }
+    queue_download(
+        &stream,
+        state_snapshot,
+        &home.ui_update_tx,
+        targets,
+        destination,
+        debug,
+    );
+    home.current_screen = ScreenState::Transfer;
+}
+
+/// Handles a key press on the Files screen. While the filter is being typed, keys edit it; otherwise they move through the listing, select entries and queue the upload of the targets.
+///
+/// While the screen picks the folder of a download, `g` downloads into the listed directory, `p` goes on to type the folder instead and `Esc` goes back to the Remote screen.
+pub fn handle_file_browser_key(
+    home: &mut Home,
+    key: KeyCode,
+    browser: &mut FileBrowser,
+    state_snapshot: &crate::state::state::StateSnapshot,
+    debug: &mut DebugScreen,
+) {
+    if browser.filtering {
+        match key {
+            KeyCode::Esc => browser.clear_filter(),
+            KeyCode::Enter => browser.filtering = false,
+            KeyCode::Backspace => browser.pop_filter(),
+            KeyCode::Down => browser.next(),
+            KeyCode::Up => browser.previous(),
+            KeyCode::Char(c) => browser.push_filter(c),
+            _ => {}
+        }
+        return;
+    }
+    let picking_folder = browser.download.is_some();
+    match key {
+        KeyCode::Esc if !browser.filter.is_empty() => browser.clear_filter(),
+        KeyCode::Esc | KeyCode::Char('q') if picking_folder => {
+            browser.download = None;
+            home.current_screen = ScreenState::Remote;
+        }
+        KeyCode::Char('g') if picking_folder => {
+            let targets = browser.download.take().unwrap_or_default();
+            download_into(home, targets, browser.cwd.clone(), state_snapshot, debug);
+        }
+        KeyCode::Char('p') if picking_folder => {
+            let mut input_box = state_snapshot.input_box.lock().unwrap();
+            let mut path_entry = state_snapshot.path_entry.lock().unwrap();
+            open_path_entry(home, &mut input_box, &mut path_entry);
+            path_entry.download = browser.download.take();
+            input_box.set_text(&quote(&browser.cwd.join("").to_string_lossy()));
+        }
+        KeyCode::Char(' ' | 'u') if picking_folder => {}
+        KeyCode::Esc | KeyCode::Char('q') => home.current_screen = ScreenState::Sessions,
+        KeyCode::Char('d') => handle_d_key(home, debug),
+        KeyCode::Down => browser.next(),
+        KeyCode::Up => browser.previous(),
+        KeyCode::Char(' ') => browser.toggle_selected(),
+        KeyCode::Char('/') => browser.filtering = true,
+        KeyCode::Char('.') => browser.toggle_hidden(),
+        KeyCode::Char('s') => browser.cycle_sort(),
+        KeyCode::Char('S') => browser.reverse_sort(),
+        KeyCode::Char('r') => browser.refresh(),
+        KeyCode::Enter => {
+            let directory = browser
+                .highlighted()
+                .filter(|entry| entry.is_dir)
+                .map(|entry| entry.path.clone());
+            if let Some(path) = directory {
+                browser.enter(path);
+            }
+        }
+        KeyCode::Backspace | KeyCode::Left => {
+            browser.leave();
+        }
+        KeyCode::Char('u') => {
+            let targets = browser.targets();
+            if targets.is_empty() {
+                browser.status = Some("Nothing to upload.".to_string());
+                return;
+            }
+            crate::internal::open_file::queue_uploads(state_snapshot, targets, debug);
+            browser.selected.clear();
+            home.current_screen = ScreenState::Transfer;
+        }
+        _ => {}
+    }
+}
+
+/// Opens the path entry screen when `p` is pressed on the Sessions screen, to type or paste the paths of files to upload.
+pub fn handle_p_key(
+    home: &mut Home,
+    input_box: &mut InputBox,
+    path_entry: &mut PathEntry,
+    state_snapshot: &crate::state::state::StateSnapshot,
+    debug: &mut DebugScreen,
+) {
+    if home.current_screen != ScreenState::Sessions || input_box.input_mode == InputMode::Editing {
+        return;
+    }
+    if crate::internal::open_file::can_send(state_snapshot, debug) {
+        open_path_entry(home, input_box, path_entry);
+    }
+}
+
+fn open_path_entry(home: &mut Home, input_box: &mut InputBox, path_entry: &mut PathEntry) {
+    path_entry.reset();
+    input_box.set_text("");
+    input_box.input_mode = InputMode::Editing;
+    unsafe { FLAG = true };
+    home.current_screen = ScreenState::PathEntry;
+}
+
+/// Handles a key press on the path entry screen, where every key edits the paths: `Tab` completes the last one, `Enter` queues the upload of all of them and `Esc` goes back to the Sessions screen.
+///
+/// While the screen asks for the folder of a download, `Enter` downloads into the one folder typed and `Esc` goes back to the Remote screen.
+pub fn handle_path_entry_key(
+    home: &mut Home,
+    key: KeyCode,
+    input_box: &mut InputBox,
+    path_entry: &mut PathEntry,
+    state_snapshot: &crate::state::state::StateSnapshot,
+    debug: &mut DebugScreen,
+) {
+    match key {
+        KeyCode::Esc => {
+            close_path_entry(input_box);
+            home.current_screen = match path_entry.download.take() {
+                Some(_) => ScreenState::Remote,
+                None => ScreenState::Sessions,
+            };
+        }
+        KeyCode::Tab => {
+            let completion = complete(&input_box.text());
+            input_box.set_text(&completion.text);
+            path_entry.candidates = completion.candidates;
+            path_entry.status = None;
+        }
+        KeyCode::Enter if path_entry.download.is_some() => {
+            match split_paths(&input_box.text()).as_slice() {
+                [folder] if folder.exists() && !folder.is_dir() => {
+                    path_entry.status = Some(format!("Not a folder: {}", folder.display()));
+                }
+                [folder] => {
+                    let targets = path_entry.download.take().unwrap_or_default();
+                    close_path_entry(input_box);
+                    download_into(home, targets, folder.clone(), state_snapshot, debug);
+                }
+                _ => path_entry.status = Some("Enter one folder to download into.".to_string()),
+            }
+        }
+        KeyCode::Enter => {
+            let paths = split_paths(&input_box.text());
+            let missing: Vec<String> = paths
+                .iter()
+                .filter(|path| !path.exists())
+                .map(|path| path.display().to_string())
+                .collect();
+            if paths.is_empty() {
+                path_entry.status = Some("Enter at least one path.".to_string());
+            } else if !missing.is_empty() {
+                path_entry.status = Some(format!("Not found: {}", missing.join(", ")));
+            } else {
+                crate::internal::open_file::queue_uploads(state_snapshot, paths, debug);
+                close_path_entry(input_box);
+                home.current_screen = ScreenState::Transfer;
+            }
+        }
+        KeyCode::Backspace => input_box.delete_char(),
+        KeyCode::Left => input_box.move_cursor_left(),
+        KeyCode::Right => input_box.move_cursor_right(),
+        KeyCode::Char(c) => input_box.enter_char(c),
+        _ => {}
+    }
+}
+
+fn close_path_entry(input_box: &mut InputBox) {
+    input_box.set_text("");
+    input_box.input_mode = InputMode::Normal;
+    unsafe { FLAG = false };
+}
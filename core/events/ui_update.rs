@@ -1,5 +1,52 @@
 use crate::state::state::ScreenState;
+use ratatui::text::Line;
+use std::sync::mpsc::Sender;
+use tcp_client::app::Connection;
+use tcp_server::protocol::messages::RemoteEntry;
+use tcp_server::protocol::progress::{Progress, ProgressTracker};
+
+/// What background tasks report to the UI thread, which applies it before drawing the next frame.
 pub enum UIUpdate {
     ShowPopup(String),
     SwitchScreen(ScreenState),
+    /// A running transfer's progress, for the Transfer row of transfer `id`.
+    TransferProgress {
+        id: u64,
+        progress: Progress,
+    },
+    /// The outcome of transfer `id`, and its verified digest if any.
+    TransferFinished {
+        id: u64,
+        status: Line<'static>,
+        digest: Option<String>,
+    },
+    /// A new status for transfer `id` while it is not running, such as a retry it waits for.
+    TransferStatus {
+        id: u64,
+        status: Line<'static>,
+    },
+    /// A connection was opened and paired.
+    Connected(Box<Connection>),
+    /// Connecting failed, with a message saying why.
+    ConnectionFailed(String),
+    /// The listing of the remote directory `cwd`, or why it failed.
+    RemoteListed {
+        cwd: String,
+        entries: Result<Vec<RemoteEntry>, String>,
+    },
+    /// A message for the status line of the Remote screen.
+    RemoteStatus(String),
+}
+
+/// A tracker sending the progress of transfer `id` to the UI.
+pub fn progress_reporter(ui_update_tx: &Sender<UIUpdate>, id: u64) -> ProgressTracker {
+    let ui_update_tx = ui_update_tx.clone();
+    ProgressTracker::new(move |progress: &Progress| {
+        ui_update_tx
+            .send(UIUpdate::TransferProgress {
+                id,
+                progress: *progress,
+            })
+            .ok();
+    })
 }
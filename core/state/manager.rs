@@ -1,5 +1,8 @@
 use super::state::{ScreenState, StateSnapshot};
-use crate::screens::{dashboard::table_ui, home::Home, session::draw_session_table_ui};
+use crate::screens::{
+    dashboard::table_ui, file_browser::draw_file_browser, home::Home, path_entry::draw_path_entry,
+    remote::draw_remote_browser, session::draw_session_table_ui,
+};
 use ratatui::DefaultTerminal;
 use ratatui::prelude::Widget;
 use std::error::Error;
@@ -54,6 +57,25 @@ pub fn manage_state(
             let debug = state_snapshot.debug_screen.lock().unwrap();
             debug.draw(frame, frame.area());
         }
+        ScreenState::Remote => {
+            let mut remote = state_snapshot.remote.lock().unwrap();
+            let mut table = state_snapshot.table.lock().unwrap();
+            table.active = false;
+            draw_remote_browser(frame, &mut remote);
+        }
+        ScreenState::Files => {
+            let mut browser = state_snapshot.file_browser.lock().unwrap();
+            let mut table = state_snapshot.table.lock().unwrap();
+            table.active = false;
+            draw_file_browser(frame, &mut browser);
+        }
+        ScreenState::PathEntry => {
+            let path_entry = state_snapshot.path_entry.lock().unwrap();
+            let mut table = state_snapshot.table.lock().unwrap();
+            let input_box = state_snapshot.input_box.lock().unwrap();
+            table.active = false;
+            draw_path_entry(frame, &path_entry, &input_box);
+        }
         ScreenState::TcpClient => {
             let mut input = state_snapshot.connection.lock().unwrap();
             let mut table = state_snapshot.table.lock().unwrap();
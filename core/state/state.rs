@@ -1,11 +1,15 @@
 use crate::{
     core_mod::widgets::TableWidget,
+    internal::transfer_queue::TransferQueue,
     screens::{
-        connection_progress::ConnectionProgress, debug::DebugScreen, help::help_popup::HelpPopup,
-        host_type::HostTypePopup, popup::InputBox, protocol_popup::ConnectionPopup,
+        connection_progress::ConnectionProgress, debug::DebugScreen, file_browser::FileBrowser,
+        help::help_popup::HelpPopup, host_type::HostTypePopup, path_entry::PathEntry,
+        popup::InputBox, protocol_popup::ConnectionPopup, remote::RemoteBrowser,
     },
 };
 use std::sync::{Arc, Mutex};
+use tcp_client::app::Endpoint;
+use tcp_client::tls::tls::TlsSettings;
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
 pub enum ScreenState {
@@ -19,6 +23,9 @@ pub enum ScreenState {
     TcpServer,
     TcpClient,
     Debug,
+    Remote,
+    PathEntry,
+    Files,
 }
 #[derive(Clone, Debug)]
 pub enum ConnectionState {
@@ -36,5 +43,12 @@ pub struct StateSnapshot {
     pub host: Arc<Mutex<HostTypePopup>>,
     pub progress: Arc<Mutex<ConnectionProgress>>,
     pub debug_screen: Arc<Mutex<DebugScreen>>,
+    pub remote: Arc<Mutex<RemoteBrowser>>,
+    pub path_entry: Arc<Mutex<PathEntry>>,
+    pub file_browser: Arc<Mutex<FileBrowser>>,
+    pub queue: TransferQueue,
     pub stream: bool,
+    pub tls: Option<TlsSettings>,
+    pub session: Option<String>,
+    pub endpoint: Option<Endpoint>,
 }
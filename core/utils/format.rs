@@ -0,0 +1,32 @@
+use std::time::Duration;
+
+/// Formats `bytes` with the largest binary unit that keeps the value at or above one.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Formats a rate in bytes per second like [`format_size`].
+pub fn format_rate(rate: f64) -> String {
+    format!("{}/s", format_size(rate as u64))
+}
+
+/// Formats `eta` as `m:ss`, or `h:mm:ss` past an hour.
+pub fn format_eta(eta: Duration) -> String {
+    let secs = eta.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs % 3600 / 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
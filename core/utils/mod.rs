@@ -1,3 +1,4 @@
 pub mod calculate;
+pub mod format;
 pub mod poll;
 pub mod reset_state;
@@ -10,6 +10,7 @@ use ratatui::{
 };
 
 use crate::screens::{dashboard::Data, session::Device};
+use tokio_util::sync::CancellationToken;
 
 const ITEM_HEIGHT: usize = 2;
 #[derive(Debug)]
@@ -32,6 +33,7 @@ pub struct TableWidget {
     pub colors: TableColors,
     pub help: bool,
     pub active: bool,
+    next_transfer_id: u64,
 }
 
 #[derive(Debug)]
@@ -78,10 +80,11 @@ impl TableWidget {
             active: false,
             state: TableState::default(),
             items: Vec::new(),
-            longest_item_lens: vec![0; 4],
+            longest_item_lens: vec![0; 5],
             scroll_state: ScrollbarState::default(),
             colors: TableColors::new(&tailwind::CYAN),
             help: false,
+            next_transfer_id: 0,
         }
     }
 
@@ -100,6 +103,52 @@ impl TableWidget {
         Ok(())
     }
 
+    /// Adds a queued transfer of `name` to or from `destination` that `cancel` cancels.
+    ///
+    /// # Returns
+    /// The id the transfer's progress and outcome updates refer to, which stays with its row however many items are added around it.
+    pub fn add_transfer(
+        &mut self,
+        name: String,
+        destination: String,
+        cancel: CancellationToken,
+    ) -> u64 {
+        let id = self.next_transfer_id;
+        self.next_transfer_id += 1;
+        self.add_item(
+            name,
+            "Queued",
+            destination,
+            chrono::Utc::now().to_rfc3339(),
+            None,
+        );
+        if let Some(Item::Data(data)) = self.items.last_mut() {
+            data.id = Some(id);
+            data.cancel = Some(cancel);
+        }
+        id
+    }
+
+    /// The transfer queued in this run with `id`.
+    pub fn transfer_mut(&mut self, id: u64) -> Option<&mut Data> {
+        self.items.iter_mut().find_map(|item| match item {
+            Item::Data(data) if data.id == Some(id) => Some(data),
+            _ => None,
+        })
+    }
+
+    /// The transfer highlighted on the Transfer screen, which only lists the transfers among the items.
+    pub fn selected_transfer(&mut self) -> Option<&mut Data> {
+        let i = self.state.selected()?;
+        self.items
+            .iter_mut()
+            .filter_map(|item| match item {
+                Item::Data(data) => Some(data),
+                Item::Device(_) => None,
+            })
+            .nth(i)
+    }
+
     pub fn enter(&mut self) -> Option<SelectedItem> {
         if let Some(i) = self.state.selected() {
             match &self.items[i] {
@@ -116,12 +165,17 @@ impl TableWidget {
         status: impl Into<Line<'static>>,
         destination: String,
         time: String,
+        digest: Option<String>,
     ) {
         self.items.push(Item::Data(Data {
+            id: None,
             name,
             status: status.into(),
             destination,
             time,
+            digest,
+            progress: None,
+            cancel: None,
         }));
 
         let data_items: Vec<&Data> = self
@@ -176,6 +230,7 @@ impl TableWidget {
                     + item.status.width() as u16
                     + item.destination.width() as u16
                     + item.time.width() as u16
+                    + item.digest.as_deref().map_or(1, |digest| digest.width()) as u16
             })
             .max()
             .unwrap_or(0);
@@ -209,11 +264,55 @@ impl TableWidget {
             * 100
             / total_width;
 
+        let digest_percent = items
+            .iter()
+            .map(|item| item.digest.as_deref().map_or(1, |digest| digest.width()) as u16)
+            .max()
+            .unwrap_or(0)
+            * 100
+            / total_width;
+
         vec![
             name_percent,
             status_percent,
             destination_percent,
             time_percent,
+            digest_percent,
         ]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transfers_are_found_by_id_among_devices_and_past_transfers() {
+        let mut table = TableWidget::new();
+        table.items.push(Item::Device(Device::new_empty()));
+        let first = table.add_transfer(
+            "first".to_string(),
+            "server storage".to_string(),
+            CancellationToken::new(),
+        );
+        table.items.push(Item::Device(Device::new_empty()));
+        table.add_item(
+            "history".to_string(),
+            "Verified",
+            "server storage".to_string(),
+            String::new(),
+            None,
+        );
+        let second = table.add_transfer(
+            "second".to_string(),
+            "server storage".to_string(),
+            CancellationToken::new(),
+        );
+
+        assert_ne!(first, second);
+        assert_eq!(table.transfer_mut(first).unwrap().name, "first");
+        assert_eq!(table.transfer_mut(second).unwrap().name, "second");
+        assert!(table.transfer_mut(second + 1).is_none());
+        assert!(table.transfer_mut(second).unwrap().cancel.is_some());
+    }
+}
@@ -0,0 +1,269 @@
+use crate::core_mod::core::expand_home;
+use std::fs;
+use std::path::PathBuf;
+
+/// The result of completing the last path of a path list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// The path list with its last path completed as far as the matches agree.
+    pub text: String,
+    /// The names the last path could still be completed to, directories ending in `/`; empty once it is unambiguous.
+    pub candidates: Vec<String>,
+}
+
+/// Splits `input` into the paths it lists, separated by whitespace, expanding a leading `~` in each.
+///
+/// A path may be quoted with `'` or `"` or have single characters escaped with `\`, the way terminals paste the paths of files dropped onto them.
+pub fn split_paths(input: &str) -> Vec<PathBuf> {
+    words(input)
+        .0
+        .into_iter()
+        .map(|(_, word)| expand_home(&word))
+        .collect()
+}
+
+/// Completes the last path of the path list `input` against the local file system.
+///
+/// A path with a single match is completed in full, followed by `/` for a directory or a space for a file so the next path can be typed; with several matches it is completed to their common prefix and the matches are listed. Hidden entries only match a name that starts with `.`.
+pub fn complete(input: &str) -> Completion {
+    let (words, open) = words(input);
+    let (prefix, partial) = match words.last() {
+        Some((start, word)) if open => (&input[..*start], word.as_str()),
+        _ => (input, ""),
+    };
+    let (dir, name) = match partial.rfind('/') {
+        Some(index) => partial.split_at(index + 1),
+        None => ("", partial),
+    };
+    let listed = if dir.is_empty() {
+        PathBuf::from(".")
+    } else {
+        expand_home(dir)
+    };
+
+    let mut matches: Vec<(String, bool)> = fs::read_dir(listed)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+            let visible = !file_name.starts_with('.') || name.starts_with('.');
+            (visible && file_name.starts_with(name)).then(|| (file_name, entry.path().is_dir()))
+        })
+        .collect();
+    matches.sort();
+
+    let unchanged = Completion {
+        text: input.to_string(),
+        candidates: Vec::new(),
+    };
+    match matches.as_slice() {
+        [] => unchanged,
+        [(only, is_dir)] => {
+            let suffix = if *is_dir { "/" } else { "" };
+            let mut text = format!("{}{}", prefix, quote(&format!("{dir}{only}{suffix}")));
+            if !is_dir {
+                text.push(' ');
+            }
+            Completion {
+                text,
+                candidates: Vec::new(),
+            }
+        }
+        [(first, _), rest @ ..] => {
+            let common = rest.iter().fold(first.as_str(), |common, (other, _)| {
+                let len = common
+                    .char_indices()
+                    .zip(other.chars())
+                    .take_while(|((_, a), b)| a == b)
+                    .last()
+                    .map_or(0, |((index, a), _)| index + a.len_utf8());
+                &common[..len]
+            });
+            Completion {
+                text: format!("{}{}", prefix, quote(&format!("{dir}{common}"))),
+                candidates: matches
+                    .iter()
+                    .map(|(name, is_dir)| {
+                        if *is_dir {
+                            format!("{name}/")
+                        } else {
+                            name.clone()
+                        }
+                    })
+                    .collect(),
+            }
+        }
+    }
+}
+
+/// Escapes the characters [`split_paths`] would otherwise take as separators or quotes.
+pub fn quote(path: &str) -> String {
+    let mut quoted = String::with_capacity(path.len());
+    for c in path.chars() {
+        if c.is_whitespace() || matches!(c, '\'' | '"' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted
+}
+
+/// The words of `input` with the byte offset each starts at, unquoted and unescaped, and whether the last word runs up to the end of `input`.
+fn words(input: &str) -> (Vec<(usize, String)>, bool) {
+    let mut words = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    let mut quote = None;
+    let mut chars = input.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some('"'), '\\') | (None, '\\') => {
+                let word = current.get_or_insert_with(|| (index, String::new()));
+                if let Some((_, escaped)) = chars.next() {
+                    word.1.push(escaped);
+                }
+            }
+            (Some(_), c) => current
+                .get_or_insert_with(|| (index, String::new()))
+                .1
+                .push(c),
+            (None, '\'' | '"') => {
+                current.get_or_insert_with(|| (index, String::new()));
+                quote = Some(c);
+            }
+            (None, c) if c.is_whitespace() => words.extend(current.take()),
+            (None, c) => current
+                .get_or_insert_with(|| (index, String::new()))
+                .1
+                .push(c),
+        }
+    }
+    let open = current.is_some();
+    words.extend(current);
+    (words, open)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree() -> (tempfile::TempDir, String) {
+        let dir = tempfile::tempdir().unwrap();
+        for dir_name in ["photos", "photo booth", "music"] {
+            fs::create_dir(dir.path().join(dir_name)).unwrap();
+        }
+        for file in ["notes.txt", "notebook.md", ".hidden"] {
+            fs::write(dir.path().join(file), b"x").unwrap();
+        }
+        let root = format!("{}/", dir.path().display());
+        (dir, root)
+    }
+
+    #[test]
+    fn splits_on_whitespace() {
+        assert_eq!(
+            split_paths("  /a/b   c\td "),
+            [PathBuf::from("/a/b"), "c".into(), "d".into()]
+        );
+        assert!(split_paths("   ").is_empty());
+    }
+
+    #[test]
+    fn splits_quoted_and_escaped_paths() {
+        assert_eq!(
+            split_paths(r#"'/my files/a.txt' "/b c/d" /e\ f/g"#),
+            [
+                PathBuf::from("/my files/a.txt"),
+                "/b c/d".into(),
+                "/e f/g".into()
+            ]
+        );
+        assert_eq!(
+            split_paths(r#"'it"s' "a\"b""#),
+            [PathBuf::from("it\"s"), "a\"b".into()]
+        );
+        assert_eq!(split_paths("'a'b"), [PathBuf::from("ab")]);
+    }
+
+    #[test]
+    fn expands_the_home_directory() {
+        let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("/"));
+        assert_eq!(split_paths("~/docs ~"), [home.join("docs"), home.clone()]);
+        assert_eq!(split_paths("~other/docs"), [PathBuf::from("~other/docs")]);
+    }
+
+    #[test]
+    fn quoted_paths_split_back_unchanged() {
+        for path in [
+            "/plain/path",
+            "/with space/a b",
+            r#"/quotes/'"\"#,
+            "/tab\there",
+        ] {
+            assert_eq!(split_paths(&quote(path)), [PathBuf::from(path)], "{path}");
+        }
+    }
+
+    #[test]
+    fn completes_a_single_file_and_moves_on() {
+        let (_dir, root) = tree();
+        let completion = complete(&format!("{root}notes"));
+        assert_eq!(completion.text, format!("{}notes.txt ", quote(&root)));
+        assert!(completion.candidates.is_empty());
+    }
+
+    #[test]
+    fn completes_a_single_directory_with_a_slash() {
+        let (_dir, root) = tree();
+        let completion = complete(&format!("{root}mu"));
+        assert_eq!(completion.text, format!("{}music/", quote(&root)));
+        assert!(completion.candidates.is_empty());
+    }
+
+    #[test]
+    fn completes_several_matches_to_their_common_prefix() {
+        let (_dir, root) = tree();
+        let completion = complete(&format!("{root}no"));
+        assert_eq!(completion.text, format!("{}note", quote(&root)));
+        assert_eq!(completion.candidates, ["notebook.md", "notes.txt"]);
+
+        let completion = complete(&format!("{root}pho"));
+        assert_eq!(completion.text, format!("{}photo", quote(&root)));
+        assert_eq!(completion.candidates, ["photo booth/", "photos/"]);
+    }
+
+    #[test]
+    fn completes_names_with_spaces_quoted() {
+        let (_dir, root) = tree();
+        let completion = complete(&format!("{}photo\\ b", quote(&root)));
+        assert_eq!(completion.text, format!("{}photo\\ booth/", quote(&root)));
+    }
+
+    #[test]
+    fn completes_only_the_last_path() {
+        let (_dir, root) = tree();
+        let first = "/already/typed ";
+        let completion = complete(&format!("{first}{root}mu"));
+        assert_eq!(completion.text, format!("{first}{}music/", quote(&root)));
+    }
+
+    #[test]
+    fn hidden_entries_need_a_leading_dot() {
+        let (_dir, root) = tree();
+        let completion = complete(&root);
+        assert!(!completion.candidates.iter().any(|c| c.starts_with('.')));
+        assert_eq!(completion.candidates.len(), 5);
+        let completion = complete(&format!("{root}.h"));
+        assert_eq!(completion.text, format!("{}.hidden ", quote(&root)));
+    }
+
+    #[test]
+    fn leaves_unmatched_input_alone() {
+        let (_dir, root) = tree();
+        let input = format!("{root}nothing");
+        let completion = complete(&input);
+        assert_eq!(completion.text, input);
+        assert!(completion.candidates.is_empty());
+    }
+}
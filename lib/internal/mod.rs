@@ -1,4 +1,7 @@
 pub mod forward_port;
 pub mod handle_upload;
+pub mod local_paths;
 pub mod open_file;
+pub mod remote_storage;
 pub mod session_store;
+pub mod transfer_queue;
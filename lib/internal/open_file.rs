@@ -1,35 +1,176 @@
-use crate::init::GLOBAL_RUNTIME;
+use crate::core_mod::widgets::{Item, TableWidget};
+use crate::events::ui_update::UIUpdate;
+use crate::internal::session_store::{self, TransferRecord};
+use crate::internal::transfer_queue::JobKind;
+use crate::screens::dashboard::Data;
 use crate::screens::debug::DebugScreen;
 use crate::screens::host_type::HostType;
 use crate::state::state::StateSnapshot;
+use ratatui::style::Stylize;
+use ratatui::text::Line;
 use rfd::FileDialog;
-use tcp_client::methods::upload::upload;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::mpsc::Sender;
+use tcp_server::protocol::compression::TransferStats;
+use tokio_util::sync::CancellationToken;
 
-pub fn open_explorer_and_file_select(state: &StateSnapshot, debug_screen: &mut DebugScreen) {
-    let host = state.host.lock().unwrap();
-    if host.selected != HostType::SENDER {
+/// Where uploads are shown to go in the Transfer screen and the session history.
+pub const UPLOAD_DESTINATION: &str = "server storage";
+/// Status of a transfer the user cancelled, in the Transfer screen and the session history.
+pub const CANCELLED: &str = "Cancelled";
+
+/// Lets the user pick one or more files and queues their upload, each shown in a row of the Transfer screen.
+///
+/// # Returns
+/// Whether the files have to be picked in the built-in file browser instead, because there is no desktop to show the file dialog on.
+pub fn open_explorer_and_file_select(
+    state: &StateSnapshot,
+    debug_screen: &mut DebugScreen,
+) -> bool {
+    if !can_send(state, debug_screen) {
+        return false;
+    }
+    if !desktop_available() {
+        debug_screen.push_line("No desktop for the file dialog; opening the file browser.");
+        return true;
+    }
+
+    let Some(paths) = FileDialog::new().pick_files() else {
+        debug_screen.push_line("No file selected.".to_string());
+        return false;
+    };
+    queue_uploads(state, paths, debug_screen);
+    false
+}
+
+/// Lets the user pick a folder and queues its upload with its whole tree, recreated under the folder's name on the server. The tree's progress is shown in a single row of the Transfer screen.
+///
+/// # Returns
+/// Whether the folder has to be picked in the built-in file browser instead, because there is no desktop to show the folder dialog on.
+pub fn open_explorer_and_folder_select(
+    state: &StateSnapshot,
+    debug_screen: &mut DebugScreen,
+) -> bool {
+    if !can_send(state, debug_screen) {
+        return false;
+    }
+    if !desktop_available() {
+        debug_screen.push_line("No desktop for the folder dialog; opening the file browser.");
+        return true;
+    }
+
+    let Some(path) = FileDialog::new().pick_folder() else {
+        debug_screen.push_line("No folder selected.".to_string());
+        return false;
+    };
+    queue_uploads(state, vec![path], debug_screen);
+    false
+}
+
+/// Queues the upload of every file or folder in `paths`, in order.
+pub fn queue_uploads(state: &StateSnapshot, paths: Vec<PathBuf>, debug_screen: &mut DebugScreen) {
+    for path in paths {
+        debug_screen.push_line(format!("Queued upload: {}", path.display()));
+        state.queue.push(JobKind::Upload { local: path });
+    }
+}
+
+/// Whether there is a desktop session for rfd's dialogs to open on. Outside Windows and macOS they need an X11 or Wayland display.
+pub fn desktop_available() -> bool {
+    if cfg!(any(target_os = "windows", target_os = "macos")) {
+        return true;
+    }
+    ["DISPLAY", "WAYLAND_DISPLAY"]
+        .iter()
+        .any(|var| std::env::var_os(var).is_some_and(|value| !value.is_empty()))
+}
+
+/// Whether a file can be sent: the app must be in client mode with an open connection. Explains why not otherwise.
+pub fn can_send(state: &StateSnapshot, debug_screen: &mut DebugScreen) -> bool {
+    if state.host.lock().unwrap().selected != HostType::SENDER {
         debug_screen.push_line("File sending is only available in client mode.");
-        return;
-    }
-    drop(host);
-
-    if let Some(path) = FileDialog::new().pick_file() {
-        debug_screen.push_line(format!("Selected file: {}", path.display()));
-        let file_path = path.to_string_lossy().into_owned();
-        match state.stream {
-            true => {
-                let result =
-                    GLOBAL_RUNTIME.block_on(upload(&file_path, "http:///192.168.0.14:9002/upload"));
-                match result {
-                    Ok(_) => debug_screen.push_line("File uploaded successfully.".to_string()),
-                    Err(e) => debug_screen.push_line(format!("Error uploading file: {}", e)),
-                }
-            }
-            false => {
-                debug_screen.push_line("No active TCP connection available.".to_string());
-            }
-        }
+        return false;
+    }
+    if !state.stream {
+        debug_screen.push_line("No active TCP connection available.".to_string());
+        return false;
+    }
+    true
+}
+
+/// Adds a queued transfer of `name` to or from `destination` as a row of the Transfer screen.
+///
+/// # Returns
+/// The id of the transfer, which its progress and outcome updates refer to, and the token that cancelling its row fires.
+pub fn add_transfer_row(
+    table: &Mutex<TableWidget>,
+    name: String,
+    destination: &str,
+) -> (u64, CancellationToken) {
+    let cancel = CancellationToken::new();
+    let id = table
+        .lock()
+        .unwrap()
+        .add_transfer(name, destination.to_string(), cancel.clone());
+    (id, cancel)
+}
+
+/// Shows the outcome of the transfer `id` in its Transfer row, ending its progress display.
+pub fn finish_transfer_row(
+    ui_update_tx: &Sender<UIUpdate>,
+    id: u64,
+    status: Line<'static>,
+    digest: Option<String>,
+) {
+    ui_update_tx
+        .send(UIUpdate::TransferFinished { id, status, digest })
+        .ok();
+}
+
+/// The status of a verified transfer, showing its original and compressed size if it was compressed.
+pub fn verified_status(stats: TransferStats) -> String {
+    if stats.compression.is_compressed() {
+        format!("Verified, {}", stats)
     } else {
-        debug_screen.push_line("No file selected.".to_string());
+        "Verified".to_string()
+    }
+}
+
+/// The status of a transfer that ended with `error`: cancelled if `cancel` fired, failed otherwise.
+pub fn failed_status(error: impl Display, cancel: &CancellationToken) -> Line<'static> {
+    if cancel.is_cancelled() {
+        Line::from(CANCELLED).dark_gray()
+    } else {
+        Line::from(format!("Failed: {}", error)).red()
+    }
+}
+
+/// The name of the file or directory at `path`, as its Transfer row shows it.
+pub fn display_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+/// Appends `record` to the current user's session history and to the matching device's transfer list.
+pub fn record_in_history(table: &Mutex<TableWidget>, record: TransferRecord) {
+    let session_name = whoami::username();
+    session_store::record_transfer(&session_name, record.clone());
+
+    let mut table = table.lock().unwrap();
+    let device = table.items.iter_mut().find_map(|item| match item {
+        Item::Device(device) if device.name == session_name => Some(device),
+        _ => None,
+    });
+    if let Some(device) = device {
+        device.last_transfer.name = record.name.clone();
+        device.last_transfer.size = format!("{} bytes", record.size);
+        device.last_transfer.status = record.status.clone();
+        device
+            .files
+            .get_or_insert_with(Vec::new)
+            .push(Data::from(&record));
     }
 }
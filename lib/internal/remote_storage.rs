@@ -0,0 +1,294 @@
+use crate::events::ui_update::UIUpdate;
+use crate::init::GLOBAL_RUNTIME;
+use crate::internal::open_file::desktop_available;
+use crate::internal::transfer_queue::JobKind;
+use crate::screens::debug::DebugScreen;
+use crate::screens::remote::RemoteBrowser;
+use crate::state::state::StateSnapshot;
+use rfd::FileDialog;
+use std::error::Error;
+use std::path::{Component, Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex, TryLockError};
+use tcp_client::methods::delete::delete;
+use tcp_client::methods::list::list;
+use tcp_server::protocol::codec::FramedStream;
+use tcp_server::protocol::manifest::EntryKind;
+use tcp_server::protocol::messages::{ListRequest, RemoteEntry};
+
+/// Lists the browser's directory on the connected server again in the background, keeping what is highlighted and selected where it is still listed.
+///
+/// The listing arrives as a [`UIUpdate::RemoteListed`] for the directory that was listed, so a listing for a directory the user has since left is dropped.
+pub fn refresh_remote(
+    stream: &Arc<Mutex<FramedStream>>,
+    ui_update_tx: &Sender<UIUpdate>,
+    browser: &mut RemoteBrowser,
+) {
+    browser.status = Some(format!("Listing /{}", browser.cwd));
+    let stream = stream.clone();
+    let ui_update_tx = ui_update_tx.clone();
+    let cwd = browser.cwd.clone();
+    GLOBAL_RUNTIME.spawn_blocking(move || {
+        let update = match stream.try_lock() {
+            Ok(mut stream) => list_remote(&mut stream, cwd),
+            Err(e) => UIUpdate::RemoteStatus(busy(e)),
+        };
+        ui_update_tx.send(update).ok();
+    });
+}
+
+/// Shows the listing of `cwd`, or why it failed, in `browser` unless the user has moved to another directory since.
+pub fn show_listing(
+    browser: &mut RemoteBrowser,
+    cwd: String,
+    entries: Result<Vec<RemoteEntry>, String>,
+) {
+    if browser.cwd != cwd {
+        return;
+    }
+    match entries {
+        Ok(entries) => {
+            browser.set_entries(entries);
+            browser.status = None;
+        }
+        Err(e) => browser.status = Some(format!("Could not list /{}: {}", cwd, e)),
+    }
+}
+
+fn list_remote(stream: &mut FramedStream, cwd: String) -> UIUpdate {
+    let request = ListRequest {
+        path: cwd.clone(),
+        ..Default::default()
+    };
+    let entries = GLOBAL_RUNTIME
+        .block_on(list(stream, &request))
+        .map_err(|e| e.to_string());
+    UIUpdate::RemoteListed { cwd, entries }
+}
+
+/// What became of a download the user asked for on the Remote screen.
+#[derive(Debug)]
+pub enum DownloadStart {
+    /// The download was queued.
+    Queued,
+    /// There is no desktop to show the folder dialog on, so the folder to download these targets into has to be picked in the built-in file browser or typed on the path entry screen.
+    PickFolder(Vec<RemoteEntry>),
+    /// Nothing was queued; the browser's status or the debug log says why.
+    Nothing,
+}
+
+/// Lets the user pick a local folder and queues the download of the browser's targets into it with [`queue_download`].
+///
+/// # Returns
+/// Whether the download was queued, or whether its folder has to be picked without the desktop's folder dialog.
+pub fn download_remote_selection(
+    stream: &Arc<Mutex<FramedStream>>,
+    state: &StateSnapshot,
+    ui_update_tx: &Sender<UIUpdate>,
+    browser: &mut RemoteBrowser,
+    debug_screen: &mut DebugScreen,
+) -> DownloadStart {
+    let targets = browser.targets();
+    if targets.is_empty() {
+        browser.status = Some("Nothing to download.".to_string());
+        return DownloadStart::Nothing;
+    }
+    if let Err(e) = stream.try_lock() {
+        browser.status = Some(busy(e));
+        return DownloadStart::Nothing;
+    }
+    if !desktop_available() {
+        debug_screen.push_line("No desktop for the folder dialog; opening the file browser.");
+        return DownloadStart::PickFolder(targets);
+    }
+    let Some(destination) = FileDialog::new().pick_folder() else {
+        debug_screen.push_line("No folder selected.".to_string());
+        return DownloadStart::Nothing;
+    };
+    browser.selected.clear();
+    browser.status = Some(format!("Downloading into {}", destination.display()));
+    queue_download(
+        stream,
+        state,
+        ui_update_tx,
+        targets,
+        destination,
+        debug_screen,
+    );
+    DownloadStart::Queued
+}
+
+/// Queues the download of the remote `targets` into the local folder `destination`. Directories are listed in the background and downloaded with their whole tree, recreated under their own name.
+///
+/// Every file becomes a job of the [`TransferQueue`](crate::internal::transfer_queue::TransferQueue), with a row on the Transfer screen that shows its progress, rate and ETA while it downloads and its outcome once done; the outcome is also recorded in the session history.
+pub fn queue_download(
+    stream: &Arc<Mutex<FramedStream>>,
+    state: &StateSnapshot,
+    ui_update_tx: &Sender<UIUpdate>,
+    targets: Vec<RemoteEntry>,
+    destination: PathBuf,
+    debug_screen: &mut DebugScreen,
+) {
+    debug_screen.push_line(format!(
+        "Downloading {} entries into {}",
+        targets.len(),
+        destination.display()
+    ));
+    let stream = stream.clone();
+    let queue = state.queue.clone();
+    let debug = state.debug_screen.clone();
+    let ui_update_tx = ui_update_tx.clone();
+    GLOBAL_RUNTIME.spawn_blocking(move || {
+        let files = {
+            let mut stream = stream.lock().unwrap();
+            GLOBAL_RUNTIME.block_on(expand_targets(&mut stream, &targets, &destination))
+        };
+        let message = match files {
+            Ok(files) => {
+                let count = files.len();
+                for (file, dir) in files {
+                    queue.push(JobKind::Download {
+                        remote: file.path,
+                        destination: dir,
+                        size: file.size,
+                    });
+                }
+                format!(
+                    "Queued {} files for download into {}.",
+                    count,
+                    destination.display()
+                )
+            }
+            Err(e) => {
+                ui_update_tx.send(UIUpdate::RemoteStatus(e.clone())).ok();
+                e
+            }
+        };
+        debug.lock().unwrap().push_line(message);
+    });
+}
+
+/// Deletes the browser's targets from the server in the background, then lists its directory again.
+pub fn delete_remote_selection(
+    stream: &Arc<Mutex<FramedStream>>,
+    state: &StateSnapshot,
+    ui_update_tx: &Sender<UIUpdate>,
+    browser: &mut RemoteBrowser,
+) {
+    let targets = browser.targets();
+    browser.status = Some(format!("Deleting {} entries", targets.len()));
+    let stream = stream.clone();
+    let debug = state.debug_screen.clone();
+    let ui_update_tx = ui_update_tx.clone();
+    let cwd = browser.cwd.clone();
+    GLOBAL_RUNTIME.spawn_blocking(move || {
+        let mut stream = match stream.try_lock() {
+            Ok(stream) => stream,
+            Err(e) => {
+                ui_update_tx.send(UIUpdate::RemoteStatus(busy(e))).ok();
+                return;
+            }
+        };
+        let mut deleted = 0;
+        let mut failure = None;
+        for target in &targets {
+            match GLOBAL_RUNTIME.block_on(delete(&mut stream, &target.path)) {
+                Ok(_) => {
+                    deleted += 1;
+                    debug
+                        .lock()
+                        .unwrap()
+                        .push_line(format!("Deleted {} from the server.", target.path));
+                }
+                Err(e) => {
+                    debug
+                        .lock()
+                        .unwrap()
+                        .push_line(format!("Error deleting {}: {}", target.path, e));
+                    failure = Some(format!("Could not delete {}: {}", target.path, e));
+                    break;
+                }
+            }
+        }
+        ui_update_tx.send(list_remote(&mut stream, cwd)).ok();
+        let status = failure.unwrap_or_else(|| format!("Deleted {} entries", deleted));
+        ui_update_tx.send(UIUpdate::RemoteStatus(status)).ok();
+    });
+}
+
+/// The files to download for `targets` and the local directory each goes into, below `destination`.
+///
+/// # Errors
+/// Returns a message naming the target that could not be listed.
+async fn expand_targets(
+    stream: &mut FramedStream,
+    targets: &[RemoteEntry],
+    destination: &Path,
+) -> Result<Vec<(RemoteEntry, PathBuf)>, String> {
+    let mut files = Vec::new();
+    for target in targets {
+        match expand_entry(stream, target, destination).await {
+            Ok(expanded) => files.extend(expanded),
+            Err(e) => return Err(format!("Error listing {}: {}", target.path, e)),
+        }
+    }
+    Ok(files)
+}
+
+/// The files to download for `entry` and the local directory each goes into: `entry` itself if it is a file, every file below it if it is a directory, whose tree is recreated under `destination`.
+async fn expand_entry(
+    stream: &mut FramedStream,
+    entry: &RemoteEntry,
+    destination: &Path,
+) -> Result<Vec<(RemoteEntry, PathBuf)>, Box<dyn Error>> {
+    if entry.kind == EntryKind::File {
+        return Ok(vec![(entry.clone(), destination.to_path_buf())]);
+    }
+
+    let request = ListRequest {
+        path: entry.path.clone(),
+        recursive: true,
+        ..Default::default()
+    };
+    let parent = entry
+        .path
+        .rsplit_once('/')
+        .map(|(parent, _)| format!("{parent}/"))
+        .unwrap_or_default();
+    tokio::fs::create_dir_all(destination.join(local_path(&entry.path, &parent)?)).await?;
+    let mut files = Vec::new();
+    for file in list(stream, &request).await? {
+        let relative = local_path(&file.path, &parent)?;
+        if file.kind == EntryKind::Directory {
+            tokio::fs::create_dir_all(destination.join(&relative)).await?;
+            continue;
+        }
+        let dir = match relative.rsplit_once('/') {
+            Some((dir, _)) => destination.join(dir),
+            None => destination.to_path_buf(),
+        };
+        files.push((file, dir));
+    }
+    Ok(files)
+}
+
+fn busy<T>(_: TryLockError<T>) -> String {
+    "The connection is busy with a transfer.".to_string()
+}
+
+/// The part of the remote `path` below `parent`, checked to stay inside the download folder.
+///
+/// # Errors
+/// Returns an error if `path` is not below `parent` or has a component other than a plain name.
+fn local_path(path: &str, parent: &str) -> Result<String, Box<dyn Error>> {
+    let relative = path
+        .strip_prefix(parent)
+        .ok_or_else(|| format!("Server listed {} outside of {}", path, parent))?;
+    let plain = Path::new(relative)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_)));
+    if relative.is_empty() || !plain {
+        return Err(format!("Server listed an invalid path {}", path).into());
+    }
+    Ok(relative.to_string())
+}
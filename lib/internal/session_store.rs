@@ -4,14 +4,33 @@ use std::{
     fs::{self, File, create_dir_all},
     io::Read,
     path::PathBuf,
+    sync::Mutex,
 };
 
+/// Serializes the read-modify-write cycles on the session and known hosts files, since queued transfers finishing at once record their history from several threads.
+static STORE_LOCK: Mutex<()> = Mutex::new(());
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SessionRecord {
     pub name: String,
     pub ip: String,
     pub last_transfer: String,
     pub last_connection: String,
+    #[serde(default)]
+    pub transfers: Vec<TransferRecord>,
+}
+
+/// A finished transfer in a session's history.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TransferRecord {
+    pub name: String,
+    pub size: u64,
+    pub destination: String,
+    pub status: String,
+    pub time: String,
+    /// The verified content digest as `<algorithm>=<hex>`, if the transfer was checked.
+    #[serde(default)]
+    pub digest: Option<String>,
 }
 
 fn get_session_file_path() -> PathBuf {
@@ -73,6 +92,7 @@ pub fn save_sessions(sessions: &[SessionRecord]) {
 }
 
 pub fn update_session_record(new_record: SessionRecord) {
+    let _guard = STORE_LOCK.lock().unwrap();
     let mut sessions = load_sessions();
     let mut found = false;
 
@@ -91,3 +111,78 @@ pub fn update_session_record(new_record: SessionRecord) {
 
     save_sessions(&sessions);
 }
+
+/// Appends `transfer` to the history of the session named `session_name`, creating the session if needed.
+pub fn record_transfer(session_name: &str, transfer: TransferRecord) {
+    let _guard = STORE_LOCK.lock().unwrap();
+    let mut sessions = load_sessions();
+    match sessions
+        .iter_mut()
+        .find(|record| record.name == session_name)
+    {
+        Some(record) => {
+            record.last_transfer = transfer.name.clone();
+            record.transfers.push(transfer);
+        }
+        None => sessions.push(SessionRecord {
+            name: session_name.to_string(),
+            ip: "unknown".to_string(),
+            last_transfer: transfer.name.clone(),
+            last_connection: transfer.time.clone(),
+            transfers: vec![transfer],
+        }),
+    }
+
+    save_sessions(&sessions);
+}
+
+/// A server whose TLS certificate was trusted on first use.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KnownHost {
+    pub address: String,
+    /// SHA-256 fingerprint of the server's certificate, as lowercase hex.
+    pub fingerprint: String,
+    pub first_seen: String,
+}
+
+fn get_known_hosts_path() -> PathBuf {
+    get_session_dir_path().join("known_hosts.json")
+}
+
+pub fn load_known_hosts() -> Vec<KnownHost> {
+    fs::read_to_string(get_known_hosts_path())
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+/// The certificate fingerprint pinned for the server at `address`, if one was seen before.
+pub fn pinned_fingerprint(address: &str) -> Option<String> {
+    load_known_hosts()
+        .into_iter()
+        .find(|host| host.address == address)
+        .map(|host| host.fingerprint)
+}
+
+/// Pins `fingerprint` for the server at `address` unless a fingerprint is already pinned for it.
+///
+/// An existing pin is never replaced; to trust a server's new certificate its entry has to be removed from `known_hosts.json` first.
+pub fn pin_fingerprint(address: &str, fingerprint: &str) {
+    let _guard = STORE_LOCK.lock().unwrap();
+    let mut hosts = load_known_hosts();
+    if hosts.iter().any(|host| host.address == address) {
+        return;
+    }
+    hosts.push(KnownHost {
+        address: address.to_string(),
+        fingerprint: fingerprint.to_string(),
+        first_seen: Utc::now().to_rfc3339(),
+    });
+
+    let path = get_known_hosts_path();
+    if let Some(parent) = path.parent() {
+        create_dir_all(parent).expect("Failed to create sessions directory");
+    }
+    let contents = serde_json::to_string_pretty(&hosts).expect("Failed to serialize known hosts");
+    fs::write(path, contents).expect("Failed to write known hosts file");
+}
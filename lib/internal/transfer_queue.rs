@@ -0,0 +1,1241 @@
+use crate::core_mod::widgets::TableWidget;
+use crate::events::ui_update::{UIUpdate, progress_reporter};
+use crate::init::GLOBAL_RUNTIME;
+use crate::internal::open_file::{
+    CANCELLED, UPLOAD_DESTINATION, add_transfer_row, display_name, failed_status,
+    finish_transfer_row, record_in_history, verified_status,
+};
+use crate::internal::session_store::TransferRecord;
+use crate::screens::debug::DebugScreen;
+use crate::utils::format::format_eta;
+use ratatui::style::Stylize;
+use ratatui::text::Line;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tcp_client::app::Endpoint;
+use tcp_client::methods::download::download_with_progress;
+use tcp_client::methods::get::resume_get_with_progress;
+use tcp_client::methods::put::{put_dir_with_progress, resume_put_with_progress};
+use tcp_client::methods::upload::{resume_upload_with_progress, upload_dir_with_progress};
+use tcp_server::protocol::codec::FramedStream;
+use tcp_server::protocol::compression::TransferStats;
+use tcp_server::protocol::error::RequestError;
+use tcp_server::protocol::messages::ErrorCode;
+use tcp_server::protocol::progress::ProgressTracker;
+use tcp_server::protocol::transfer::interrupt_transfer;
+use tokio_util::sync::CancellationToken;
+
+/// How long [`resync`] waits for the server to acknowledge that a broken-off transfer is over.
+const RESYNC_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Limits of a [`TransferQueue`].
+#[derive(Debug, Clone, Copy)]
+pub struct QueueConfig {
+    /// How many transfers may run at the same time over HTTPS. Over the plain TCP connection only one runs at a time whatever this says, since the connection carries one transfer at a time.
+    pub concurrency: usize,
+    /// How many times a failed transfer is retried before it is given up.
+    pub retries: u32,
+    /// How long the first retry waits; every further retry waits twice as long as the one before.
+    pub backoff: Duration,
+    /// The longest a retry ever waits.
+    pub max_backoff: Duration,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            concurrency: 3,
+            retries: 4,
+            backoff: Duration::from_secs(2),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+}
+
+impl QueueConfig {
+    /// How long to wait before the `attempt`th retry.
+    fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+
+    /// Whether a job that failed with `error` after `attempts` retries is tried again.
+    fn retry(&self, attempts: u32, error: &(dyn Error + 'static)) -> bool {
+        attempts < self.retries && retryable(error)
+    }
+}
+
+/// What a queued transfer moves.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobKind {
+    /// Uploads a local file, or a directory with its whole tree, into the server's storage.
+    Upload { local: PathBuf },
+    /// Downloads the remote file at `remote`, `size` bytes long, into the local directory `destination`.
+    Download {
+        remote: String,
+        destination: PathBuf,
+        size: u64,
+    },
+}
+
+impl JobKind {
+    /// The name the job's row and history record show.
+    fn name(&self) -> String {
+        match self {
+            JobKind::Upload { local } => display_name(local),
+            JobKind::Download { remote, .. } => {
+                remote.rsplit('/').next().unwrap_or(remote).to_string()
+            }
+        }
+    }
+
+    /// How many bytes the job moves, as far as is known before it runs.
+    fn size(&self) -> u64 {
+        match self {
+            JobKind::Upload { local } => fs::metadata(local)
+                .ok()
+                .filter(|metadata| metadata.is_file())
+                .map_or(0, |metadata| metadata.len()),
+            JobKind::Download { size, .. } => *size,
+        }
+    }
+
+    /// The HTTP route the job runs through when the server serves its routes over HTTPS.
+    fn route(&self) -> &'static str {
+        match self {
+            JobKind::Upload { .. } => "upload",
+            JobKind::Download { .. } => "download",
+        }
+    }
+
+    /// Where the job's row and history record show it going.
+    fn destination(&self) -> String {
+        match self {
+            JobKind::Upload { .. } => UPLOAD_DESTINATION.to_string(),
+            JobKind::Download { destination, .. } => destination.display().to_string(),
+        }
+    }
+}
+
+/// A transfer waiting in, or run by, a [`TransferQueue`], as persisted across restarts.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Job {
+    pub id: u64,
+    #[serde(flatten)]
+    pub kind: JobKind,
+    /// How many times the job has failed and been retried.
+    #[serde(default)]
+    pub attempts: u32,
+    /// The server the job transfers to or from, as [`QueueContext::server`] names it; `None` until the job first starts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub server: Option<String>,
+}
+
+/// The connection the queue runs its jobs over: the HTTP `client` and the routes of `endpoint` when the server serves them over HTTPS, the TCP `stream` otherwise.
+#[derive(Clone)]
+pub struct QueueContext {
+    pub stream: Arc<Mutex<FramedStream>>,
+    pub client: Option<Client>,
+    pub endpoint: Endpoint,
+    /// Which server the connection reaches: the fingerprint of its certificate over TLS, its address otherwise. Jobs only run on the server they were queued for.
+    pub server: String,
+}
+
+impl QueueContext {
+    /// The HTTP client and the URL of `route`, or `None` if jobs go over the TCP stream.
+    fn route(&self, route: &str) -> Option<(&Client, String)> {
+        Some((self.client.as_ref()?, self.endpoint.url(route)?))
+    }
+
+    /// Whether jobs go over the TCP stream, one at a time.
+    fn over_stream(&self) -> bool {
+        self.route("").is_none()
+    }
+}
+
+/// A job together with the id of the Transfer row that shows it and the token cancelling that row fires.
+struct Entry {
+    job: Job,
+    transfer: u64,
+    cancel: CancellationToken,
+}
+
+#[derive(Default)]
+struct QueueState {
+    waiting: VecDeque<Entry>,
+    /// Every job not yet finished, waiting, running or backing off, by id; what is persisted.
+    unfinished: BTreeMap<u64, Job>,
+    running: usize,
+    /// Whether a job is running over the TCP stream, which carries one transfer at a time.
+    stream_busy: bool,
+    next_id: u64,
+    context: Option<QueueContext>,
+}
+
+impl QueueState {
+    /// Takes the waiting jobs whose rows were cancelled out of the queue.
+    fn take_cancelled(&mut self) -> VecDeque<Entry> {
+        let (waiting, cancelled): (VecDeque<Entry>, VecDeque<Entry>) =
+            std::mem::take(&mut self.waiting)
+                .into_iter()
+                .partition(|entry| !entry.cancel.is_cancelled());
+        self.waiting = waiting;
+        for entry in &cancelled {
+            self.unfinished.remove(&entry.job.id);
+        }
+        cancelled
+    }
+
+    /// Takes the waiting jobs that can start now, in queue order, binding each to the connected server if it was not yet.
+    ///
+    /// Jobs only start while the queue is connected, in as many of the `concurrency` slots as are free, and only on the server they were queued for. Over the TCP stream only one job runs at a time, so the others wait in the queue rather than for the stream.
+    fn take_startable(&mut self, concurrency: usize) -> Vec<(Entry, QueueContext)> {
+        let Some(context) = self.context.clone() else {
+            return Vec::new();
+        };
+        let over_stream = context.over_stream();
+        let mut started = Vec::new();
+        let mut skipped = VecDeque::new();
+        while let Some(mut entry) = self.waiting.pop_front() {
+            let free = self.running < concurrency && !(over_stream && self.stream_busy);
+            let ours = entry
+                .job
+                .server
+                .as_ref()
+                .is_none_or(|server| *server == context.server);
+            if !free || !ours {
+                skipped.push_back(entry);
+                continue;
+            }
+            if entry.job.server.is_none() {
+                entry.job.server = Some(context.server.clone());
+                self.unfinished.insert(entry.job.id, entry.job.clone());
+            }
+            self.running += 1;
+            self.stream_busy |= over_stream;
+            started.push((entry, context.clone()));
+        }
+        self.waiting = skipped;
+        started
+    }
+
+    /// How many waiting jobs were queued for a server other than `server`.
+    fn waiting_elsewhere(&self, server: &str) -> usize {
+        self.waiting
+            .iter()
+            .filter(|entry| {
+                entry
+                    .job
+                    .server
+                    .as_deref()
+                    .is_some_and(|other| other != server)
+            })
+            .count()
+    }
+}
+
+/// Runs uploads and downloads in the background, a few at a time, each shown as a row of the Transfer screen.
+///
+/// Jobs wait until the queue has a [`QueueContext`] for the server they were queued for and a free slot. A job that fails is retried with exponential backoff unless it was cancelled or the error is one retrying cannot fix. Files are sent and fetched resumably, so a retry, or a job restored after a restart, continues from the bytes an earlier attempt left on the receiving side. Unfinished jobs are saved to `queue.json` in the app's data directory whenever the queue changes, so [`TransferQueue::restore`] can pick them up after a restart.
+///
+/// Jobs run side by side over HTTPS; without it they take turns on the shared TCP connection.
+#[derive(Clone)]
+pub struct TransferQueue {
+    state: Arc<Mutex<QueueState>>,
+    config: QueueConfig,
+    table: Arc<Mutex<TableWidget>>,
+    debug: Arc<Mutex<DebugScreen>>,
+    ui_update_tx: Sender<UIUpdate>,
+    queue_file: PathBuf,
+}
+
+impl TransferQueue {
+    pub fn new(
+        config: QueueConfig,
+        table: Arc<Mutex<TableWidget>>,
+        debug: Arc<Mutex<DebugScreen>>,
+        ui_update_tx: Sender<UIUpdate>,
+    ) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(QueueState::default())),
+            config,
+            table,
+            debug,
+            ui_update_tx,
+            queue_file: get_queue_file_path(),
+        }
+    }
+
+    /// Saves unfinished jobs to, and restores them from, `queue_file` instead of `queue.json` in the app's data directory.
+    pub fn with_queue_file(mut self, queue_file: impl Into<PathBuf>) -> Self {
+        self.queue_file = queue_file.into();
+        self
+    }
+
+    /// Queues the jobs a previous run left unfinished, each with a fresh row, to start once the queue is connected.
+    ///
+    /// # Returns
+    /// How many jobs were restored.
+    pub fn restore(&self) -> usize {
+        let jobs = load_jobs(&self.queue_file);
+        let count = jobs.len();
+        for job in jobs {
+            self.enqueue(job);
+        }
+        count
+    }
+
+    /// Queues a transfer as a new row of the Transfer screen and starts it if a slot is free.
+    pub fn push(&self, kind: JobKind) {
+        let job = {
+            let mut state = self.state.lock().unwrap();
+            let job = Job {
+                id: state.next_id,
+                kind,
+                attempts: 0,
+                server: state.context.as_ref().map(|context| context.server.clone()),
+            };
+            state.next_id += 1;
+            job
+        };
+        self.enqueue(job);
+        self.dispatch();
+    }
+
+    /// Lets the queue run its jobs over `context` from now on and starts the jobs waiting for its server.
+    pub fn connect(&self, context: QueueContext) {
+        if context.over_stream() && self.config.concurrency > 1 {
+            self.debug.lock().unwrap().push_line(format!(
+                "Transfers run one at a time over the TCP connection; turn on TLS to run {} at once.",
+                self.config.concurrency
+            ));
+        }
+        let elsewhere = {
+            let mut state = self.state.lock().unwrap();
+            let elsewhere = state.waiting_elsewhere(&context.server);
+            state.context = Some(context);
+            elsewhere
+        };
+        if elsewhere > 0 {
+            self.debug.lock().unwrap().push_line(format!(
+                "Queued transfers for another server: {elsewhere}; they wait until it is connected."
+            ));
+        }
+        self.dispatch();
+    }
+
+    /// Stops starting jobs over `context`, whose connection broke, if the queue still runs over it; waiting jobs wait for the next [`TransferQueue::connect`].
+    fn disconnect(&self, context: &QueueContext) {
+        let mut state = self.state.lock().unwrap();
+        let current = state
+            .context
+            .as_ref()
+            .is_some_and(|current| Arc::ptr_eq(&current.stream, &context.stream));
+        if current {
+            state.context = None;
+        }
+    }
+
+    /// Ends the waiting jobs whose rows were cancelled and starts as many waiting jobs as there are free slots.
+    pub fn dispatch(&self) {
+        let (cancelled, started) = {
+            let mut state = self.state.lock().unwrap();
+            let cancelled = state.take_cancelled();
+            let started = state.take_startable(self.config.concurrency);
+            if !cancelled.is_empty() || !started.is_empty() {
+                save_jobs(&self.queue_file, &state.unfinished);
+            }
+            (cancelled, started)
+        };
+
+        for entry in cancelled {
+            let size = entry.job.kind.size();
+            self.record(&entry, Line::from(CANCELLED).dark_gray(), size, None);
+        }
+        for (entry, context) in started {
+            self.run(entry, context);
+        }
+    }
+
+    /// Shows `job` as a queued row and puts it at the back of the queue.
+    fn enqueue(&self, job: Job) {
+        let (transfer, cancel) =
+            add_transfer_row(&self.table, job.kind.name(), &job.kind.destination());
+        let mut state = self.state.lock().unwrap();
+        state.next_id = state.next_id.max(job.id + 1);
+        state.unfinished.insert(job.id, job.clone());
+        state.waiting.push_back(Entry {
+            job,
+            transfer,
+            cancel,
+        });
+        save_jobs(&self.queue_file, &state.unfinished);
+    }
+
+    /// Runs `entry` over `context` on a blocking thread. Over the TCP stream, a transfer that broke off is cancelled on the server before the stream is handed on, so the next job starts in step with it; if that fails the connection is given up.
+    fn run(&self, entry: Entry, context: QueueContext) {
+        let queue = self.clone();
+        GLOBAL_RUNTIME.spawn_blocking(move || {
+            let progress = progress_reporter(&queue.ui_update_tx, entry.transfer);
+            let result = match context.route(entry.job.kind.route()) {
+                Some((client, url)) => GLOBAL_RUNTIME.block_on(over_http(
+                    client,
+                    &url,
+                    &entry.job.kind,
+                    &entry.cancel,
+                    &progress,
+                )),
+                None => {
+                    let mut stream = context.stream.lock().unwrap();
+                    let result = GLOBAL_RUNTIME.block_on(over_stream(
+                        &mut stream,
+                        &entry.job.kind,
+                        &entry.cancel,
+                        &progress,
+                    ));
+                    if let Err(e) = &result
+                        && !entry.cancel.is_cancelled()
+                        && retryable(e.as_ref())
+                        && let Err(e) = GLOBAL_RUNTIME.block_on(resync(&mut stream))
+                    {
+                        queue.debug.lock().unwrap().push_line(format!(
+                            "Lost the connection to the server ({e}); queued transfers wait until it is reconnected."
+                        ));
+                        queue.disconnect(&context);
+                    }
+                    result
+                }
+            };
+            queue.finish(entry, result, &progress, context.over_stream());
+        });
+    }
+
+    /// Settles a job that ran, `over_stream` or not: records it if it succeeded, was cancelled or is out of retries, and schedules its retry otherwise.
+    fn finish(
+        &self,
+        mut entry: Entry,
+        result: Result<Outcome, Box<dyn Error>>,
+        progress: &ProgressTracker,
+        over_stream: bool,
+    ) {
+        let retry = match &result {
+            Err(e) => {
+                !entry.cancel.is_cancelled() && self.config.retry(entry.job.attempts, e.as_ref())
+            }
+            Ok(_) => false,
+        };
+        {
+            let mut state = self.state.lock().unwrap();
+            state.running -= 1;
+            if over_stream {
+                state.stream_busy = false;
+            }
+            if retry {
+                entry.job.attempts += 1;
+                state.unfinished.insert(entry.job.id, entry.job.clone());
+            } else {
+                state.unfinished.remove(&entry.job.id);
+            }
+            save_jobs(&self.queue_file, &state.unfinished);
+        }
+
+        match result {
+            Ok(outcome) => {
+                self.debug.lock().unwrap().push_line(format!(
+                    "Transferred {} ({}).",
+                    entry.job.kind.name(),
+                    outcome.stats
+                ));
+                let status = outcome.status();
+                self.record(
+                    &entry,
+                    Line::from(status.clone()).green(),
+                    outcome.size,
+                    outcome.digest,
+                );
+            }
+            Err(e) if retry => {
+                let delay = self.config.delay(entry.job.attempts);
+                self.debug.lock().unwrap().push_line(format!(
+                    "Error transferring {}: {}; retrying in {}.",
+                    entry.job.kind.name(),
+                    e,
+                    format_eta(delay)
+                ));
+                let status = Line::from(format!(
+                    "Retry {}/{} in {}: {}",
+                    entry.job.attempts,
+                    self.config.retries,
+                    format_eta(delay),
+                    e
+                ))
+                .yellow();
+                self.ui_update_tx
+                    .send(UIUpdate::TransferStatus {
+                        id: entry.transfer,
+                        status,
+                    })
+                    .ok();
+                self.requeue_after(entry, delay);
+            }
+            Err(e) => {
+                self.debug.lock().unwrap().push_line(format!(
+                    "Error transferring {}: {}",
+                    entry.job.kind.name(),
+                    e
+                ));
+                let size = progress.progress().map_or(0, |progress| progress.total);
+                self.record(&entry, failed_status(&e, &entry.cancel), size, None);
+            }
+        }
+        self.dispatch();
+    }
+
+    /// Puts `entry` back at the front of the queue once `delay` has passed, or at once if its row is cancelled in the meantime.
+    fn requeue_after(&self, entry: Entry, delay: Duration) {
+        let queue = self.clone();
+        GLOBAL_RUNTIME.spawn(async move {
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => {}
+                _ = entry.cancel.cancelled() => {}
+            }
+            queue.state.lock().unwrap().waiting.push_front(entry);
+            queue.dispatch();
+        });
+    }
+
+    /// Shows the outcome of `entry` in its row and records it in the session history.
+    fn record(&self, entry: &Entry, status: Line<'static>, size: u64, digest: Option<String>) {
+        finish_transfer_row(
+            &self.ui_update_tx,
+            entry.transfer,
+            status.clone(),
+            digest.clone(),
+        );
+        let record = TransferRecord {
+            name: entry.job.kind.name(),
+            size,
+            destination: entry.job.kind.destination(),
+            status: status.to_string(),
+            time: chrono::Utc::now().to_rfc3339(),
+            digest,
+        };
+        record_in_history(&self.table, record);
+    }
+}
+
+/// What a finished job moved.
+struct Outcome {
+    /// Size of the file or tree, including any bytes an earlier attempt moved.
+    size: u64,
+    /// What the last attempt moved.
+    stats: TransferStats,
+    digest: Option<String>,
+    verified: bool,
+}
+
+impl Outcome {
+    fn status(&self) -> String {
+        if self.verified {
+            verified_status(self.stats)
+        } else {
+            "Downloaded".to_string()
+        }
+    }
+}
+
+/// Runs the transfer `kind` through the HTTP routes at `url`.
+async fn over_http(
+    client: &Client,
+    url: &str,
+    kind: &JobKind,
+    cancel: &CancellationToken,
+    progress: &ProgressTracker,
+) -> Result<Outcome, Box<dyn Error>> {
+    match kind {
+        JobKind::Upload { local } => upload(client, url, local, cancel, progress).await,
+        JobKind::Download {
+            remote,
+            destination,
+            ..
+        } => {
+            let (path, digest) =
+                download_with_progress(client, remote, url, destination, progress, cancel).await?;
+            let size = tokio::fs::metadata(&path).await?.len();
+            Ok(Outcome {
+                size,
+                stats: TransferStats::uncompressed(size),
+                verified: digest.is_some(),
+                digest: digest.map(|digest| digest.to_string()),
+            })
+        }
+    }
+}
+
+/// Runs the transfer `kind` over the TCP `stream`.
+async fn over_stream(
+    stream: &mut FramedStream,
+    kind: &JobKind,
+    cancel: &CancellationToken,
+    progress: &ProgressTracker,
+) -> Result<Outcome, Box<dyn Error>> {
+    match kind {
+        JobKind::Upload { local } => put(stream, local, cancel, progress).await,
+        JobKind::Download {
+            remote,
+            destination,
+            size,
+        } => download(stream, remote, destination, *size, cancel, progress).await,
+    }
+}
+
+async fn upload(
+    client: &Client,
+    url: &str,
+    local: &Path,
+    cancel: &CancellationToken,
+    progress: &ProgressTracker,
+) -> Result<Outcome, Box<dyn Error>> {
+    let local_path = local.to_string_lossy();
+    if local.is_dir() {
+        let uploaded = upload_dir_with_progress(client, &local_path, url, progress, cancel).await?;
+        let stats: TransferStats = uploaded.into_iter().map(|(_, stats)| stats).sum();
+        return Ok(Outcome {
+            size: stats.original,
+            stats,
+            digest: None,
+            verified: true,
+        });
+    }
+    let (digest, stats) =
+        resume_upload_with_progress(client, &local_path, url, progress, cancel).await?;
+    Ok(Outcome {
+        size: tokio::fs::metadata(local).await?.len(),
+        stats,
+        digest: Some(digest.to_string()),
+        verified: true,
+    })
+}
+
+/// Uploads `local` over the TCP `stream`, for servers that do not serve their HTTP routes over HTTPS. A directory is measured as one transfer with all its files.
+async fn put(
+    stream: &mut FramedStream,
+    local: &Path,
+    cancel: &CancellationToken,
+    progress: &ProgressTracker,
+) -> Result<Outcome, Box<dyn Error>> {
+    let name = display_name(local);
+    if local.is_dir() {
+        let replies = put_dir_with_progress(stream, local, &name, progress, cancel).await?;
+        let stats: TransferStats = replies.into_iter().filter_map(|reply| reply.stats).sum();
+        return Ok(Outcome {
+            size: stats.original,
+            stats,
+            digest: None,
+            verified: true,
+        });
+    }
+    let reply = resume_put_with_progress(stream, local, &name, progress, cancel).await?;
+    Ok(Outcome {
+        size: tokio::fs::metadata(local).await?.len(),
+        stats: reply.stats.unwrap_or_default(),
+        digest: reply.digest.map(|digest| digest.to_string()),
+        verified: true,
+    })
+}
+
+async fn download(
+    stream: &mut FramedStream,
+    remote: &str,
+    destination: &Path,
+    size: u64,
+    cancel: &CancellationToken,
+    progress: &ProgressTracker,
+) -> Result<Outcome, Box<dyn Error>> {
+    tokio::fs::create_dir_all(destination).await?;
+    let (digest, stats) =
+        resume_get_with_progress(stream, remote, destination, progress, cancel).await?;
+    Ok(Outcome {
+        size,
+        stats,
+        verified: digest.is_some(),
+        digest: digest.map(|digest| digest.to_string()),
+    })
+}
+
+/// Brings `stream` back in step with the server after a transfer on it broke off, ending whatever is left of the transfer with [`interrupt_transfer`] so the server keeps what a resumable upload received for the retry.
+///
+/// # Errors
+/// Returns an error if the connection fails or the server does not acknowledge the interruption within [`RESYNC_TIMEOUT`].
+async fn resync(stream: &mut FramedStream) -> Result<(), Box<dyn Error>> {
+    match tokio::time::timeout(RESYNC_TIMEOUT, interrupt_transfer(stream)).await {
+        Ok(result) => result,
+        Err(_) => Err("The server did not acknowledge the interrupted transfer".into()),
+    }
+}
+
+/// Whether retrying might get past `error`: not if the request itself was refused or the content did not match its digest, only if the transfer broke off.
+fn retryable(error: &(dyn Error + 'static)) -> bool {
+    !matches!(
+        RequestError::from_boxed(error).code,
+        ErrorCode::BadRequest
+            | ErrorCode::InvalidPath
+            | ErrorCode::NotFound
+            | ErrorCode::TooLarge
+            | ErrorCode::DigestMismatch
+            | ErrorCode::QuotaExceeded
+            | ErrorCode::Unauthorized
+            | ErrorCode::Cancelled
+    )
+}
+
+fn get_queue_file_path() -> PathBuf {
+    let mut dir = dirs::data_local_dir().unwrap_or_else(|| std::env::current_dir().unwrap());
+    dir.push("zync");
+    dir.push("queue.json");
+    dir
+}
+
+/// The jobs a previous run saved as unfinished to `path`; none if there are none or they cannot be read.
+fn load_jobs(path: &Path) -> Vec<Job> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+fn save_jobs(path: &Path, jobs: &BTreeMap<u64, Job>) {
+    if let Err(e) = write_jobs(path, jobs) {
+        log::warn!("Failed to save the transfer queue to {:?}: {}", path, e);
+    }
+}
+
+fn write_jobs(path: &Path, jobs: &BTreeMap<u64, Job>) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let jobs: Vec<&Job> = jobs.values().collect();
+    fs::write(path, serde_json::to_string_pretty(&jobs)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tcp_server::protocol::codec::FrameCodec;
+    use tcp_server::protocol::messages::Scheme;
+    use tcp_server::protocol::progress::Progress;
+    use tcp_server::storage::storage::Storage;
+
+    fn config() -> QueueConfig {
+        QueueConfig {
+            concurrency: 3,
+            retries: 2,
+            backoff: Duration::from_secs(2),
+            max_backoff: Duration::from_secs(60),
+        }
+    }
+
+    fn job(id: u64, server: Option<&str>) -> Job {
+        Job {
+            id,
+            kind: JobKind::Download {
+                remote: format!("dir/file{id}.bin"),
+                destination: PathBuf::from("/tmp/downloads"),
+                size: 100,
+            },
+            attempts: 0,
+            server: server.map(str::to_string),
+        }
+    }
+
+    fn entry(job: Job) -> Entry {
+        Entry {
+            job,
+            transfer: 0,
+            cancel: CancellationToken::new(),
+        }
+    }
+
+    fn context(server: &str, https: bool) -> QueueContext {
+        let (stream, _) = tokio::io::duplex(64);
+        let mut endpoint = Endpoint::parse("127.0.0.1:8080", Scheme::Https).unwrap();
+        endpoint.http_port = https.then_some(8443);
+        QueueContext {
+            stream: Arc::new(Mutex::new(FrameCodec::framed(stream))),
+            client: https.then(Client::new),
+            endpoint,
+            server: server.to_string(),
+        }
+    }
+
+    fn state(jobs: Vec<Job>, context: Option<QueueContext>) -> QueueState {
+        let mut state = QueueState {
+            context,
+            ..Default::default()
+        };
+        for job in jobs {
+            state.unfinished.insert(job.id, job.clone());
+            state.waiting.push_back(entry(job));
+        }
+        state
+    }
+
+    fn ids(entries: &[(Entry, QueueContext)]) -> Vec<u64> {
+        entries.iter().map(|(entry, _)| entry.job.id).collect()
+    }
+
+    fn waiting(state: &QueueState) -> Vec<u64> {
+        state.waiting.iter().map(|entry| entry.job.id).collect()
+    }
+
+    /// Serves `storage` over `stream` the way a paired connection does, until the client hangs up.
+    async fn serve(stream: tokio::io::DuplexStream, storage: Storage) {
+        use futures::{SinkExt, StreamExt};
+        use tcp_server::http::get::get_file;
+        use tcp_server::http::manifest::receive_manifest;
+        use tcp_server::http::upload::upload_file;
+        use tcp_server::protocol::frame::Opcode;
+        use tcp_server::protocol::transfer::send_error;
+
+        let mut stream = FrameCodec::framed(stream);
+        while let Some(Ok(frame)) = stream.next().await {
+            let result = match frame.opcode {
+                Opcode::Get => get_file(&mut stream, &frame, &storage).await,
+                Opcode::Put => upload_file(&mut stream, &frame, &storage).await,
+                Opcode::Manifest => receive_manifest(&mut stream, &frame, &storage).await,
+                _ => Err(RequestError::cancelled().into()),
+            };
+            let failure = result.err().map(|e| RequestError::from_boxed(e.as_ref()));
+            if let Some(failure) = failure
+                && send_error(&mut stream, &failure).await.is_err()
+            {
+                return;
+            }
+            if stream.flush().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Runs `kind` over a new connection to `storage`, hanging up once `hang_up_at` bytes of it are done.
+    ///
+    /// # Returns
+    /// How the attempt ended and how many bytes it found already done when it started.
+    async fn attempt(
+        storage: &Storage,
+        kind: &JobKind,
+        hang_up_at: u64,
+    ) -> (Result<Outcome, Box<dyn Error>>, u64) {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let server = tokio::spawn(serve(server, storage.clone()));
+        let mut stream = FrameCodec::framed(client);
+        let attempt = attempt_on(&mut stream, kind, hang_up_at).await;
+        drop(stream);
+        server.await.unwrap();
+        attempt
+    }
+
+    /// Runs `kind` over `stream`, giving up on it once `hang_up_at` bytes of it are done and leaving the stream wherever the transfer stopped.
+    ///
+    /// # Returns
+    /// How the attempt ended and how many bytes it found already done when it started.
+    async fn attempt_on(
+        stream: &mut FramedStream,
+        kind: &JobKind,
+        hang_up_at: u64,
+    ) -> (Result<Outcome, Box<dyn Error>>, u64) {
+        let hang_up = CancellationToken::new();
+        let started_at = Arc::new(Mutex::new(None));
+        let progress = {
+            let hang_up = hang_up.clone();
+            let started_at = started_at.clone();
+            ProgressTracker::new(move |progress: &Progress| {
+                started_at.lock().unwrap().get_or_insert(progress.done);
+                if progress.done >= hang_up_at {
+                    hang_up.cancel();
+                }
+            })
+            .with_interval(Duration::ZERO)
+        };
+        let cancel = CancellationToken::new();
+        let result = tokio::select! {
+            result = over_stream(stream, kind, &cancel, &progress) => result,
+            _ = hang_up.cancelled() => Err("Connection lost".into()),
+        };
+        let started_at = started_at.lock().unwrap().unwrap_or_default();
+        (result, started_at)
+    }
+
+    /// Writes `size` bytes of a pattern zstd barely shrinks to `path`.
+    fn write_noise(path: &Path, size: usize) {
+        let mut seed = size as u64 | 1;
+        let bytes: Vec<u8> = (0..size)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed as u8
+            })
+            .collect();
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let config = config();
+        assert_eq!(config.delay(1), Duration::from_secs(2));
+        assert_eq!(config.delay(2), Duration::from_secs(4));
+        assert_eq!(config.delay(3), Duration::from_secs(8));
+        assert_eq!(config.delay(5), Duration::from_secs(32));
+        assert_eq!(config.delay(6), Duration::from_secs(60));
+        assert_eq!(config.delay(100), Duration::from_secs(60));
+        assert_eq!(config.delay(0), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn retries_stop_at_the_cap() {
+        let config = config();
+        let broken = std::io::Error::new(std::io::ErrorKind::ConnectionReset, "reset");
+        assert!(config.retry(0, &broken));
+        assert!(config.retry(1, &broken));
+        assert!(!config.retry(2, &broken));
+        assert!(!config.retry(3, &broken));
+    }
+
+    #[test]
+    fn only_broken_off_transfers_are_retried() {
+        let config = config();
+        for code in [
+            ErrorCode::Internal,
+            ErrorCode::Busy,
+            ErrorCode::OffsetMismatch,
+        ] {
+            assert!(config.retry(0, &RequestError::new(code, "")), "{code:?}");
+        }
+        for code in [
+            ErrorCode::DigestMismatch,
+            ErrorCode::NotFound,
+            ErrorCode::Unauthorized,
+            ErrorCode::QuotaExceeded,
+            ErrorCode::Cancelled,
+        ] {
+            assert!(!config.retry(0, &RequestError::new(code, "")), "{code:?}");
+        }
+        let other: Box<dyn Error> = "Connection closed by peer".into();
+        assert!(config.retry(0, other.as_ref()));
+    }
+
+    #[test]
+    fn cancelled_waiting_jobs_leave_the_queue() {
+        let mut state = state(vec![job(0, None), job(1, None), job(2, None)], None);
+        state.waiting[1].cancel.cancel();
+
+        let cancelled = state.take_cancelled();
+        assert_eq!(cancelled.len(), 1);
+        assert_eq!(cancelled[0].job.id, 1);
+        assert_eq!(waiting(&state), [0, 2]);
+        assert_eq!(state.unfinished.keys().copied().collect::<Vec<_>>(), [0, 2]);
+        assert!(state.take_cancelled().is_empty());
+    }
+
+    #[test]
+    fn jobs_wait_for_a_connection() {
+        let mut state = state(vec![job(0, None)], None);
+        assert!(state.take_startable(3).is_empty());
+        assert_eq!(waiting(&state), [0]);
+        assert_eq!(state.running, 0);
+    }
+
+    #[test]
+    fn jobs_only_start_on_their_server() {
+        let jobs = vec![job(0, Some("other")), job(1, Some("this")), job(2, None)];
+        let mut state = state(jobs, Some(context("this", true)));
+        assert_eq!(state.waiting_elsewhere("this"), 1);
+
+        let started = state.take_startable(3);
+        assert_eq!(ids(&started), [1, 2]);
+        assert_eq!(waiting(&state), [0]);
+        assert_eq!(started[1].0.job.server.as_deref(), Some("this"));
+        assert_eq!(state.unfinished[&2].server.as_deref(), Some("this"));
+    }
+
+    #[test]
+    fn https_jobs_run_side_by_side_up_to_the_concurrency() {
+        let jobs = (0..5).map(|id| job(id, None)).collect();
+        let mut state = state(jobs, Some(context("this", true)));
+        assert_eq!(ids(&state.take_startable(3)), [0, 1, 2]);
+        assert_eq!(state.running, 3);
+        assert!(!state.stream_busy);
+        assert!(state.take_startable(3).is_empty());
+        assert_eq!(waiting(&state), [3, 4]);
+    }
+
+    #[test]
+    fn stream_jobs_run_one_at_a_time() {
+        let jobs = (0..3).map(|id| job(id, None)).collect();
+        let mut state = state(jobs, Some(context("this", false)));
+        assert_eq!(ids(&state.take_startable(3)), [0]);
+        assert!(state.stream_busy);
+        assert!(state.take_startable(3).is_empty());
+        assert_eq!(waiting(&state), [1, 2]);
+
+        state.running -= 1;
+        state.stream_busy = false;
+        assert_eq!(ids(&state.take_startable(3)), [1]);
+    }
+
+    #[tokio::test]
+    async fn resync_skips_what_is_left_of_a_broken_off_transfer() {
+        use futures::{SinkExt, StreamExt};
+        use tcp_server::protocol::frame::{Frame, Opcode};
+        use tcp_server::protocol::messages::ErrorReply;
+        use tcp_server::protocol::transfer::send_error;
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let mut client = FrameCodec::framed(client);
+        let mut server = FrameCodec::framed(server);
+        for _ in 0..3 {
+            server
+                .send(Frame::new(Opcode::Data, vec![0; 100]))
+                .await
+                .unwrap();
+        }
+        let acknowledge = tokio::spawn(async move {
+            let frame = server.next().await.unwrap().unwrap();
+            let reply: ErrorReply = frame.parse().unwrap();
+            assert_eq!(reply.code, ErrorCode::Interrupted);
+            send_error(&mut server, &RequestError::interrupted())
+                .await
+                .unwrap();
+            server
+                .send(Frame::new(Opcode::Ok, Vec::new()))
+                .await
+                .unwrap();
+        });
+
+        resync(&mut client).await.unwrap();
+        acknowledge.await.unwrap();
+        let next = client.next().await.unwrap().unwrap();
+        assert_eq!(next.opcode, Opcode::Ok);
+    }
+
+    #[tokio::test]
+    async fn directory_uploads_over_the_stream_are_measured_whole_and_can_be_cancelled() {
+        let local = tempfile::tempdir().unwrap();
+        for tree in ["photos", "again"] {
+            fs::create_dir_all(local.path().join(tree).join("sub")).unwrap();
+            for name in ["a.bin", "b.bin", "sub/c.bin"] {
+                write_noise(&local.path().join(tree).join(name), 300_000);
+            }
+        }
+        let remote = tempfile::tempdir().unwrap();
+        let storage = Storage::new(remote.path().join("root"));
+        storage.ensure_root().unwrap();
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(serve(server, storage.clone()));
+        let mut stream = FrameCodec::framed(client);
+
+        let starts = Arc::new(Mutex::new(Vec::new()));
+        let progress = {
+            let starts = starts.clone();
+            ProgressTracker::new(move |progress: &Progress| {
+                if progress.done == 0 {
+                    starts.lock().unwrap().push(progress.total);
+                }
+            })
+        };
+        let tree = local.path().join("photos");
+        put(&mut stream, &tree, &CancellationToken::new(), &progress)
+            .await
+            .unwrap();
+        assert_eq!(*starts.lock().unwrap(), [900_000]);
+        let done = progress.progress().unwrap();
+        assert_eq!((done.done, done.total), (900_000, 900_000));
+        assert!(remote.path().join("root/photos/sub/c.bin").is_file());
+
+        let cancel = CancellationToken::new();
+        let progress = {
+            let cancel = cancel.clone();
+            ProgressTracker::new(move |progress: &Progress| {
+                if progress.done > 400_000 {
+                    cancel.cancel();
+                }
+            })
+            .with_interval(Duration::ZERO)
+        };
+        let tree = local.path().join("again");
+        let Err(error) = put(&mut stream, &tree, &cancel, &progress).await else {
+            panic!("The cancelled upload went through");
+        };
+        assert_eq!(
+            RequestError::from_boxed(error.as_ref()).code,
+            ErrorCode::Cancelled
+        );
+        assert!(!remote.path().join("root/again/sub/c.bin").exists());
+
+        let single = local.path().join("photos/a.bin");
+        let outcome = put(
+            &mut stream,
+            &single,
+            &CancellationToken::new(),
+            &ProgressTracker::disabled(),
+        )
+        .await
+        .unwrap();
+        assert!(outcome.digest.is_some());
+        assert_eq!(
+            fs::read(remote.path().join("root/a.bin")).unwrap(),
+            fs::read(&single).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn broken_off_downloads_over_the_stream_resume_from_the_partial_file() {
+        let remote = tempfile::tempdir().unwrap();
+        let storage = Storage::new(remote.path().join("root"));
+        storage.ensure_root().unwrap();
+        write_noise(&remote.path().join("root/big.bin"), 2_000_000);
+        let local = tempfile::tempdir().unwrap();
+        let kind = JobKind::Download {
+            remote: "big.bin".to_string(),
+            destination: local.path().to_path_buf(),
+            size: 2_000_000,
+        };
+
+        let (result, started_at) = attempt(&storage, &kind, 500_000).await;
+        assert!(result.is_err());
+        assert_eq!(started_at, 0);
+        // Lets the last write of the broken-off attempt land, as the queue's backoff would.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let partial = fs::metadata(local.path().join(".big.bin.part"))
+            .unwrap()
+            .len();
+        assert!((500_000..2_000_000).contains(&partial), "{partial}");
+
+        let (result, started_at) = attempt(&storage, &kind, u64::MAX).await;
+        let outcome = result.unwrap();
+        assert_eq!(started_at, partial);
+        assert_eq!(outcome.stats.original, 2_000_000 - partial);
+        assert_eq!(outcome.size, 2_000_000);
+        assert_eq!(
+            fs::read(local.path().join("big.bin")).unwrap(),
+            fs::read(remote.path().join("root/big.bin")).unwrap()
+        );
+        assert!(!local.path().join(".big.bin.part").exists());
+    }
+
+    #[tokio::test]
+    async fn broken_off_uploads_over_the_stream_resume_from_what_the_server_kept() {
+        let remote = tempfile::tempdir().unwrap();
+        let storage = Storage::new(remote.path().join("root"));
+        storage.ensure_root().unwrap();
+        let local = tempfile::tempdir().unwrap();
+        let file = local.path().join("big.bin");
+        write_noise(&file, 2_000_000);
+        let kind = JobKind::Upload {
+            local: file.clone(),
+        };
+
+        let (result, started_at) = attempt(&storage, &kind, 500_000).await;
+        assert!(result.is_err());
+        assert_eq!(started_at, 0);
+        let partial = storage.partial_len("big.bin").unwrap();
+        assert!((1..2_000_000).contains(&partial), "{partial}");
+
+        let (result, started_at) = attempt(&storage, &kind, u64::MAX).await;
+        let outcome = result.unwrap();
+        assert_eq!(started_at, partial);
+        assert_eq!(outcome.stats.original, 2_000_000 - partial);
+        assert_eq!(outcome.size, 2_000_000);
+        assert_eq!(
+            fs::read(remote.path().join("root/big.bin")).unwrap(),
+            fs::read(&file).unwrap()
+        );
+        assert_eq!(storage.partial_len("big.bin").unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn uploads_broken_off_on_a_live_connection_resume_after_the_resync() {
+        let remote = tempfile::tempdir().unwrap();
+        let storage = Storage::new(remote.path().join("root"));
+        storage.ensure_root().unwrap();
+        let local = tempfile::tempdir().unwrap();
+        let file = local.path().join("big.bin");
+        write_noise(&file, 2_000_000);
+        let kind = JobKind::Upload {
+            local: file.clone(),
+        };
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(serve(server, storage.clone()));
+        let mut stream = FrameCodec::framed(client);
+
+        let (result, _) = attempt_on(&mut stream, &kind, 500_000).await;
+        assert!(result.is_err());
+        resync(&mut stream).await.unwrap();
+        let partial = storage.partial_len("big.bin").unwrap();
+        assert!((1..2_000_000).contains(&partial), "{partial}");
+
+        let (result, started_at) = attempt_on(&mut stream, &kind, u64::MAX).await;
+        let outcome = result.unwrap();
+        assert_eq!(started_at, partial);
+        assert_eq!(outcome.stats.original, 2_000_000 - partial);
+        assert_eq!(
+            fs::read(remote.path().join("root/big.bin")).unwrap(),
+            fs::read(&file).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn resync_fails_on_a_closed_connection() {
+        let (client, server) = tokio::io::duplex(64);
+        let mut client = FrameCodec::framed(client);
+        drop(server);
+        assert!(resync(&mut client).await.is_err());
+    }
+
+    #[test]
+    fn the_queue_file_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("zync").join("queue.json");
+        let mut upload = job(1, Some("AB:CD"));
+        upload.kind = JobKind::Upload {
+            local: PathBuf::from("/home/me/photo.jpg"),
+        };
+        upload.attempts = 2;
+        let jobs: BTreeMap<u64, Job> = [job(0, None), upload]
+            .into_iter()
+            .map(|job| (job.id, job))
+            .collect();
+
+        write_jobs(&path, &jobs).unwrap();
+        assert_eq!(load_jobs(&path), jobs.into_values().collect::<Vec<_>>());
+        write_jobs(&path, &BTreeMap::new()).unwrap();
+        assert!(load_jobs(&path).is_empty());
+    }
+
+    #[test]
+    fn unreadable_queue_files_restore_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("queue.json");
+        assert!(load_jobs(&path).is_empty());
+        fs::write(&path, "not json").unwrap();
+        assert!(load_jobs(&path).is_empty());
+    }
+
+    #[test]
+    fn jobs_saved_without_a_server_load_unbound() {
+        let saved = r#"[{"id":3,"kind":"upload","local":"/a.txt"}]"#;
+        let jobs: Vec<Job> = serde_json::from_str(saved).unwrap();
+        assert_eq!(jobs[0].server, None);
+        assert_eq!(jobs[0].attempts, 0);
+    }
+}
@@ -0,0 +1,31 @@
+use futures_util::SinkExt;
+use std::error::Error;
+use tcp_server::protocol::codec::FramedStream;
+use tcp_server::protocol::frame::{Frame, Opcode};
+use tcp_server::protocol::messages::{OkReply, PathRequest};
+use tcp_server::protocol::transfer::{next_frame, reject_error};
+
+/// Asks the server to delete `remote_path` from its storage.
+///
+/// # Returns
+/// The server's `OK` reply on success. A rejected request is returned as a boxed
+/// [`RequestError`](tcp_server::protocol::error::RequestError) whose code says why, e.g. `NotFound` or `InvalidPath`.
+pub async fn delete(
+    stream: &mut FramedStream,
+    remote_path: &str,
+) -> Result<OkReply, Box<dyn Error>> {
+    let request = PathRequest {
+        path: remote_path.to_string(),
+    };
+    stream.send(Frame::json(Opcode::Delete, &request)?).await?;
+
+    let reply = next_frame(stream).await?;
+    reject_error(&reply)?;
+    if reply.opcode != Opcode::Ok {
+        return Err(format!("Expected OK frame, got {}", reply.opcode).into());
+    }
+
+    let reply: OkReply = reply.parse()?;
+    log::info!("{}", reply.message);
+    Ok(reply)
+}
@@ -0,0 +1,191 @@
+use reqwest::header::{CONTENT_RANGE, RANGE};
+use reqwest::{Client, StatusCode};
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use tcp_server::protocol::digest::{DIGEST_HEADER, Digest, Hasher};
+use tcp_server::protocol::error::RequestError;
+use tcp_server::protocol::progress::ProgressTracker;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use tokio_util::sync::CancellationToken;
+
+/// Downloads `remote_path` from the HTTP download route at `url` into the `destination` directory through `client`, which carries the TLS settings for `https` URLs.
+///
+/// The body is written to a hidden `.part` file that is renamed into place once complete. If a `.part` file from an interrupted download exists, only the missing bytes are requested with a `Range` header; a server that ignores the range sends the whole file, which then replaces the partial one, and a partial file longer than the remote file is discarded.
+///
+/// The whole file, including bytes kept from an earlier attempt, is hashed and checked against the digest the server announces; on a mismatch the `.part` file is deleted and the download fails.
+///
+/// # Returns
+/// The path the file was saved to and its verified digest, or `None` if the server announced none.
+///
+/// # Errors
+/// Returns an error if the request fails, the server answers with a non-success status, the content does not match its digest, or the file cannot be written.
+pub async fn download(
+    client: &Client,
+    remote_path: &str,
+    url: &str,
+    destination: &Path,
+) -> Result<(PathBuf, Option<Digest>), Box<dyn Error>> {
+    fetch_file(
+        client,
+        remote_path,
+        url,
+        destination,
+        &ProgressTracker::disabled(),
+    )
+    .await
+}
+
+/// Downloads `remote_path` like [`download`], measuring the file as it is saved with `progress` until it is done or `cancel` fires.
+///
+/// A cancelled download drops its request and removes its `.part` file.
+///
+/// # Returns
+/// The path the file was saved to and its verified digest, or `None` if the server announced none.
+///
+/// # Errors
+/// Returns a `Cancelled` [`RequestError`] if the download was cancelled, or any error [`download`] returns.
+pub async fn download_with_progress(
+    client: &Client,
+    remote_path: &str,
+    url: &str,
+    destination: &Path,
+    progress: &ProgressTracker,
+    cancel: &CancellationToken,
+) -> Result<(PathBuf, Option<Digest>), Box<dyn Error>> {
+    tokio::select! {
+        result = fetch_file(client, remote_path, url, destination, progress) => return result,
+        _ = cancel.cancelled() => {}
+    }
+    if let Some(name) = Path::new(remote_path).file_name() {
+        tokio::fs::remove_file(part_path(&destination.join(name)))
+            .await
+            .ok();
+    }
+    log::info!("Download of {} cancelled", remote_path);
+    Err(Box::new(RequestError::cancelled()))
+}
+
+/// The hidden file a download to `final_path` is written to until it is complete.
+fn part_path(final_path: &Path) -> PathBuf {
+    let name = final_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    final_path.with_file_name(format!(".{name}.part"))
+}
+
+async fn fetch_file(
+    client: &Client,
+    remote_path: &str,
+    url: &str,
+    destination: &Path,
+    progress: &ProgressTracker,
+) -> Result<(PathBuf, Option<Digest>), Box<dyn Error>> {
+    let name = Path::new(remote_path)
+        .file_name()
+        .ok_or("Remote path has no file name")?;
+    let final_path = destination.join(name);
+    let part_path = part_path(&final_path);
+    tokio::fs::create_dir_all(destination).await?;
+
+    let mut offset = tokio::fs::metadata(&part_path)
+        .await
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+
+    let request_url = reqwest::Url::parse_with_params(url, &[("path", remote_path)])?;
+    let (mut response, mut file) = loop {
+        let mut request = client.get(request_url.clone());
+        if offset > 0 {
+            log::info!("Resuming download of {} at byte {}", remote_path, offset);
+            request = request.header(RANGE, format!("bytes={offset}-"));
+        }
+        let response = request.send().await?;
+
+        match response.status() {
+            StatusCode::PARTIAL_CONTENT => {
+                let file = OpenOptions::new().append(true).open(&part_path).await?;
+                break (response, file);
+            }
+            StatusCode::OK => {
+                let file = File::create(&part_path).await?;
+                break (response, file);
+            }
+            StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => {
+                let total = response
+                    .headers()
+                    .get(CONTENT_RANGE)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.strip_prefix("bytes */"))
+                    .and_then(|total| total.parse::<u64>().ok());
+                if total == Some(offset) {
+                    // The partial file already holds every byte.
+                    let file = OpenOptions::new().append(true).open(&part_path).await?;
+                    break (response, file);
+                }
+                log::warn!("Partial download of {} is stale, restarting", remote_path);
+                offset = 0;
+            }
+            status => {
+                let message = response.text().await.unwrap_or_default();
+                return Err(
+                    format!("HTTP request failed with status: {} {}", status, message).into(),
+                );
+            }
+        }
+    };
+
+    let expected = response
+        .headers()
+        .get(DIGEST_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::parse::<Digest>)
+        .transpose()?;
+    let mut hasher = Hasher::new(
+        expected
+            .as_ref()
+            .map(|digest| digest.algorithm)
+            .unwrap_or_default(),
+    );
+    let kept = if response.status() == StatusCode::OK {
+        0
+    } else {
+        hasher
+            .update_reader(&mut File::open(&part_path).await?)
+            .await?
+    };
+    let remaining = match response.status() {
+        StatusCode::RANGE_NOT_SATISFIABLE => 0,
+        _ => response.content_length().unwrap_or(0),
+    };
+    progress.start(kept, kept + remaining);
+
+    let mut received = 0u64;
+    while let Some(chunk) = response.chunk().await? {
+        file.write_all(&chunk).await?;
+        hasher.update(&chunk);
+        received += chunk.len() as u64;
+        progress.advance(chunk.len() as u64);
+    }
+    file.sync_all().await?;
+    drop(file);
+
+    let digest = hasher.finalize();
+    if let Some(expected) = &expected {
+        if let Err(e) = expected.verify(&digest) {
+            tokio::fs::remove_file(&part_path).await?;
+            return Err(Box::new(e));
+        }
+    }
+    tokio::fs::rename(&part_path, &final_path).await?;
+
+    log::info!(
+        "Downloaded {} ({} new bytes) to {:?} ({})",
+        remote_path,
+        received,
+        final_path,
+        digest
+    );
+    Ok((final_path, expected.map(|_| digest)))
+}
@@ -1,45 +1,248 @@
-use std::path::PathBuf;
-use tokio::{fs::File, io::AsyncReadExt, io::AsyncWriteExt, net::TcpStream};
+use futures_util::SinkExt;
+use std::path::{Path, PathBuf};
+use tcp_server::protocol::codec::FramedStream;
+use tcp_server::protocol::compression::{Compression, TransferStats};
+use tcp_server::protocol::digest::{Digest, Hasher, HashingWriter};
+use tcp_server::protocol::error::RequestError;
+use tcp_server::protocol::frame::{Frame, Opcode};
+use tcp_server::protocol::messages::{GetRequest, SendHeader};
+use tcp_server::protocol::progress::ProgressTracker;
+use tcp_server::protocol::transfer::{
+    cancel_transfer, next_frame, recv_encoded_body, reject_error,
+};
+use tokio::fs::{File, OpenOptions};
+use tokio_util::sync::CancellationToken;
+
+/// Requests `remote_path` from the server and saves it into the `destination` directory.
+///
+/// Sends a `GET` frame accepting zstd compression and waits for the server's `SEND` header before handing over to [`receive_files`].
+///
+/// # Returns
+/// Returns the verified digest of the file and how many bytes it took on the wire if it is received and saved successfully, or an error carrying the server's message if the request is rejected.
+pub async fn get(
+    stream: &mut FramedStream,
+    remote_path: &str,
+    destination: &Path,
+) -> Result<(Option<Digest>, TransferStats), Box<dyn std::error::Error>> {
+    fetch(
+        stream,
+        remote_path,
+        destination,
+        0,
+        &ProgressTracker::disabled(),
+    )
+    .await
+}
+
+/// Requests `remote_path` like [`get`], measuring the file as it is saved with `progress` until it is done or `cancel` fires.
+///
+/// A cancelled download is cancelled on the server with [`cancel_transfer`], leaving the connection ready for the next request, and its `.part` file is removed.
+///
+/// # Returns
+/// Returns the verified digest of the file and how many bytes it took on the wire if it is received and saved successfully, a `Cancelled` [`RequestError`] if it was cancelled, or an error carrying the server's message if the request is rejected.
+pub async fn get_with_progress(
+    stream: &mut FramedStream,
+    remote_path: &str,
+    destination: &Path,
+    progress: &ProgressTracker,
+    cancel: &CancellationToken,
+) -> Result<(Option<Digest>, TransferStats), Box<dyn std::error::Error>> {
+    fetch_with_progress(stream, remote_path, destination, 0, progress, cancel).await
+}
+
+/// Requests `remote_path` like [`get`], continuing from the partial file an interrupted download left in `destination`.
+///
+/// Only the bytes after the partial file's end are requested; without a partial file the whole file is downloaded.
+///
+/// # Returns
+/// Returns the verified digest of the file and how many bytes the rest of it took on the wire if it is received and saved successfully, or an error carrying the server's message if the request is rejected.
+pub async fn resume_get(
+    stream: &mut FramedStream,
+    remote_path: &str,
+    destination: &Path,
+) -> Result<(Option<Digest>, TransferStats), Box<dyn std::error::Error>> {
+    let offset = partial_len(remote_path, destination).await?;
+    fetch(
+        stream,
+        remote_path,
+        destination,
+        offset,
+        &ProgressTracker::disabled(),
+    )
+    .await
+}
+
+/// Requests `remote_path` like [`resume_get`], measuring the file with `progress`, which counts the partial file as done, until it is done or `cancel` fires.
+///
+/// A cancelled download is cancelled like one from [`get_with_progress`], and its `.part` file is removed with what earlier attempts left in it.
+///
+/// # Returns
+/// Returns the verified digest of the file and how many bytes the rest of it took on the wire if it is received and saved successfully, a `Cancelled` [`RequestError`] if it was cancelled, or an error carrying the server's message if the request is rejected.
+pub async fn resume_get_with_progress(
+    stream: &mut FramedStream,
+    remote_path: &str,
+    destination: &Path,
+    progress: &ProgressTracker,
+    cancel: &CancellationToken,
+) -> Result<(Option<Digest>, TransferStats), Box<dyn std::error::Error>> {
+    let offset = partial_len(remote_path, destination).await?;
+    fetch_with_progress(stream, remote_path, destination, offset, progress, cancel).await
+}
+
+/// How many bytes of `remote_path` an interrupted download left in `destination`, or 0 if it left none.
+async fn partial_len(
+    remote_path: &str,
+    destination: &Path,
+) -> Result<u64, Box<dyn std::error::Error>> {
+    let name = Path::new(remote_path)
+        .file_name()
+        .ok_or("Remote path has no file name")?;
+    let offset = tokio::fs::metadata(part_path(&destination.join(name)))
+        .await
+        .map(|metadata| metadata.len())
+        .unwrap_or(0);
+    if offset > 0 {
+        log::info!("Resuming download of {} at byte {}", remote_path, offset);
+    }
+    Ok(offset)
+}
+
+async fn fetch_with_progress(
+    stream: &mut FramedStream,
+    remote_path: &str,
+    destination: &Path,
+    offset: u64,
+    progress: &ProgressTracker,
+    cancel: &CancellationToken,
+) -> Result<(Option<Digest>, TransferStats), Box<dyn std::error::Error>> {
+    tokio::select! {
+        result = fetch(stream, remote_path, destination, offset, progress) => return result,
+        _ = cancel.cancelled() => {}
+    }
+    cancel_transfer(stream).await?;
+    if let Some(name) = Path::new(remote_path).file_name() {
+        tokio::fs::remove_file(part_path(&destination.join(name)))
+            .await
+            .ok();
+    }
+    log::info!("Download of {} cancelled", remote_path);
+    Err(Box::new(RequestError::cancelled()))
+}
+
+async fn fetch(
+    stream: &mut FramedStream,
+    remote_path: &str,
+    destination: &Path,
+    offset: u64,
+    progress: &ProgressTracker,
+) -> Result<(Option<Digest>, TransferStats), Box<dyn std::error::Error>> {
+    let request = GetRequest {
+        path: remote_path.to_string(),
+        offset,
+        algorithm: Default::default(),
+        compression: Compression::Zstd,
+    };
+    stream.send(Frame::json(Opcode::Get, &request)?).await?;
+
+    let reply = next_frame(stream).await?;
+    reject_error(&reply)?;
+    if reply.opcode != Opcode::Send {
+        return Err(format!("Expected SEND frame, got {}", reply.opcode).into());
+    }
+    let header: SendHeader = reply.parse()?;
+    receive_files(stream, &header, destination, progress).await
+}
 
 #[deny(clippy::never_loop)]
 #[deny(clippy::ptr_arg)]
-/// Receives a file over a TCP stream and saves it to the specified destination path asynchronously.
+/// Receives a file over a framed TCP stream and saves it to the specified destination directory asynchronously.
+///
+/// The function takes the file name, size and starting offset from the server's `SEND` header, ensures the destination directory exists, and writes the received `DATA` frames to a hidden `.part` file that is renamed into place once complete. A non-zero offset appends to the `.part` file left by an earlier attempt, which must hold exactly that many bytes; if the transfer fails, the `.part` file is kept for [`resume_get`].
 ///
-/// The function reads the file size and destination information from the provided slices, ensures the destination directory exists, and writes the received file data to disk.
+/// A body the header announces as compressed is decoded as it arrives. The whole file, including bytes kept from an earlier attempt, is hashed as it is written. If it does not match the digest announced in the header, the `.part` file is deleted and the transfer fails.
 ///
 /// # Parameters
-/// - `buffer`: Temporary buffer used for reading data from the stream.
-/// - `parts`: Slice containing protocol-specific metadata, where `parts[2]` is expected to be the file size as a string.
-/// - `destination`: Slice containing destination information, where `destination[1]` is the filename and `destination[2]` is the directory path.
+/// - `header`: The `SEND` header announcing the file path, size and offset.
+/// - `destination`: Directory the file is saved into, under the header's path.
+/// - `progress`: Measures the file as it is written, counting the bytes kept from an earlier attempt as already done.
 ///
 /// # Returns
-/// Returns `Ok(())` if the file is received and saved successfully, or an error if any step fails.
+/// Returns the verified digest, or `None` if the server announced none, along with how many bytes the body took on the wire, once the file is received and saved successfully, or an error if any step fails.
 pub async fn receive_files(
-    stream: &mut TcpStream,
-    buffer: &mut [u8],
-    parts: &[&str],
-    destination: &[&str],
-) -> Result<(), Box<dyn std::error::Error>> {
-    let source = destination[1];
-    let destination = PathBuf::from(destination[2]);
+    stream: &mut FramedStream,
+    header: &SendHeader,
+    destination: &Path,
+    progress: &ProgressTracker,
+) -> Result<(Option<Digest>, TransferStats), Box<dyn std::error::Error>> {
+    let remaining = header
+        .size
+        .checked_sub(header.offset)
+        .ok_or("Server resumed past the end of the file")?;
+    let source = Path::new(&header.path)
+        .file_name()
+        .ok_or("Server sent an empty file name")?;
     let final_path = destination.join(source);
+    let part_path = part_path(&final_path);
 
-    let file_size: u64 = parts[2].parse()?;
-    log::info!("Receiving file: {} to {:?}", source, final_path);
+    log::info!("Receiving file: {} to {:?}", header.path, final_path);
 
     if let Some(parent) = final_path.parent() {
         tokio::fs::create_dir_all(parent).await?;
     }
-    let mut file = File::create(&final_path).await?;
-    let mut remaining = file_size;
-
-    while remaining > 0 {
-        let bytes_to_read = std::cmp::min(remaining as usize, buffer.len());
-        let n = stream.read(&mut buffer[..bytes_to_read]).await?;
-        file.write_all(&buffer[..n]).await?;
-        remaining -= n as u64;
+    let algorithm = header
+        .digest
+        .as_ref()
+        .map(|digest| digest.algorithm)
+        .unwrap_or_default();
+    let mut hasher = Hasher::new(algorithm);
+    let file = if header.offset == 0 {
+        File::create(&part_path).await?
+    } else {
+        let file = OpenOptions::new().append(true).open(&part_path).await?;
+        let held = file.metadata().await?.len();
+        if held != header.offset {
+            return Err(format!(
+                "Partial file holds {} bytes but the server resumed at {}",
+                held, header.offset
+            )
+            .into());
+        }
+        hasher
+            .update_reader(&mut File::open(&part_path).await?)
+            .await?;
+        file
+    };
+
+    progress.start(header.offset, header.size);
+    let mut writer = HashingWriter::new(progress.writer(file), hasher);
+    let stats = recv_encoded_body(stream, &mut writer, remaining, header.compression).await?;
+    let (file, digest) = writer.into_parts();
+    let file = file.into_inner();
+    file.sync_all().await?;
+    drop(file);
+
+    if let Some(expected) = &header.digest {
+        if let Err(e) = expected.verify(&digest) {
+            tokio::fs::remove_file(&part_path).await?;
+            return Err(Box::new(e));
+        }
     }
+    tokio::fs::rename(&part_path, &final_path).await?;
+
+    log::info!(
+        "File received and saved to: {:?} ({}, {})",
+        final_path,
+        digest,
+        stats
+    );
+    Ok((header.digest.as_ref().map(|_| digest), stats))
+}
 
-    log::info!("File received and saved to: {:?}", final_path);
-    Ok(())
+/// The hidden `.part` file a download into `path` is written to until it completes.
+fn part_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{name}.part"))
 }
@@ -0,0 +1,30 @@
+use futures_util::SinkExt;
+use std::error::Error;
+use tcp_server::protocol::codec::FramedStream;
+use tcp_server::protocol::frame::{Frame, Opcode, ZYNC_VERSION};
+use tcp_server::protocol::messages::{HelloRequest, WelcomeReply};
+use tcp_server::protocol::transfer::{next_frame, reject_error};
+
+use crate::app::USER;
+
+/// Introduces this client to the server with a `HELLO` frame, which servers answer before pairing as well as after.
+///
+/// # Returns
+/// The server's `WELCOME` reply: its name, zync release and protocol version, its free space and the requests and features it supports.
+///
+/// # Errors
+/// Returns an error if the server refuses the request or the connection fails.
+pub async fn hello(stream: &mut FramedStream) -> Result<WelcomeReply, Box<dyn Error>> {
+    let request = HelloRequest {
+        name: USER.clone(),
+        version: ZYNC_VERSION.to_string(),
+    };
+    stream.send(Frame::json(Opcode::Hello, &request)?).await?;
+
+    let reply = next_frame(stream).await?;
+    reject_error(&reply)?;
+    if reply.opcode != Opcode::Welcome {
+        return Err(format!("Expected WELCOME frame, got {}", reply.opcode).into());
+    }
+    Ok(reply.parse()?)
+}
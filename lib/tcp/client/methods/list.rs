@@ -1,24 +1,46 @@
-use log::{info, warn};
-use serde_json::Value;
-use std::{error::Error, str};
-use tokio::{io::AsyncReadExt, net::TcpStream};
+use futures_util::SinkExt;
+use std::error::Error;
+use tcp_server::protocol::codec::FramedStream;
+use tcp_server::protocol::frame::{Frame, Opcode};
+use tcp_server::protocol::messages::{ListRequest, ListingReply, RemoteEntry};
+use tcp_server::protocol::transfer::{next_frame, reject_error};
 
-pub async fn list(stream: &mut TcpStream, buf: &mut [u8]) -> Result<(), Box<dyn Error>> {
-    let request = stream.read(buf).await?;
-    let initial_message = str::from_utf8(&buf[..request])?.trim();
-    info!("{}", initial_message);
-
-    let request = stream.read(buf).await?;
-    let json_response = str::from_utf8(&buf[..request])?.trim();
-
-    let file_list: Value = serde_json::from_str(json_response)?;
-    if let Value::Array(files) = file_list {
-        for file in files {
-            println!("{}", file.as_str().unwrap_or(""));
+/// Lists the part of the server's storage `request` describes, following the server's pages until every matching entry has been received.
+///
+/// The request's `offset` is where the listing starts and its `limit` the size of each page the server is asked for.
+///
+/// # Returns
+/// Every matching entry from the request's offset on, in the server's order, or an error carrying the server's message if a request is rejected.
+pub async fn list(
+    stream: &mut FramedStream,
+    request: &ListRequest,
+) -> Result<Vec<RemoteEntry>, Box<dyn Error>> {
+    let mut request = request.clone();
+    let mut entries = Vec::new();
+    loop {
+        let page = list_page(stream, &request).await?;
+        entries.extend(page.entries);
+        match page.next_offset {
+            Some(next) if next > request.offset => request.offset = next,
+            _ => return Ok(entries),
         }
-    } else {
-        warn!("Unexpected response format");
     }
+}
 
-    Ok(())
+/// Sends a single `LIST` request and returns the page of entries the server answers with.
+///
+/// # Returns
+/// The server's `LISTING` reply, with the page, the number of matching entries and where the next page starts, or an error carrying the server's message if the request is rejected.
+pub async fn list_page(
+    stream: &mut FramedStream,
+    request: &ListRequest,
+) -> Result<ListingReply, Box<dyn Error>> {
+    stream.send(Frame::json(Opcode::List, request)?).await?;
+
+    let reply = next_frame(stream).await?;
+    reject_error(&reply)?;
+    if reply.opcode != Opcode::Listing {
+        return Err(format!("Expected LISTING frame, got {}", reply.opcode).into());
+    }
+    Ok(reply.parse()?)
 }
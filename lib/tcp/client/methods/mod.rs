@@ -1,3 +1,8 @@
+pub mod delete;
+pub mod download;
 pub mod get;
+pub mod hello;
 pub mod list;
+pub mod pair;
+pub mod put;
 pub mod upload;
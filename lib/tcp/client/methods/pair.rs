@@ -0,0 +1,164 @@
+use futures_util::SinkExt;
+use reqwest::Client;
+use reqwest::header::CONTENT_TYPE;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+use tcp_server::pairing::pairing::{Role, SessionKey, Spake2};
+use tcp_server::protocol::codec::FramedStream;
+use tcp_server::protocol::frame::{Frame, Opcode};
+use tcp_server::protocol::messages::{
+    ConfirmReply, ConfirmRequest, HttpEndpoint, PairReply, PairRequest,
+};
+use tcp_server::protocol::transfer::{next_frame, reject_error};
+use tcp_server::tls::tls::write_private;
+
+use crate::app::USER;
+
+/// The outcome of pairing over a TCP connection.
+#[derive(Debug, Clone)]
+pub struct Paired {
+    /// The session token that authorizes this client's HTTP requests to the same server.
+    pub session: String,
+    /// Where the server serves its HTTP routes, if it said.
+    pub http: Option<HttpEndpoint>,
+}
+
+/// Pairs this connection with the server using the `code` it displays.
+///
+/// Runs the SPAKE2 handshake over `PAIR`/`CHALLENGE`/`CONFIRM` frames; the code itself never crosses the wire. The handshake is bound to `certificate`, the fingerprint of the certificate the server presented if the connection runs over TLS, so it only succeeds with the server that holds that certificate. The client confirms its key first and the server answers with its own confirmation, which is checked before the session is trusted. Once this returns, the server serves the connection's requests.
+///
+/// # Errors
+/// Returns an error if the code is wrong, in which case the server displays a new one after a few wrong guesses, if the server could not prove it used the same code and certificate, or if the connection fails.
+pub async fn pair(
+    stream: &mut FramedStream,
+    code: &str,
+    certificate: Option<&str>,
+) -> Result<Paired, Box<dyn Error>> {
+    let (spake, request) = start(code);
+    stream.send(Frame::json(Opcode::Pair, &request)?).await?;
+
+    let reply = next_frame(stream).await?;
+    reject_error(&reply)?;
+    if reply.opcode != Opcode::Challenge {
+        return Err(format!("Expected CHALLENGE frame, got {}", reply.opcode).into());
+    }
+    let reply: PairReply = reply.parse()?;
+    let http = reply.http;
+    let (key, confirm) = finish(spake, reply, certificate)?;
+    stream.send(Frame::json(Opcode::Confirm, &confirm)?).await?;
+
+    let reply = next_frame(stream).await?;
+    reject_error(&reply)?;
+    if reply.opcode != Opcode::Ok {
+        return Err(format!("Expected OK frame, got {}", reply.opcode).into());
+    }
+    verify(&key, &reply.parse()?)?;
+    Ok(Paired {
+        session: key.token(),
+        http,
+    })
+}
+
+/// Pairs with the server through its HTTP `/pair` route at `url`, like [`pair`], binding the handshake to the `certificate` fingerprint `client` pins for it.
+///
+/// # Returns
+/// The session token to send with every later request.
+///
+/// # Errors
+/// Returns an error if the code is wrong, either request fails, or the server answers with a non-success status.
+pub async fn pair_http(
+    client: &Client,
+    url: &str,
+    code: &str,
+    certificate: Option<&str>,
+) -> Result<String, Box<dyn Error>> {
+    let (spake, request) = start(code);
+    let response = client
+        .post(url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(&request)?)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let message = response.text().await.unwrap_or_default();
+        return Err(format!("Pairing failed with status: {} {}", status, message).into());
+    }
+    let reply: PairReply = serde_json::from_slice(&response.bytes().await?)?;
+    let (key, confirm) = finish(spake, reply, certificate)?;
+
+    let response = client
+        .post(format!("{}/confirm", url.trim_end_matches('/')))
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(&confirm)?)
+        .send()
+        .await?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let message = response.text().await.unwrap_or_default();
+        return Err(format!("Pairing failed with status: {} {}", status, message).into());
+    }
+    let reply: ConfirmReply = serde_json::from_slice(&response.bytes().await?)?;
+    verify(&key, &reply)?;
+    Ok(key.token())
+}
+
+fn start(code: &str) -> (Spake2, PairRequest) {
+    let spake = Spake2::start(Role::Client, code);
+    let request = PairRequest {
+        name: USER.clone(),
+        message: spake.message(),
+    };
+    (spake, request)
+}
+
+/// Derives the session key from the server's reply and the `certificate` it presented, along with the confirmation proving this side derived it and the masked [`user_secret`] its user name is bound to.
+fn finish(
+    spake: Spake2,
+    reply: PairReply,
+    certificate: Option<&str>,
+) -> Result<(SessionKey, ConfirmRequest), Box<dyn Error>> {
+    let key = spake.finish(&reply.message, certificate)?;
+    let confirm = ConfirmRequest {
+        session: reply.session,
+        confirmation: key.confirmation(Role::Client),
+        secret: key.mask_secret(&user_secret()?),
+    };
+    Ok((key, confirm))
+}
+
+/// Checks the server derived the same key, which it can only have done with the same code and certificate.
+fn verify(key: &SessionKey, reply: &ConfirmReply) -> Result<(), Box<dyn Error>> {
+    if !key.verify_confirmation(Role::Server, &reply.confirmation) {
+        return Err("The server did not prove it knows the pairing code".into());
+    }
+    Ok(())
+}
+
+/// The secret this client proves when pairing, so no other client can pair under its user name; created on first use and kept in `user_secret` in the app's data directory, readable only by its owner.
+///
+/// # Errors
+/// Returns an error if the secret cannot be read or saved.
+pub fn user_secret() -> Result<[u8; 32], Box<dyn Error>> {
+    let path = get_user_secret_path();
+    if let Ok(contents) = fs::read(&path) {
+        if let Ok(secret) = contents.try_into() {
+            return Ok(secret);
+        }
+        log::warn!("Replacing the malformed user secret in {:?}", path);
+    }
+    let secret = rand::random::<[u8; 32]>();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_private(&path, &secret)?;
+    Ok(secret)
+}
+
+fn get_user_secret_path() -> PathBuf {
+    let mut dir = dirs::data_local_dir().unwrap_or_else(|| std::env::current_dir().unwrap());
+    dir.push("zync");
+    dir.push("user_secret");
+    dir
+}
@@ -0,0 +1,259 @@
+use futures_util::SinkExt;
+use std::error::Error;
+use std::io::SeekFrom;
+use std::path::Path;
+use tcp_server::protocol::codec::FramedStream;
+use tcp_server::protocol::compression::Compression;
+use tcp_server::protocol::digest::{HashAlgorithm, digest_reader};
+use tcp_server::protocol::error::RequestError;
+use tcp_server::protocol::frame::{Frame, Opcode};
+use tcp_server::protocol::manifest::{Manifest, mode_of, mtime_of};
+use tcp_server::protocol::messages::{OkReply, PutRequest, ReadyReply};
+use tcp_server::protocol::progress::ProgressTracker;
+use tcp_server::protocol::transfer::{
+    cancel_transfer, next_frame, reject_error, send_encoded_body,
+};
+use tokio::fs::File;
+use tokio::io::AsyncSeekExt;
+use tokio_util::sync::CancellationToken;
+
+/// Uploads a local file to the server's storage over the raw TCP protocol.
+///
+/// Sends a `PUT` frame with the remote path, file size, BLAKE3 digest, mode and modification time, waits for the server to answer `READY`,
+/// then streams the file from disk as `DATA` frames. The server
+/// rejects the upload and discards it if what it received does not match the digest.
+///
+/// Unless the file is small or its type is already compressed, the request proposes zstd compression and the body is compressed if the server agrees in its `READY` reply.
+///
+/// # Parameters
+/// - `stream`: The framed TCP stream to which the file will be uploaded.
+/// - `local_path`: The path of the file to upload.
+/// - `remote_path`: Where the file should be stored, relative to the server's storage root.
+///
+/// # Returns
+/// The server's `OK` reply, carrying the verified digest and how many bytes the body took on the wire, once the file has been stored, or an error if the server rejects the upload or any I/O operation fails.
+pub async fn put(
+    stream: &mut FramedStream,
+    local_path: &Path,
+    remote_path: &str,
+) -> Result<OkReply, Box<dyn Error>> {
+    send_file(
+        stream,
+        local_path,
+        remote_path,
+        false,
+        &ProgressTracker::disabled(),
+        false,
+    )
+    .await
+}
+
+/// Uploads a local file like [`put`], measuring it with `progress` as it is sent until it is done or `cancel` fires.
+///
+/// A cancelled upload is cancelled on the server with [`cancel_transfer`], which removes the partial file and leaves the connection ready for the next request.
+///
+/// # Returns
+/// The server's `OK` reply once the file has been stored, a `Cancelled` [`RequestError`] if it was cancelled, or an error if the server rejects the upload or any I/O operation fails.
+pub async fn put_with_progress(
+    stream: &mut FramedStream,
+    local_path: &Path,
+    remote_path: &str,
+    progress: &ProgressTracker,
+    cancel: &CancellationToken,
+) -> Result<OkReply, Box<dyn Error>> {
+    send_cancellable(stream, local_path, remote_path, false, progress, cancel).await
+}
+
+/// Uploads a local file like [`put`], continuing from whatever an earlier, interrupted upload left on the server.
+///
+/// The server reports in its `READY` reply how many bytes it already holds, and only the rest of the file is sent. If this attempt fails too, the server keeps what arrived for the next one.
+///
+/// # Returns
+/// The server's `OK` reply once the file has been stored, or an error if the server rejects the upload or any I/O operation fails.
+pub async fn resume_put(
+    stream: &mut FramedStream,
+    local_path: &Path,
+    remote_path: &str,
+) -> Result<OkReply, Box<dyn Error>> {
+    send_file(
+        stream,
+        local_path,
+        remote_path,
+        true,
+        &ProgressTracker::disabled(),
+        false,
+    )
+    .await
+}
+
+/// Uploads a local file like [`resume_put`], measuring it with `progress`, which counts the bytes the server already holds as done, until it is done or `cancel` fires.
+///
+/// A cancelled upload is cancelled on the server with [`cancel_transfer`], which removes the partial file with what earlier attempts left in it, and leaves the connection ready for the next request.
+///
+/// # Returns
+/// The server's `OK` reply once the file has been stored, a `Cancelled` [`RequestError`] if it was cancelled, or an error if the server rejects the upload or any I/O operation fails.
+pub async fn resume_put_with_progress(
+    stream: &mut FramedStream,
+    local_path: &Path,
+    remote_path: &str,
+    progress: &ProgressTracker,
+    cancel: &CancellationToken,
+) -> Result<OkReply, Box<dyn Error>> {
+    send_cancellable(stream, local_path, remote_path, true, progress, cancel).await
+}
+
+async fn send_cancellable(
+    stream: &mut FramedStream,
+    local_path: &Path,
+    remote_path: &str,
+    resume: bool,
+    progress: &ProgressTracker,
+    cancel: &CancellationToken,
+) -> Result<OkReply, Box<dyn Error>> {
+    tokio::select! {
+        result = send_file(stream, local_path, remote_path, resume, progress, false) => return result,
+        _ = cancel.cancelled() => {}
+    }
+    cancel_transfer(stream).await?;
+    log::info!("Upload of {} cancelled", local_path.display());
+    Err(Box::new(RequestError::cancelled()))
+}
+
+/// Uploads the local directory `local_dir` and everything below it to `remote_root` in the server's storage.
+///
+/// Walks the tree and sends it as a `MANIFEST` frame first, so the server can create the directories and reject a tree that breaks its limits before any data is sent. Each file then follows with a [`put`] to its place under `remote_root`, carrying its mode and modification time. Symlinks are skipped.
+///
+/// # Returns
+/// The server's `OK` reply for each file, in upload order, or an error if the tree cannot be read, the server rejects the manifest or a file, or any I/O operation fails.
+pub async fn put_dir(
+    stream: &mut FramedStream,
+    local_dir: &Path,
+    remote_root: &str,
+) -> Result<Vec<OkReply>, Box<dyn Error>> {
+    send_tree(stream, local_dir, remote_root, &ProgressTracker::disabled()).await
+}
+
+/// Uploads the local directory `local_dir` like [`put_dir`], measuring the whole tree as one transfer with `progress` until it is done or `cancel` fires.
+///
+/// A cancelled upload is cancelled on the server with [`cancel_transfer`], whether it stopped in the middle of a file, which the server then removes, or between two files; the files already stored are kept, and the connection is left ready for the next request.
+///
+/// # Returns
+/// The server's `OK` reply for each file, in upload order, a `Cancelled` [`RequestError`] if it was cancelled, or an error if the tree cannot be read, the server rejects the manifest or a file, or any I/O operation fails.
+pub async fn put_dir_with_progress(
+    stream: &mut FramedStream,
+    local_dir: &Path,
+    remote_root: &str,
+    progress: &ProgressTracker,
+    cancel: &CancellationToken,
+) -> Result<Vec<OkReply>, Box<dyn Error>> {
+    tokio::select! {
+        result = send_tree(stream, local_dir, remote_root, progress) => return result,
+        _ = cancel.cancelled() => {}
+    }
+    cancel_transfer(stream).await?;
+    log::info!("Upload of {} cancelled", local_dir.display());
+    Err(Box::new(RequestError::cancelled()))
+}
+
+async fn send_tree(
+    stream: &mut FramedStream,
+    local_dir: &Path,
+    remote_root: &str,
+    progress: &ProgressTracker,
+) -> Result<Vec<OkReply>, Box<dyn Error>> {
+    let manifest = Manifest::scan(local_dir, remote_root)?;
+    log::info!(
+        "Sending {} as {} files ({} bytes)",
+        local_dir.display(),
+        manifest.files().count(),
+        manifest.total_size()
+    );
+    stream
+        .send(Frame::json(Opcode::Manifest, &manifest)?)
+        .await?;
+    let reply = next_frame(stream).await?;
+    reject_error(&reply)?;
+    if reply.opcode != Opcode::Ok {
+        return Err(format!("Expected OK frame, got {}", reply.opcode).into());
+    }
+
+    progress.start(0, manifest.total_size());
+    let mut replies = Vec::new();
+    for entry in manifest.files() {
+        let local_path = entry.local_path(local_dir);
+        let remote_path = manifest.remote_path(entry);
+        replies.push(send_file(stream, &local_path, &remote_path, false, progress, true).await?);
+    }
+    Ok(replies)
+}
+
+/// Sends the file at `local_path` to `remote_path`, measuring it with `progress` as it is read for sending.
+///
+/// `progress` is started with the file once the server says where it resumes, unless `in_tree` is set: then the caller started it for the whole tree the file belongs to, and the file only counts towards it.
+async fn send_file(
+    stream: &mut FramedStream,
+    local_path: &Path,
+    remote_path: &str,
+    resume: bool,
+    progress: &ProgressTracker,
+    in_tree: bool,
+) -> Result<OkReply, Box<dyn Error>> {
+    let mut file = File::open(local_path).await?;
+    log::info!("File opened: {}", local_path.display());
+
+    let metadata = file.metadata().await?;
+    let file_size = metadata.len();
+    let digest = digest_reader(&mut file, HashAlgorithm::Blake3).await?;
+    file.seek(SeekFrom::Start(0)).await?;
+    let request = PutRequest {
+        path: remote_path.to_string(),
+        size: file_size,
+        resume,
+        digest: Some(digest),
+        mode: mode_of(&metadata),
+        mtime: mtime_of(&metadata),
+        compression: Compression::for_path(local_path, file_size),
+    };
+    stream.send(Frame::json(Opcode::Put, &request)?).await?;
+
+    let ready = next_frame(stream).await?;
+    reject_error(&ready)?;
+    if ready.opcode != Opcode::Ready {
+        return Err(format!("Expected READY frame, got {}", ready.opcode).into());
+    }
+    let ready: ReadyReply = if ready.payload.is_empty() {
+        ReadyReply::default()
+    } else {
+        ready.parse()?
+    };
+    if ready.offset > file_size {
+        return Err(format!(
+            "Server holds {} bytes, more than the {} byte file",
+            ready.offset, file_size
+        )
+        .into());
+    }
+    if ready.offset > 0 {
+        log::info!("Resuming upload at byte {}", ready.offset);
+        file.seek(SeekFrom::Start(ready.offset)).await?;
+    }
+
+    if !in_tree {
+        progress.start(ready.offset, file_size);
+    }
+    let stats = send_encoded_body(
+        stream,
+        &mut progress.reader(&mut file),
+        file_size - ready.offset,
+        ready.compression,
+    )
+    .await?;
+    log::info!("Upload complete: {} sent", stats);
+
+    let reply = next_frame(stream).await?;
+    reject_error(&reply)?;
+    if reply.opcode != Opcode::Ok {
+        return Err(format!("Expected OK frame, got {}", reply.opcode).into());
+    }
+    Ok(reply.parse()?)
+}
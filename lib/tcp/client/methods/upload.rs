@@ -1,15 +1,75 @@
-use reqwest::Client;
+use futures_util::StreamExt;
+use reqwest::header::{CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE};
+use reqwest::{Body, Client, StatusCode};
 use std::error::Error;
+use std::io::SeekFrom;
 use std::path::Path;
-use tokio::fs;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tcp_server::protocol::compression::{Compression, TransferStats, encode_reader};
+use tcp_server::protocol::digest::{DIGEST_HEADER, Digest, HashAlgorithm, digest_reader};
+use tcp_server::protocol::error::RequestError;
+use tcp_server::protocol::manifest::{Manifest, mode_of, mtime_of};
+use tcp_server::protocol::messages::UploadStatus;
+use tcp_server::protocol::progress::ProgressTracker;
+use tcp_server::protocol::transfer::CHUNK_SIZE;
+use tokio::fs::File;
+use tokio::io::AsyncSeekExt;
+use tokio_util::io::ReaderStream;
+use tokio_util::sync::CancellationToken;
 
-pub async fn upload(file_path: &str, url: &str) -> Result<(), Box<dyn Error>> {
+/// Uploads the file at `file_path` to the HTTP upload route at `url` through `client`, which carries the TLS settings for `https` URLs.
+///
+/// The file is streamed from disk in chunks with its size sent as `Content-Length`, so the whole file is never held in memory. Its BLAKE3 digest is computed first and sent in the digest header for the server to verify.
+///
+/// Unless the file is small or its type is already compressed, it is compressed with zstd on the fly and sent with `Content-Encoding: zstd`, its size moving to the query string.
+///
+/// # Returns
+/// The digest the server verified and how many bytes the body took on the wire.
+///
+/// # Errors
+/// Returns an error if the path is empty, the file cannot be opened or is empty, the request fails, or the server answers with a non-success status, including a digest mismatch.
+pub async fn upload(
+    client: &Client,
+    file_path: &str,
+    url: &str,
+) -> Result<(Digest, TransferStats), Box<dyn Error>> {
+    send_whole_file(client, file_path, url, &ProgressTracker::disabled()).await
+}
+
+/// Uploads the file at `file_path` like [`upload`], measuring it with `progress` as it is read for sending until it is done or `cancel` fires.
+///
+/// A cancelled upload drops its request, so the server discards what it received.
+///
+/// # Returns
+/// The digest the server verified and how many bytes the body took on the wire.
+///
+/// # Errors
+/// Returns a `Cancelled` [`RequestError`] if the upload was cancelled, or an error if the path is empty, the file cannot be opened or is empty, the request fails, or the server answers with a non-success status, including a digest mismatch.
+pub async fn upload_with_progress(
+    client: &Client,
+    file_path: &str,
+    url: &str,
+    progress: &ProgressTracker,
+    cancel: &CancellationToken,
+) -> Result<(Digest, TransferStats), Box<dyn Error>> {
+    tokio::select! {
+        result = send_whole_file(client, file_path, url, progress) => result,
+        _ = cancel.cancelled() => Err(Box::new(RequestError::cancelled())),
+    }
+}
+
+async fn send_whole_file(
+    client: &Client,
+    file_path: &str,
+    url: &str,
+    progress: &ProgressTracker,
+) -> Result<(Digest, TransferStats), Box<dyn Error>> {
     if file_path.trim().is_empty() {
         return Err("Provided file path is empty".into());
     }
-
-    let file_bytes = fs::read(file_path).await?;
-    if file_bytes.is_empty() {
+    let size = tokio::fs::metadata(file_path).await?.len();
+    if size == 0 {
         return Err(format!("File '{}' is empty", file_path).into());
     }
 
@@ -17,15 +77,266 @@ pub async fn upload(file_path: &str, url: &str) -> Result<(), Box<dyn Error>> {
         .file_name()
         .and_then(|os_str| os_str.to_str())
         .unwrap_or(file_path);
+    progress.start(0, size);
+    send_file(client, Path::new(file_path), filename, url, progress).await
+}
+
+/// Uploads the local directory `dir_path` and everything below it through the HTTP upload route at `url`, storing it under the directory's name.
+///
+/// Walks the tree and posts it as a JSON manifest to `{url}/manifest` first, so the server can create the directories and reject a tree that breaks its limits before any data is sent. Each file then follows as an upload to its place in the tree, carrying its digest, mode and modification time, and compressed like an [`upload`]. Symlinks are skipped.
+///
+/// # Returns
+/// The digest the server verified for each file and how many bytes it took on the wire, in upload order.
+///
+/// # Errors
+/// Returns an error if the tree cannot be read, any request fails, or the server answers with a non-success status.
+pub async fn upload_dir(
+    client: &Client,
+    dir_path: &str,
+    url: &str,
+) -> Result<Vec<(Digest, TransferStats)>, Box<dyn Error>> {
+    send_tree(client, dir_path, url, &ProgressTracker::disabled()).await
+}
 
-    let request_url = reqwest::Url::parse_with_params(url, &[("path", filename)])?;
+/// Uploads the local directory `dir_path` like [`upload_dir`], measuring the whole tree as one transfer with `progress` until it is done or `cancel` fires.
+///
+/// A cancelled upload drops the request of the file in flight, so the server discards what it received of it; the files already stored are kept.
+///
+/// # Returns
+/// The digest the server verified for each file and how many bytes it took on the wire, in upload order.
+///
+/// # Errors
+/// Returns a `Cancelled` [`RequestError`] if the upload was cancelled, or an error if the tree cannot be read, any request fails, or the server answers with a non-success status.
+pub async fn upload_dir_with_progress(
+    client: &Client,
+    dir_path: &str,
+    url: &str,
+    progress: &ProgressTracker,
+    cancel: &CancellationToken,
+) -> Result<Vec<(Digest, TransferStats)>, Box<dyn Error>> {
+    tokio::select! {
+        result = send_tree(client, dir_path, url, progress) => result,
+        _ = cancel.cancelled() => Err(Box::new(RequestError::cancelled())),
+    }
+}
 
-    let client = Client::new();
-    let response = client.post(request_url).body(file_bytes).send().await?;
+async fn send_tree(
+    client: &Client,
+    dir_path: &str,
+    url: &str,
+    progress: &ProgressTracker,
+) -> Result<Vec<(Digest, TransferStats)>, Box<dyn Error>> {
+    let dir = Path::new(dir_path);
+    let root = dir
+        .file_name()
+        .and_then(|os_str| os_str.to_str())
+        .ok_or_else(|| format!("'{}' does not name a directory", dir_path))?;
+    let manifest = Manifest::scan(dir, root)?;
 
+    let response = client
+        .post(format!("{}/manifest", url.trim_end_matches('/')))
+        .header(CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(&manifest)?)
+        .send()
+        .await?;
     if !response.status().is_success() {
-        return Err(format!("HTTP request failed with status: {}", response.status()).into());
+        let status = response.status();
+        let message = response.text().await.unwrap_or_default();
+        return Err(format!("HTTP request failed with status: {} {}", status, message).into());
+    }
+
+    progress.start(0, manifest.total_size());
+    let mut uploaded = Vec::new();
+    for entry in manifest.files() {
+        let local_path = entry.local_path(dir);
+        let remote_path = manifest.remote_path(entry);
+        uploaded.push(send_file(client, &local_path, &remote_path, url, progress).await?);
+    }
+    Ok(uploaded)
+}
+
+/// Streams the file at `local_path` to `remote_path` through the upload route at `url`, with its digest, mode and modification time, compressing it where worthwhile.
+///
+/// The file's bytes count towards `progress` as they are read, which the caller has started.
+async fn send_file(
+    client: &Client,
+    local_path: &Path,
+    remote_path: &str,
+    url: &str,
+    progress: &ProgressTracker,
+) -> Result<(Digest, TransferStats), Box<dyn Error>> {
+    let mut file = File::open(local_path).await?;
+    let metadata = file.metadata().await?;
+    let size = metadata.len();
+    let digest = digest_reader(&mut file, HashAlgorithm::Blake3).await?;
+    file.seek(SeekFrom::Start(0)).await?;
+
+    let mut params = vec![("path", remote_path.to_string())];
+    if let Some(mode) = mode_of(&metadata) {
+        params.push(("mode", mode.to_string()));
+    }
+    if let Some(mtime) = mtime_of(&metadata) {
+        params.push(("mtime", mtime.to_string()));
+    }
+    let compression = Compression::for_path(local_path, size);
+    if compression.is_compressed() {
+        params.push(("size", size.to_string()));
     }
+    let request_url = reqwest::Url::parse_with_params(url, &params)?;
 
-    Ok(())
+    let transferred = Arc::new(AtomicU64::new(0));
+    let counter = transferred.clone();
+    let reader = encode_reader(progress.reader(file), compression);
+    let chunks = ReaderStream::with_capacity(reader, CHUNK_SIZE).inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            counter.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        }
+    });
+    let request = client
+        .post(request_url)
+        .header(DIGEST_HEADER, digest.to_string())
+        .body(Body::wrap_stream(chunks));
+    let request = if compression.is_compressed() {
+        request.header(CONTENT_ENCODING, compression.to_string())
+    } else {
+        request.header(CONTENT_LENGTH, size)
+    };
+    let response = request.send().await?;
+
+    if !response.status().is_success() {
+        let status = response.status();
+        let message = response.text().await.unwrap_or_default();
+        return Err(format!("HTTP request failed with status: {} {}", status, message).into());
+    }
+
+    let stats = TransferStats {
+        original: size,
+        transferred: transferred.load(Ordering::Relaxed),
+        compression,
+    };
+    Ok((digest, stats))
+}
+
+/// Uploads the file at `file_path` like [`upload`], continuing from whatever an earlier, interrupted upload left on the server.
+///
+/// Asks the upload route how many bytes the server already holds, then streams only the rest of the file with that offset, so a dropped connection only costs the bytes that were in flight, and the server keeps what arrived for the next attempt. The digest of the whole file is sent along and checked by the server once the file is complete. The rest is compressed like an [`upload`] where worthwhile; the server counts the bytes it decoded, so a body cut short still leaves it with a known number of the file's bytes.
+///
+/// # Returns
+/// The digest the server verified.
+///
+/// # Errors
+/// Returns an error if the path is empty, the file cannot be read, either request fails, or the server answers with a non-success status.
+pub async fn resume_upload(
+    client: &Client,
+    file_path: &str,
+    url: &str,
+) -> Result<Digest, Box<dyn Error>> {
+    let (digest, _) = send_rest(client, file_path, url, &ProgressTracker::disabled()).await?;
+    Ok(digest)
+}
+
+/// Uploads the file at `file_path` like [`resume_upload`], measuring it with `progress`, which counts the bytes the server already holds as done, until it is done or `cancel` fires.
+///
+/// A cancelled upload drops its request; the server keeps what it received, for a later resume.
+///
+/// # Returns
+/// The digest the server verified and how many bytes the rest of the file took on the wire.
+///
+/// # Errors
+/// Returns a `Cancelled` [`RequestError`] if the upload was cancelled, or an error if the path is empty, the file cannot be read, either request fails, or the server answers with a non-success status.
+pub async fn resume_upload_with_progress(
+    client: &Client,
+    file_path: &str,
+    url: &str,
+    progress: &ProgressTracker,
+    cancel: &CancellationToken,
+) -> Result<(Digest, TransferStats), Box<dyn Error>> {
+    tokio::select! {
+        result = send_rest(client, file_path, url, progress) => result,
+        _ = cancel.cancelled() => Err(Box::new(RequestError::cancelled())),
+    }
+}
+
+async fn send_rest(
+    client: &Client,
+    file_path: &str,
+    url: &str,
+    progress: &ProgressTracker,
+) -> Result<(Digest, TransferStats), Box<dyn Error>> {
+    if file_path.trim().is_empty() {
+        return Err("Provided file path is empty".into());
+    }
+
+    let mut file = File::open(file_path).await?;
+    let size = file.metadata().await?.len();
+    let digest = digest_reader(&mut file, HashAlgorithm::Blake3).await?;
+    file.seek(SeekFrom::Start(0)).await?;
+    let filename = Path::new(file_path)
+        .file_name()
+        .and_then(|os_str| os_str.to_str())
+        .unwrap_or(file_path);
+
+    let status_url = reqwest::Url::parse_with_params(url, &[("path", filename)])?;
+    let response = client.get(status_url).send().await?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Upload status request failed with status: {}",
+            response.status()
+        )
+        .into());
+    }
+    let status: UploadStatus = serde_json::from_slice(&response.bytes().await?)?;
+    let offset = if status.offset > size {
+        0
+    } else {
+        status.offset
+    };
+    if offset > 0 {
+        log::info!("Resuming upload of {} at byte {}", filename, offset);
+        file.seek(SeekFrom::Start(offset)).await?;
+    }
+
+    let request_url = reqwest::Url::parse_with_params(
+        url,
+        &[
+            ("path", filename.to_string()),
+            ("offset", offset.to_string()),
+            ("size", size.to_string()),
+        ],
+    )?;
+    let compression = Compression::for_path(Path::new(file_path), size - offset);
+    let transferred = Arc::new(AtomicU64::new(0));
+    let counter = transferred.clone();
+    progress.start(offset, size);
+    let reader = encode_reader(progress.reader(file), compression);
+    let chunks = ReaderStream::with_capacity(reader, CHUNK_SIZE).inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            counter.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+        }
+    });
+    let request = client
+        .post(request_url)
+        .header(DIGEST_HEADER, digest.to_string())
+        .body(Body::wrap_stream(chunks));
+    let request = if compression.is_compressed() {
+        request.header(CONTENT_ENCODING, compression.to_string())
+    } else {
+        request.header(CONTENT_LENGTH, size - offset)
+    };
+    let response = request.send().await?;
+
+    match response.status() {
+        StatusCode::OK => {
+            let stats = TransferStats {
+                original: size - offset,
+                transferred: transferred.load(Ordering::Relaxed),
+                compression,
+            };
+            Ok((digest, stats))
+        }
+        status => {
+            let message = response.text().await.unwrap_or_default();
+            Err(format!("HTTP request failed with status: {} {}", status, message).into())
+        }
+    }
 }
@@ -1,16 +1,149 @@
 use once_cell::sync::Lazy;
 use std::error::Error;
+use tcp_server::protocol::codec::{FrameCodec, FramedStream};
+use tcp_server::protocol::messages::{HttpEndpoint, Scheme, WelcomeReply};
+use tcp_server::tcp::tcp::DEFAULT_PORT;
 use tokio::{net::TcpListener, net::TcpStream};
 use whoami::username;
 
+use crate::methods::hello::hello;
+use crate::methods::pair::pair;
+use crate::tls::tls::{self, TlsSettings};
+
 pub static USER: Lazy<String> = Lazy::new(|| username().to_string());
-pub fn connect_sync(address: &str) -> Result<TcpStream, Box<dyn Error>> {
-    let rt = tokio::runtime::Runtime::new()?;
-    rt.block_on(async {
-        let stream = TcpStream::connect(address).await?;
-        Ok(stream)
+
+/// Where a zync server is reached: its TCP listener on `host:tcp_port` and, if it serves them over HTTPS, its HTTP routes on `scheme://host:http_port`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Endpoint {
+    pub host: String,
+    pub tcp_port: u16,
+    pub http_port: Option<u16>,
+    /// `Https` when the server is reached over TLS, its TCP connection wrapped in TLS and its HTTP routes spoken to over HTTPS; `Http` when it is reached in the clear, in which case its HTTP routes are never used, since session tokens are only sent over HTTPS.
+    pub scheme: Scheme,
+}
+
+impl Endpoint {
+    /// Parses `address`, a host with an optional `:port` that defaults to [`DEFAULT_PORT`]; IPv6 hosts given with a port are written in brackets. An `https://` or `http://` prefix picks whether the server is reached over TLS, otherwise `default_scheme` does.
+    ///
+    /// Where the server's HTTP routes are is only learned on [`connect`].
+    ///
+    /// # Errors
+    /// Returns an error if the scheme is not `http` or `https`, the host is missing or the port is not a number.
+    pub fn parse(address: &str, default_scheme: Scheme) -> Result<Self, Box<dyn Error>> {
+        let address = address.trim().trim_end_matches('/');
+        let (scheme, address) = match address.split_once("://") {
+            Some(("https", rest)) => (Scheme::Https, rest),
+            Some(("http", rest)) => (Scheme::Http, rest),
+            Some((scheme, _)) => return Err(format!("Unsupported scheme {}", scheme).into()),
+            None => (default_scheme, address),
+        };
+        let (host, port) = match address.rsplit_once(':') {
+            Some((host, port)) if !host.contains(':') || host.ends_with(']') => {
+                let port = port
+                    .parse::<u16>()
+                    .map_err(|_| format!("Invalid port in {}", address))?;
+                (host, port)
+            }
+            _ => (address, DEFAULT_PORT),
+        };
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(format!("Missing host in {}", address).into());
+        }
+        Ok(Endpoint {
+            host: host.to_string(),
+            tcp_port: port,
+            http_port: None,
+            scheme,
+        })
+    }
+
+    /// The `host:port` address of the server's TCP listener.
+    pub fn address(&self) -> String {
+        self.with_port(self.tcp_port)
+    }
+
+    /// The URL of the server's HTTP `route`, such as `upload`, or `None` if the server does not serve its routes over HTTPS.
+    pub fn url(&self, route: &str) -> Option<String> {
+        self.http_port.map(|port| {
+            format!(
+                "{}://{}/{}",
+                self.scheme,
+                self.with_port(port),
+                route.trim_start_matches('/')
+            )
+        })
+    }
+
+    /// Points the endpoint at the HTTP routes a server reported while pairing, `http`, as long as both they and the endpoint use HTTPS. A server reached over HTTPS that reports none is assumed to serve them on its TCP port.
+    fn negotiate_http(&mut self, http: Option<HttpEndpoint>) {
+        self.http_port = match (self.scheme, http) {
+            (Scheme::Http, _) => None,
+            (Scheme::Https, Some(http)) if http.scheme == Scheme::Https => Some(http.port),
+            (Scheme::Https, Some(_)) => None,
+            (Scheme::Https, None) => Some(self.tcp_port),
+        };
+    }
+
+    fn with_port(&self, port: u16) -> String {
+        if self.host.contains(':') {
+            format!("[{}]:{}", self.host, port)
+        } else {
+            format!("{}:{}", self.host, port)
+        }
+    }
+}
+
+/// An open, paired connection to a zync server.
+pub struct Connection {
+    pub stream: FramedStream,
+    /// Over TLS, the fingerprint of the certificate the server presented.
+    pub fingerprint: Option<String>,
+    /// The session token that authorizes HTTP requests to the same server.
+    pub session: String,
+    /// Where the server is reached, with its HTTP routes as it reported them while pairing.
+    pub endpoint: Endpoint,
+    /// What the server said about itself in reply to the `HELLO` the connection opened with, with the free space it only tells paired clients.
+    pub server: WelcomeReply,
+}
+
+/// Connects to the zync server at `endpoint`, wrapping the connection in TLS when `tls` is given, exchanges `HELLO`/`WELCOME` with it and pairs with it using the `code` it displays, then asks again for the free space it withholds before pairing.
+///
+/// The returned connection's endpoint has its scheme set to match `tls` and points at the HTTP routes the server reported while pairing, as long as they are served over HTTPS; session tokens are never sent over plain HTTP. Over `tls`, a server that reports none is assumed to serve them on its TCP port.
+///
+/// # Errors
+/// Returns an error if the server cannot be reached, the TLS handshake fails, including when the server's certificate does not match the pinned fingerprint, or the pairing code is wrong.
+pub async fn connect(
+    mut endpoint: Endpoint,
+    tls: Option<&TlsSettings>,
+    code: &str,
+) -> Result<Connection, Box<dyn Error>> {
+    let address = endpoint.address();
+    let stream = TcpStream::connect(&address).await?;
+    let (mut stream, fingerprint) = match tls {
+        Some(settings) => {
+            let (stream, fingerprint) = tls::connect(stream, &address, settings).await?;
+            (FrameCodec::framed(stream), Some(fingerprint))
+        }
+        None => (FrameCodec::framed(stream), None),
+    };
+    let mut server = hello(&mut stream).await?;
+    let paired = pair(&mut stream, code, fingerprint.as_deref()).await?;
+    server.free_space = hello(&mut stream).await?.free_space;
+    endpoint.scheme = match tls {
+        Some(_) => Scheme::Https,
+        None => Scheme::Http,
+    };
+    endpoint.negotiate_http(paired.http);
+    Ok(Connection {
+        stream,
+        fingerprint,
+        session: paired.session,
+        endpoint,
+        server,
     })
 }
+
 /// Asynchronously binds a TCP listener to port 4239 on all network interfaces.
 ///
 /// # Returns
@@ -106,3 +239,117 @@ pub async fn listen() -> Result<TcpListener, Box<dyn std::error::Error + Send>>
 //     }
 //     Ok(())
 // }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoint(address: &str) -> Endpoint {
+        Endpoint::parse(address, Scheme::Https).unwrap()
+    }
+
+    #[test]
+    fn parses_hosts_and_ports() {
+        let parsed = endpoint(" 10.0.0.2:4000 ");
+        assert_eq!(parsed.host, "10.0.0.2");
+        assert_eq!(parsed.tcp_port, 4000);
+        assert_eq!(parsed.http_port, None);
+        assert_eq!(endpoint("example.com").tcp_port, DEFAULT_PORT);
+        assert_eq!(
+            endpoint("example.com").address(),
+            format!("example.com:{DEFAULT_PORT}")
+        );
+    }
+
+    #[test]
+    fn parses_ipv6_hosts() {
+        assert_eq!(endpoint("[::1]:7").host, "::1");
+        assert_eq!(endpoint("[::1]:7").tcp_port, 7);
+        assert_eq!(endpoint("[::1]:7").address(), "[::1]:7");
+        assert_eq!(endpoint("::1").host, "::1");
+        assert_eq!(endpoint("::1").tcp_port, DEFAULT_PORT);
+        assert_eq!(endpoint("[::1]").host, "::1");
+        assert_eq!(endpoint("[::1]").tcp_port, DEFAULT_PORT);
+    }
+
+    #[test]
+    fn parses_schemes() {
+        assert_eq!(endpoint("host:9").scheme, Scheme::Https);
+        assert_eq!(
+            Endpoint::parse("host:9", Scheme::Http).unwrap().scheme,
+            Scheme::Http
+        );
+        let parsed = Endpoint::parse("https://host:9/", Scheme::Http).unwrap();
+        assert_eq!(parsed.scheme, Scheme::Https);
+        assert_eq!(parsed.address(), "host:9");
+        let parsed = endpoint("http://[::1]:7");
+        assert_eq!(parsed.scheme, Scheme::Http);
+        assert_eq!(parsed.host, "::1");
+        assert_eq!(parsed.tcp_port, 7);
+        assert_eq!(endpoint("http://host").tcp_port, DEFAULT_PORT);
+    }
+
+    #[test]
+    fn rejects_missing_hosts_and_bad_ports() {
+        for address in [
+            "",
+            ":5",
+            "[]:5",
+            "host:x",
+            "host:70000",
+            "host:",
+            "https://",
+            "ftp://host:5",
+        ] {
+            assert!(
+                Endpoint::parse(address, Scheme::Https).is_err(),
+                "{address:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn urls_need_http_routes_and_follow_the_scheme() {
+        let mut parsed = endpoint("host:9");
+        assert_eq!(parsed.url("upload"), None);
+        parsed.http_port = Some(8443);
+        assert_eq!(parsed.url("upload").unwrap(), "https://host:8443/upload");
+        assert_eq!(
+            parsed.url("/pair/confirm").unwrap(),
+            "https://host:8443/pair/confirm"
+        );
+        let mut ipv6 = endpoint("[::1]:9");
+        ipv6.http_port = Some(9);
+        assert_eq!(ipv6.url("upload").unwrap(), "https://[::1]:9/upload");
+        ipv6.scheme = Scheme::Http;
+        assert_eq!(ipv6.url("upload").unwrap(), "http://[::1]:9/upload");
+    }
+
+    #[test]
+    fn negotiates_only_https_routes() {
+        let https = HttpEndpoint {
+            port: 8443,
+            scheme: Scheme::Https,
+        };
+        let http = HttpEndpoint {
+            port: 8080,
+            scheme: Scheme::Http,
+        };
+        let negotiated = |reported: Option<HttpEndpoint>, tls: bool| {
+            let mut parsed = endpoint(if tls {
+                "https://host:9"
+            } else {
+                "http://host:9"
+            });
+            parsed.negotiate_http(reported);
+            parsed.http_port
+        };
+
+        assert_eq!(negotiated(Some(https), true), Some(8443));
+        assert_eq!(negotiated(Some(https), false), None);
+        assert_eq!(negotiated(Some(http), true), None);
+        assert_eq!(negotiated(Some(http), false), None);
+        assert_eq!(negotiated(None, true), Some(9));
+        assert_eq!(negotiated(None, false), None);
+    }
+}
@@ -6,3 +6,6 @@ pub mod methods;
 
 #[path = "../utils/mod.rs"]
 pub mod utils;
+
+#[path = "../tls/mod.rs"]
+pub mod tls;
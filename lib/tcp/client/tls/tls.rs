@@ -0,0 +1,175 @@
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+
+use reqwest::Client;
+use reqwest::header::{HeaderMap, HeaderValue};
+use rustls::ClientConfig;
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{DigitallySignedStruct, SignatureScheme};
+use tcp_server::pairing::pairing::SESSION_HEADER;
+use tcp_server::tls::tls::{crypto_provider, fingerprint};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::client::TlsStream;
+
+/// How a client authenticates a zync server over TLS.
+///
+/// zync servers present self-signed certificates, so instead of a certificate authority the client trusts a SHA-256 fingerprint: the one pinned for the server, or on first use whatever the server presents.
+#[derive(Debug, Clone, Default)]
+pub struct TlsSettings {
+    /// The fingerprint the server must present, or `None` to accept and report any certificate.
+    pub pinned: Option<String>,
+}
+
+impl TlsSettings {
+    pub fn pinned(fingerprint: impl Into<String>) -> Self {
+        Self {
+            pinned: Some(fingerprint.into()),
+        }
+    }
+
+    fn verifier(&self) -> Arc<PinnedCertVerifier> {
+        Arc::new(PinnedCertVerifier {
+            pinned: self.pinned.clone(),
+            seen: Mutex::new(None),
+            provider: crypto_provider(),
+        })
+    }
+
+    fn client_config(
+        &self,
+        verifier: Arc<PinnedCertVerifier>,
+    ) -> Result<ClientConfig, Box<dyn Error>> {
+        let config = ClientConfig::builder_with_provider(verifier.provider.clone())
+            .with_safe_default_protocol_versions()?
+            .dangerous()
+            .with_custom_certificate_verifier(verifier)
+            .with_no_client_auth();
+        Ok(config)
+    }
+}
+
+/// Accepts a server certificate by its fingerprint and remembers the fingerprint it was shown.
+///
+/// Handshake signatures are still verified against the certificate, so a server can only pass by holding the pinned certificate's private key.
+#[derive(Debug)]
+struct PinnedCertVerifier {
+    pinned: Option<String>,
+    seen: Mutex<Option<String>>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl PinnedCertVerifier {
+    fn seen(&self) -> Option<String> {
+        self.seen.lock().unwrap().clone()
+    }
+}
+
+impl ServerCertVerifier for PinnedCertVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let actual = fingerprint(end_entity.as_ref());
+        *self.seen.lock().unwrap() = Some(actual.clone());
+        match &self.pinned {
+            Some(expected) if !expected.eq_ignore_ascii_case(&actual) => {
+                Err(rustls::Error::General(format!(
+                    "Server certificate fingerprint {actual} does not match the pinned {expected}"
+                )))
+            }
+            _ => Ok(ServerCertVerified::assertion()),
+        }
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.provider.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+/// Performs a TLS handshake over `stream` with the server at `address`.
+///
+/// # Returns
+/// The encrypted stream and the fingerprint of the certificate the server presented, so a first connection can pin it.
+///
+/// # Errors
+/// Returns an error if the handshake fails, including when the server's certificate does not match `settings.pinned`.
+pub async fn connect(
+    stream: TcpStream,
+    address: &str,
+    settings: &TlsSettings,
+) -> Result<(TlsStream<TcpStream>, String), Box<dyn Error>> {
+    let verifier = settings.verifier();
+    let connector = TlsConnector::from(Arc::new(settings.client_config(verifier.clone())?));
+    let host = address
+        .rsplit_once(':')
+        .map_or(address, |(host, _)| host)
+        .trim_matches(|c| c == '[' || c == ']');
+    let server_name = ServerName::try_from(host.to_string())?;
+    let stream = connector.connect(server_name, stream).await?;
+    let fingerprint = verifier
+        .seen()
+        .ok_or("Server did not present a certificate")?;
+    Ok((stream, fingerprint))
+}
+
+/// Builds the HTTP client used for uploads and downloads.
+///
+/// With `settings` the client speaks HTTPS to zync servers, authenticating them by fingerprint as [`connect`] does; without, it is a plain client. A `session` token from pairing is sent with every request, which is only allowed over HTTPS since anyone watching plain HTTP could replay it.
+///
+/// # Errors
+/// Returns an error if a `session` is given without `settings`, or if the TLS configuration or the client cannot be built.
+pub fn http_client(
+    settings: Option<&TlsSettings>,
+    session: Option<&str>,
+) -> Result<Client, Box<dyn Error>> {
+    if session.is_some() && settings.is_none() {
+        return Err("Session tokens are only sent over HTTPS".into());
+    }
+    let mut builder = Client::builder();
+    if let Some(settings) = settings {
+        builder = builder.use_preconfigured_tls(settings.client_config(settings.verifier())?);
+    }
+    if let Some(token) = session {
+        let mut headers = HeaderMap::new();
+        headers.insert(SESSION_HEADER, HeaderValue::from_str(token)?);
+        builder = builder.default_headers(headers).https_only(true);
+    }
+    Ok(builder.build()?)
+}
@@ -0,0 +1,38 @@
+use std::error::Error;
+
+use futures_util::SinkExt;
+use log::info;
+
+use crate::protocol::codec::FramedStream;
+use crate::protocol::error::RequestError;
+use crate::protocol::frame::{Frame, Opcode};
+use crate::protocol::messages::{ErrorCode, OkReply, PathRequest};
+use crate::storage::storage::Storage;
+
+/// Handles a `DELETE` request frame by removing the named entry from storage.
+///
+/// Removal goes through [`Storage::delete`], so `..`, absolute paths and symlinked parents escaping storage are rejected. On success, replies with an `OK` frame naming the removed path.
+///
+/// # Errors
+/// Returns a [`RequestError`] if the path is invalid or does not exist, or if removal fails.
+pub async fn delete_file(
+    stream: &mut FramedStream,
+    request: &Frame,
+    storage: &Storage,
+) -> Result<(), Box<dyn Error>> {
+    let request: PathRequest = request
+        .parse()
+        .map_err(|e| RequestError::new(ErrorCode::BadRequest, e.to_string()))?;
+    let path = storage.delete(&request.path).await?;
+    info!("Deleted {:?}", path);
+
+    let reply = OkReply {
+        message: format!("Deleted {}", request.path),
+        path: request.path,
+        digest: None,
+        stats: None,
+    };
+    stream.send(Frame::json(Opcode::Ok, &reply)?).await?;
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+use std::convert::Infallible;
+use std::io::SeekFrom;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+use warp::Filter;
+use warp::Reply;
+use warp::http::Response;
+use warp::hyper::Body;
+
+use super::put::error_response;
+use crate::protocol::digest::{DIGEST_HEADER, HashAlgorithm, digest_reader};
+use crate::protocol::error::RequestError;
+use crate::protocol::transfer::CHUNK_SIZE;
+use crate::storage::storage::Storage;
+
+#[derive(Debug, serde::Deserialize)]
+pub struct DownloadQuery {
+    pub path: String,
+}
+
+/// The part of a file a `Range` header asks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ByteRange {
+    /// No usable `Range` header; the whole file is sent.
+    Full,
+    /// The inclusive byte range `start..=end`.
+    Partial { start: u64, end: u64 },
+    /// The range lies outside of the file.
+    Unsatisfiable,
+}
+
+/// Handles `GET /download`, streaming a file out of storage.
+///
+/// A single `Range: bytes=...` header is honoured with `206 Partial Content`, so a client can resume an interrupted download from the bytes it already has. Multiple ranges are not supported and fall back to the whole file.
+///
+/// Every response carries the BLAKE3 digest of the full file in the [`DIGEST_HEADER`], so the client can verify what it assembled.
+pub async fn download(
+    query: DownloadQuery,
+    range: Option<String>,
+    storage: Storage,
+) -> Result<impl Reply, Infallible> {
+    let (mut file, size) = match storage.open_read(&query.path).await {
+        Ok(opened) => opened,
+        Err(e) => return Ok(error_response(&e)),
+    };
+
+    let digest = match digest_reader(&mut file, HashAlgorithm::Blake3).await {
+        Ok(digest) => digest,
+        Err(e) => return Ok(error_response(&RequestError::from(&e))),
+    };
+    let (start, end) = match parse_range(range.as_deref(), size) {
+        ByteRange::Full => (0, size.saturating_sub(1)),
+        ByteRange::Partial { start, end } => (start, end),
+        ByteRange::Unsatisfiable => {
+            return Ok(Response::builder()
+                .status(416)
+                .header("content-range", format!("bytes */{size}"))
+                .header(DIGEST_HEADER, digest.to_string())
+                .body(Body::empty())
+                .unwrap());
+        }
+    };
+    let length = if size == 0 { 0 } else { end - start + 1 };
+
+    if let Err(e) = file.seek(SeekFrom::Start(start)).await {
+        return Ok(error_response(&RequestError::from(&e)));
+    }
+    log::info!(
+        "Serving {:?} bytes {}..{} of {}",
+        query.path,
+        start,
+        start + length,
+        size
+    );
+
+    let body = Body::wrap_stream(ReaderStream::with_capacity(file.take(length), CHUNK_SIZE));
+    let builder = Response::builder()
+        .header("accept-ranges", "bytes")
+        .header(DIGEST_HEADER, digest.to_string())
+        .header("content-length", length);
+    let builder = if range.is_some() && length != size {
+        builder
+            .status(206)
+            .header("content-range", format!("bytes {start}-{end}/{size}"))
+    } else {
+        builder.status(200)
+    };
+    Ok(builder.body(body).unwrap())
+}
+
+/// Parses a `Range` header against a file of `size` bytes.
+fn parse_range(header: Option<&str>, size: u64) -> ByteRange {
+    let Some(spec) = header.and_then(|value| value.trim().strip_prefix("bytes=")) else {
+        return ByteRange::Full;
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
+    }
+    let Some((start, end)) = spec.trim().split_once('-') else {
+        return ByteRange::Full;
+    };
+
+    let (start, end) = match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Ok(start), Ok(end)) => (start, end.min(size.saturating_sub(1))),
+        (Ok(start), Err(_)) if end.is_empty() => (start, size.saturating_sub(1)),
+        (Err(_), Ok(suffix)) if start.is_empty() => {
+            if suffix == 0 {
+                return ByteRange::Unsatisfiable;
+            }
+            (size.saturating_sub(suffix), size.saturating_sub(1))
+        }
+        _ => return ByteRange::Full,
+    };
+    if start >= size || start > end {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Partial { start, end }
+}
+
+/// The `/download` route, serving each request from the storage `storage` extracts for it.
+pub fn router<F>(
+    storage: F,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+where
+    F: Filter<Extract = (Storage,), Error = warp::Rejection> + Clone + Send + Sync + 'static,
+{
+    warp::path("download")
+        .and(warp::get())
+        .and(warp::query::<DownloadQuery>())
+        .and(warp::header::optional::<String>("range"))
+        .and(storage)
+        .and_then(download)
+}
@@ -1,55 +1,72 @@
-use std::{error::Error, path::Path};
+use std::error::Error;
+use std::io::SeekFrom;
 
+use futures_util::SinkExt;
 use log::info;
-use tokio::{
-    fs::File,
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
-};
+use tokio::io::AsyncSeekExt;
 
-/// Handles a file transfer request over a TCP stream by sending the specified file's contents to the client.
+use crate::protocol::codec::FramedStream;
+use crate::protocol::digest::digest_reader;
+use crate::protocol::error::RequestError;
+use crate::protocol::frame::{Frame, Opcode};
+use crate::protocol::messages::{ErrorCode, GetRequest, SendHeader};
+use crate::protocol::progress::ProgressTracker;
+use crate::protocol::transfer::send_encoded_body;
+use crate::storage::storage::Storage;
+
+/// Handles a `GET` request frame by sending the specified file's contents to the client.
+///
+/// The request payload names the file path relative to the storage root; it is opened with [`Storage::open_read`], so paths escaping storage are rejected. On success, sends a `SEND` frame with the file path, size, starting offset and the digest of the full file in the requested algorithm, then streams the file contents from that offset as `DATA` frames over the connection, logging its progress every few seconds.
 ///
-/// Expects a command in the buffer with exactly three whitespace-separated parts, where the second part is the file path relative to the "storage/" directory. If the command is invalid, sends an error message to the client and returns an error. On success, sends a "SEND" header with the file path and size, then streams the file contents in chunks over the TCP connection.
+/// If the client accepts compression, the body is compressed with zstd unless the file is small or its type is already compressed; the `SEND` header tells the client which it was.
 ///
 /// # Errors
-/// Returns an error if the command format is invalid, the file cannot be opened, or any I/O operation fails during the transfer.
-pub async fn get_file(stream: &mut TcpStream, buffer: &mut [u8]) -> Result<(), Box<dyn Error>> {
+/// Returns an error if the request payload is invalid, the offset lies beyond the end of the file, the file cannot be opened, or any I/O operation fails during the transfer.
+pub async fn get_file(
+    stream: &mut FramedStream,
+    request: &Frame,
+    storage: &Storage,
+) -> Result<(), Box<dyn Error>> {
     info!("Getting file..");
-    let buf_string = String::from_utf8_lossy(buffer)
-        .trim_matches(char::from(0))
-        .trim()
-        .to_string();
-    let parts: Vec<&str> = buf_string.split_whitespace().collect();
-
-    if parts.len() != 3 {
-        stream.write_all(b"Invalid Command").await?;
-        return Err("Invalid command".into());
-    }
-    let raw_path = parts[1];
-    let format_path = format!("storage/{raw_path}");
-    let path = Path::new(&format_path);
-    let mut file = File::open(&path).await?;
-    let file_size = file.metadata().await?.len();
-    log::info!("Sending {format_path:?} ({} bytes)", file_size);
-    stream
-        .write_all(format!("SEND {} {}\n", format_path, file_size).as_bytes())
-        .await?;
-    stream.flush().await?;
-
-    let mut total_sent = 0;
-    loop {
-        let bytes_read = file.read(buffer).await?;
-        if bytes_read == 0 {
-            break;
-        }
-        stream.write_all(&buffer[..bytes_read]).await?;
-        stream.flush().await?;
-
-        total_sent += bytes_read;
-        info!("Progress: {}/{} bytes", total_sent, file_size);
+    let request: GetRequest = request
+        .parse()
+        .map_err(|e| RequestError::new(ErrorCode::BadRequest, e.to_string()))?;
+    let (mut file, file_size) = storage.open_read(&request.path).await?;
+    if request.offset > file_size {
+        return Err(RequestError::new(
+            ErrorCode::BadRequest,
+            format!(
+                "Offset {} is beyond the end of {} ({} bytes)",
+                request.offset, request.path, file_size
+            ),
+        )
+        .into());
     }
+    let digest = digest_reader(&mut file, request.algorithm).await?;
+    file.seek(SeekFrom::Start(request.offset)).await?;
+    log::info!(
+        "Sending {:?} ({} bytes from offset {})",
+        request.path,
+        file_size,
+        request.offset
+    );
+
+    let remaining = file_size - request.offset;
+    let progress = ProgressTracker::logging(format!("Sending {:?}", request.path));
+    let header = SendHeader {
+        compression: request.compression.negotiate(&request.path, remaining),
+        path: request.path,
+        size: file_size,
+        offset: request.offset,
+        digest: Some(digest),
+    };
+    stream.send(Frame::json(Opcode::Send, &header)?).await?;
+
+    progress.start(header.offset, header.size);
+    let mut reader = progress.reader(&mut file);
+    let stats = send_encoded_body(stream, &mut reader, remaining, header.compression).await?;
 
-    info!("Upload complete: {} bytes sent", total_sent);
+    info!("Upload complete: {}", stats);
 
     info!("File sent");
 
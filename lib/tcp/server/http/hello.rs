@@ -0,0 +1,103 @@
+use std::error::Error;
+
+use futures_util::SinkExt;
+use log::info;
+
+use crate::protocol::codec::FramedStream;
+use crate::protocol::error::RequestError;
+use crate::protocol::frame::{Frame, Opcode, PROTOCOL_VERSION, ZYNC_VERSION};
+use crate::protocol::messages::{ErrorCode, Feature, HelloRequest, WelcomeReply};
+use crate::storage::storage::Storage;
+use crate::tcp::allowed_request::AllowedRequest;
+
+/// Handles a `HELLO` request frame, answering with a `WELCOME` frame that describes the server: its name, zync release and protocol version, how much the paired user's `storage` can still take, the requests it handles and its `features`.
+///
+/// Before the connection is paired there is no `storage`, and how much space the server has left is not told to a client that has not proven it knows the pairing code.
+///
+/// # Errors
+/// Returns a `BadRequest` [`RequestError`] if the payload is malformed.
+pub async fn hello(
+    stream: &mut FramedStream,
+    request: &Frame,
+    storage: Option<&Storage>,
+    features: &[Feature],
+) -> Result<(), Box<dyn Error>> {
+    let request: HelloRequest = request
+        .parse()
+        .map_err(|e| RequestError::new(ErrorCode::BadRequest, e.to_string()))?;
+    info!(
+        "Hello from {} running zync {}",
+        request.name, request.version
+    );
+    let reply = WelcomeReply {
+        name: whoami::devicename(),
+        version: ZYNC_VERSION.to_string(),
+        protocol: PROTOCOL_VERSION,
+        free_space: storage.and_then(Storage::free_space),
+        commands: AllowedRequest::ALL
+            .iter()
+            .map(ToString::to_string)
+            .collect(),
+        features: features.to_vec(),
+    };
+    stream.send(Frame::json(Opcode::Welcome, &reply)?).await?;
+    Ok(())
+}
+
+/// The features a TCP server announces: those every server has, plus `tls` and `http` when it serves them.
+pub fn features(tls: bool, http: bool) -> Vec<Feature> {
+    let mut features = vec![Feature::Pairing];
+    if tls {
+        features.push(Feature::Tls);
+    }
+    if http {
+        features.push(Feature::Http);
+    }
+    features.extend([
+        Feature::Resume,
+        Feature::Zstd,
+        Feature::Blake3,
+        Feature::Sha256,
+    ]);
+    features
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::codec::FrameCodec;
+    use futures_util::StreamExt;
+
+    async fn welcome(storage: Option<&Storage>) -> WelcomeReply {
+        let (server, client) = tokio::io::duplex(4096);
+        let mut server = FrameCodec::framed(server);
+        let mut client = FrameCodec::framed(client);
+        let request = HelloRequest {
+            name: "client".to_string(),
+            version: ZYNC_VERSION.to_string(),
+        };
+        let request = Frame::json(Opcode::Hello, &request).unwrap();
+        hello(&mut server, &request, storage, &features(false, false))
+            .await
+            .unwrap();
+        let reply = client.next().await.unwrap().unwrap();
+        assert_eq!(reply.opcode, Opcode::Welcome);
+        reply.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn free_space_is_withheld_before_pairing() {
+        let reply = welcome(None).await;
+        assert_eq!(reply.free_space, None);
+        assert_eq!(reply.protocol, PROTOCOL_VERSION);
+        assert!(reply.features.contains(&Feature::Pairing));
+    }
+
+    #[tokio::test]
+    async fn free_space_is_told_once_paired() {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::new(dir.path()).with_quota(Some(1000));
+        let reply = welcome(Some(&storage)).await;
+        assert!(reply.free_space.is_some_and(|free| free <= 1000));
+    }
+}
@@ -0,0 +1,44 @@
+use std::error::Error;
+
+use futures_util::SinkExt;
+use log::info;
+
+use crate::protocol::codec::FramedStream;
+use crate::protocol::error::RequestError;
+use crate::protocol::frame::{Frame, Opcode};
+use crate::protocol::manifest::Manifest;
+use crate::protocol::messages::{ErrorCode, OkReply};
+use crate::storage::storage::Storage;
+
+/// Handles a `MANIFEST` request frame by preparing storage for the directory tree it describes.
+///
+/// The tree is checked and its directories created through [`Storage::prepare_tree`], then acknowledged with an `OK` frame. The client follows up with a `PUT` for each file of the tree.
+///
+/// # Errors
+/// Returns a [`RequestError`] if the manifest cannot be parsed, a path is invalid, or the tree exceeds the upload limit or quota.
+pub async fn receive_manifest(
+    stream: &mut FramedStream,
+    request: &Frame,
+    storage: &Storage,
+) -> Result<(), Box<dyn Error>> {
+    let manifest: Manifest = request
+        .parse()
+        .map_err(|e| RequestError::new(ErrorCode::BadRequest, e.to_string()))?;
+    storage.prepare_tree(&manifest)?;
+    let files = manifest.files().count();
+    let size = manifest.total_size();
+    info!(
+        "Prepared {:?} for {} files ({} bytes)",
+        manifest.root, files, size
+    );
+
+    let reply = OkReply {
+        message: format!("Ready for {files} files ({size} bytes)"),
+        path: manifest.root,
+        digest: None,
+        stats: None,
+    };
+    stream.send(Frame::json(Opcode::Ok, &reply)?).await?;
+
+    Ok(())
+}
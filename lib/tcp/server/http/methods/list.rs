@@ -1,34 +1,226 @@
-use serde_json::json;
-use std::{error::Error, fs};
-use tokio::{io::AsyncWriteExt, net::TcpStream};
+use futures_util::SinkExt;
+use glob::{MatchOptions, Pattern};
+use std::error::Error;
 
-/// Lists the names of files in the "storage" directory and sends them as a JSON array over the provided TCP stream.
+use crate::protocol::codec::FramedStream;
+use crate::protocol::error::RequestError;
+use crate::protocol::frame::{Frame, Opcode};
+use crate::protocol::messages::{ErrorCode, ListRequest, ListingReply, RemoteEntry};
+use crate::storage::storage::Storage;
+
+/// Most entries a single `LISTING` reply carries, whatever limit the request asks for.
+pub const MAX_LIST_PAGE: usize = 1000;
+
+/// Handles a `LIST` request frame by describing the requested part of storage in a single `LISTING` frame.
 ///
-/// The function writes a status message to the stream, collects all file names from the "storage" directory,
-/// serializes them into a JSON array, and transmits the result to the client. The stream is flushed to ensure
-/// all data is sent.
+/// The request payload is an optional [`ListRequest`]; an empty one lists the top of the storage root. Entries come from [`Storage::entries`], are filtered by the request's glob pattern and paged by its offset and limit, the page being capped at [`MAX_LIST_PAGE`] entries. The reply is a [`ListingReply`] carrying the page, the number of matching entries and where the next page starts.
 ///
 /// # Errors
-/// Returns an error if reading the directory, serializing the file names, or writing to the stream fails.
-pub async fn list_storage(stream: &mut TcpStream) -> Result<(), Box<dyn Error>> {
-    stream.write_all(b"Listing storage items...\n").await?;
+/// Returns `BadRequest` if the payload or its pattern is invalid, every error [`Storage::entries`] can return, or an error if writing to the stream fails.
+pub async fn list_storage(
+    stream: &mut FramedStream,
+    request: &Frame,
+    storage: &Storage,
+) -> Result<(), Box<dyn Error>> {
+    let request: ListRequest = if request.payload.is_empty() {
+        ListRequest::default()
+    } else {
+        request
+            .parse()
+            .map_err(|e| RequestError::new(ErrorCode::BadRequest, e.to_string()))?
+    };
+    let pattern = request
+        .pattern
+        .as_deref()
+        .map(Pattern::new)
+        .transpose()
+        .map_err(|e| RequestError::new(ErrorCode::BadRequest, format!("Invalid pattern: {e}")))?;
+
+    let entries = {
+        let storage = storage.clone();
+        let path = request.path.clone();
+        tokio::task::spawn_blocking(move || storage.entries(&path, request.recursive)).await??
+    };
+    let matching: Vec<RemoteEntry> = entries
+        .into_iter()
+        .filter(|entry| {
+            pattern
+                .as_ref()
+                .is_none_or(|pattern| matches(pattern, entry))
+        })
+        .collect();
+
+    let total = matching.len();
+    let limit = request
+        .limit
+        .unwrap_or(MAX_LIST_PAGE)
+        .clamp(1, MAX_LIST_PAGE);
+    let entries: Vec<RemoteEntry> = matching
+        .into_iter()
+        .skip(request.offset)
+        .take(limit)
+        .collect();
+    let end = request.offset + entries.len();
+    let reply = ListingReply {
+        entries,
+        total,
+        next_offset: (end < total).then_some(end),
+    };
+    log::info!(
+        "Listing {} of {} entries under {:?}",
+        reply.entries.len(),
+        total,
+        request.path
+    );
+    stream.send(Frame::json(Opcode::Listing, &reply)?).await?;
+
+    Ok(())
+}
 
-    let mut file_names = Vec::new(); // Create a vector to hold file names
-    let entries = fs::read_dir("storage")?; // Retrieve the entries in the "storage" directory
-    for entry in entries {
-        let entry = entry?; // Unwrap each entry safely
-        let file_name = entry.file_name(); // Get the file name
-        let file_name_str = file_name.to_string_lossy().to_string(); // Convert the file name to a string
-        file_names.push(file_name_str); // Add the file name to the vector
+/// Whether `entry` matches `pattern`: its whole path if the pattern contains `/`, its name otherwise.
+fn matches(pattern: &Pattern, entry: &RemoteEntry) -> bool {
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+    if pattern.as_str().contains('/') {
+        pattern.matches_with(&entry.path, options)
+    } else {
+        let name = entry.path.rsplit('/').next().unwrap_or(&entry.path);
+        pattern.matches_with(name, options)
     }
+}
 
-    let json = json!(file_names); // Serialize the vector to a JSON array
-    let json_str = json.to_string(); // Convert the JSON array to a string
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::codec::FrameCodec;
+    use futures_util::StreamExt;
 
-    log::info!("{json_str}");
-    stream.write_all(json_str.as_bytes()).await?; // Send the JSON string over the stream
-    stream.write_all(b"\n").await?; // Add a newline to indicate the end of the message
-    stream.flush().await?; // Flush the stream to ensure all data is sent
+    fn storage(files: &[&str]) -> (tempfile::TempDir, Storage) {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::new(dir.path().join("root"));
+        for file in files {
+            let path = storage.root().join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, b"x").unwrap();
+        }
+        (dir, storage)
+    }
 
-    Ok(())
+    async fn list(storage: &Storage, request: &ListRequest) -> Result<ListingReply, String> {
+        let (server, client) = tokio::io::duplex(64 * 1024);
+        let mut server = FrameCodec::framed(server);
+        let mut client = FrameCodec::framed(client);
+        let request = Frame::json(Opcode::List, request).unwrap();
+        list_storage(&mut server, &request, storage)
+            .await
+            .map_err(|e| e.to_string())?;
+        let reply = client.next().await.unwrap().unwrap();
+        assert_eq!(reply.opcode, Opcode::Listing);
+        Ok(reply.parse().unwrap())
+    }
+
+    fn paths(reply: &ListingReply) -> Vec<&str> {
+        reply.entries.iter().map(|e| e.path.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn pages_follow_the_offset_and_limit() {
+        let names: Vec<String> = (0..5).map(|i| format!("f{i}.txt")).collect();
+        let (_dir, storage) = storage(&names.iter().map(String::as_str).collect::<Vec<_>>());
+        let page = |offset| ListRequest {
+            offset,
+            limit: Some(2),
+            ..Default::default()
+        };
+
+        let first = list(&storage, &page(0)).await.unwrap();
+        assert_eq!(paths(&first), ["f0.txt", "f1.txt"]);
+        assert_eq!(first.total, 5);
+        assert_eq!(first.next_offset, Some(2));
+        let last = list(&storage, &page(4)).await.unwrap();
+        assert_eq!(paths(&last), ["f4.txt"]);
+        assert_eq!(last.next_offset, None);
+        let past = list(&storage, &page(9)).await.unwrap();
+        assert!(past.entries.is_empty());
+        assert_eq!(past.total, 5);
+        assert_eq!(past.next_offset, None);
+    }
+
+    #[tokio::test]
+    async fn pages_are_capped() {
+        let names: Vec<String> = (0..MAX_LIST_PAGE + 1).map(|i| format!("f{i:04}")).collect();
+        let (_dir, storage) = storage(&names.iter().map(String::as_str).collect::<Vec<_>>());
+
+        let unlimited = list(&storage, &ListRequest::default()).await.unwrap();
+        assert_eq!(unlimited.entries.len(), MAX_LIST_PAGE);
+        assert_eq!(unlimited.next_offset, Some(MAX_LIST_PAGE));
+        let huge = ListRequest {
+            limit: Some(usize::MAX),
+            ..Default::default()
+        };
+        assert_eq!(
+            list(&storage, &huge).await.unwrap().entries.len(),
+            MAX_LIST_PAGE
+        );
+        let zero = ListRequest {
+            limit: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(list(&storage, &zero).await.unwrap().entries.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn patterns_without_a_slash_match_names() {
+        let (_dir, storage) = storage(&["a.txt", "b.jpg", "docs/c.txt", "docs/d.md"]);
+        let request = ListRequest {
+            recursive: true,
+            pattern: Some("*.txt".to_string()),
+            ..Default::default()
+        };
+        let reply = list(&storage, &request).await.unwrap();
+        assert_eq!(paths(&reply), ["a.txt", "docs/c.txt"]);
+        assert_eq!(reply.total, 2);
+    }
+
+    #[tokio::test]
+    async fn patterns_with_a_slash_match_whole_paths() {
+        let (_dir, storage) = storage(&["a.txt", "docs/c.txt", "docs/deep/e.txt"]);
+        let request = |pattern: &str| ListRequest {
+            recursive: true,
+            pattern: Some(pattern.to_string()),
+            ..Default::default()
+        };
+        let reply = list(&storage, &request("docs/*.txt")).await.unwrap();
+        assert_eq!(paths(&reply), ["docs/c.txt"]);
+        let reply = list(&storage, &request("docs/**/*.txt")).await.unwrap();
+        assert_eq!(paths(&reply), ["docs/c.txt", "docs/deep/e.txt"]);
+    }
+
+    #[tokio::test]
+    async fn paging_counts_only_matching_entries() {
+        let (_dir, storage) = storage(&["a.txt", "b.jpg", "c.txt", "d.jpg", "e.txt"]);
+        let request = ListRequest {
+            pattern: Some("*.txt".to_string()),
+            offset: 1,
+            limit: Some(1),
+            ..Default::default()
+        };
+        let reply = list(&storage, &request).await.unwrap();
+        assert_eq!(paths(&reply), ["c.txt"]);
+        assert_eq!(reply.total, 3);
+        assert_eq!(reply.next_offset, Some(2));
+    }
+
+    #[tokio::test]
+    async fn invalid_patterns_are_rejected() {
+        let (_dir, storage) = storage(&["a.txt"]);
+        let request = ListRequest {
+            pattern: Some("[".to_string()),
+            ..Default::default()
+        };
+        let error = list(&storage, &request).await.unwrap_err();
+        assert!(error.contains("Invalid pattern"), "{error}");
+    }
 }
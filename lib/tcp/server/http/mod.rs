@@ -1,4 +1,10 @@
+pub mod delete;
+pub mod download;
 pub mod get;
+pub mod hello;
+pub mod manifest;
 pub mod methods;
+pub mod pair;
 pub mod put;
 pub mod storage;
+pub mod upload;
@@ -0,0 +1,161 @@
+use std::convert::Infallible;
+use std::error::Error;
+use std::net::{IpAddr, Ipv6Addr};
+
+use futures_util::SinkExt;
+use warp::{Filter, Rejection, Reply};
+
+use super::put::error_response;
+use crate::pairing::pairing::{PairedClient, Pairing, SESSION_HEADER};
+use crate::protocol::codec::FramedStream;
+use crate::protocol::error::RequestError;
+use crate::protocol::frame::{Frame, Opcode};
+use crate::protocol::messages::{ConfirmRequest, ErrorCode, HttpEndpoint, PairReply, PairRequest};
+use crate::storage::storage::Storage;
+use crate::users::users::UserRegistry;
+
+/// The address HTTP pairing attempts are counted against: warp does not see the peer's address behind the scheduler's incoming stream, so they all share one budget.
+const HTTP_PEER: IpAddr = IpAddr::V6(Ipv6Addr::UNSPECIFIED);
+
+/// Handles a `PAIR` request frame from the client at `peer`, answering with a `CHALLENGE` frame that carries the server's half of the handshake and the `http` endpoint the server's HTTP routes are on, if any.
+///
+/// # Errors
+/// Returns a [`RequestError`] if the payload or the client's SPAKE2 message is malformed, or if `peer` started too many handshakes lately.
+pub async fn pair(
+    stream: &mut FramedStream,
+    request: &Frame,
+    pairing: &Pairing,
+    http: Option<HttpEndpoint>,
+    peer: IpAddr,
+) -> Result<(), Box<dyn Error>> {
+    let request: PairRequest = request
+        .parse()
+        .map_err(|e| RequestError::new(ErrorCode::BadRequest, e.to_string()))?;
+    let reply = PairReply {
+        http,
+        ..pairing.respond(&request, peer)?
+    };
+    stream.send(Frame::json(Opcode::Challenge, &reply)?).await?;
+    Ok(())
+}
+
+/// Handles a `CONFIRM` request frame, completing the pairing and replying with an `OK` frame that carries the server's key confirmation.
+///
+/// # Returns
+/// The client the connection is now paired as, and its user's storage, registered in `users` if they are new.
+///
+/// # Errors
+/// Returns an `Unauthorized` [`RequestError`] if the client used the wrong code or an unknown pairing session or its user name belongs to another client, or a `BadRequest` if its name cannot be registered.
+pub async fn confirm(
+    stream: &mut FramedStream,
+    request: &Frame,
+    pairing: &Pairing,
+    users: &UserRegistry,
+) -> Result<(PairedClient, Storage), Box<dyn Error>> {
+    let request: ConfirmRequest = request
+        .parse()
+        .map_err(|e| RequestError::new(ErrorCode::BadRequest, e.to_string()))?;
+    let (client, reply) = pairing.confirm(&request)?;
+    let storage = users
+        .storage_for(&client.name, &client.credential)
+        .inspect_err(|_| {
+            pairing.revoke(&client.token);
+        })?;
+    stream.send(Frame::json(Opcode::Ok, &reply)?).await?;
+    Ok((client, storage))
+}
+
+/// A request turned away by [`authorized`], answered by [`recover`].
+#[derive(Debug)]
+struct Rejected(RequestError);
+
+impl warp::reject::Reject for Rejected {}
+
+/// Lets a request through only if it carries the session token of a client paired through `pairing` in the [`SESSION_HEADER`], extracting that client.
+///
+/// The token is a bearer credential, so it is only accepted when the routes are served over TLS: unless `secure`, every request is refused rather than letting a token that could be captured off the wire through.
+pub fn authorized(
+    pairing: Pairing,
+    secure: bool,
+) -> impl Filter<Extract = (PairedClient,), Error = Rejection> + Clone {
+    warp::header::optional::<String>(SESSION_HEADER).and_then(move |token: Option<String>| {
+        let result = if secure {
+            pairing.authorize(token.as_deref())
+        } else {
+            Err(RequestError::new(
+                ErrorCode::Unauthorized,
+                "Session tokens are only accepted over HTTPS",
+            ))
+        }
+        .map_err(|e| warp::reject::custom(Rejected(e)));
+        async move { result }
+    })
+}
+
+/// Extracts the storage of the paired user making the request, rejecting requests from unpaired clients, or any request unless `secure`, like [`authorized`].
+pub fn user_storage(
+    pairing: Pairing,
+    users: UserRegistry,
+    secure: bool,
+) -> impl Filter<Extract = (Storage,), Error = Rejection> + Clone {
+    authorized(pairing, secure).and_then(move |client: PairedClient| {
+        let result = users
+            .storage_for(&client.name, &client.credential)
+            .map_err(|e| warp::reject::custom(Rejected(e)));
+        async move { result }
+    })
+}
+
+/// Turns a rejection from [`authorized`] into a `401` response, passing every other rejection on.
+pub async fn recover(rejection: Rejection) -> Result<warp::reply::Response, Rejection> {
+    match rejection.find::<Rejected>() {
+        Some(Rejected(e)) => Ok(error_response(e)),
+        None => Err(rejection),
+    }
+}
+
+async fn respond(request: PairRequest, pairing: Pairing) -> Result<impl Reply, Infallible> {
+    match pairing.respond(&request, HTTP_PEER) {
+        Ok(reply) => Ok(warp::reply::json(&reply).into_response()),
+        Err(e) => Ok(error_response(&e)),
+    }
+}
+
+async fn confirm_http(
+    request: ConfirmRequest,
+    pairing: Pairing,
+    users: UserRegistry,
+) -> Result<impl Reply, Infallible> {
+    let registered = pairing.confirm(&request).and_then(|(client, reply)| {
+        users
+            .register(&client.name, &client.credential)
+            .inspect_err(|_| {
+                pairing.revoke(&client.token);
+            })?;
+        Ok(reply)
+    });
+    match registered {
+        Ok(reply) => Ok(warp::reply::json(&reply).into_response()),
+        Err(e) => Ok(error_response(&e)),
+    }
+}
+
+/// The `/pair` routes: `POST /pair` starts a handshake like the `PAIR` frame and `POST /pair/confirm` completes it like `CONFIRM`, registering the client in `users` and answering with the [`ConfirmReply`](crate::protocol::messages::ConfirmReply) as JSON.
+pub fn router(
+    pairing: Pairing,
+    users: UserRegistry,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let confirm_pairing = pairing.clone();
+    let start = warp::path!("pair")
+        .and(warp::post())
+        .and(warp::body::json::<PairRequest>())
+        .and(warp::any().map(move || pairing.clone()))
+        .and_then(respond);
+    let confirm = warp::path!("pair" / "confirm")
+        .and(warp::post())
+        .and(warp::body::json::<ConfirmRequest>())
+        .and(warp::any().map(move || confirm_pairing.clone()))
+        .and(warp::any().map(move || users.clone()))
+        .and_then(confirm_http);
+    start.or(confirm)
+}
@@ -1,22 +1,73 @@
-use bytes::Bytes;
+use async_compression::tokio::bufread::ZstdDecoder;
+use bytes::Buf;
+use futures_util::{Stream, StreamExt};
 use std::convert::Infallible;
-use std::path::Path;
-use tokio::fs;
-use tokio::fs::create_dir_all;
+use std::fmt::Display;
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::{ReaderStream, StreamReader};
 use warp::Filter;
 use warp::Reply;
 use warp::cors;
 use warp::http::Response;
 use warp::hyper::Body;
 
-use super::storage::STORAGE_PATH;
+use crate::protocol::compression::Compression;
+use crate::protocol::digest::{DIGEST_HEADER, Digest, HashingWriter};
+use crate::protocol::error::RequestError;
+use crate::protocol::frame::MAX_PAYLOAD_LEN;
+use crate::protocol::manifest::Manifest;
+use crate::protocol::messages::{ErrorCode, UploadStatus};
+use crate::protocol::transfer::CHUNK_SIZE;
+use crate::storage::storage::{PendingWrite, Storage, apply_metadata};
 
+/// Query string of the `/upload` route.
+///
+/// `offset` turns a `POST` into a resumable upload session: the body holds the file's bytes from `offset` on, and `size` is the full file size, defaulting to `offset` plus the body's `Content-Length`. A compressed body's `Content-Length` says nothing about the file, so it needs `size`. A stored file is given the Unix permission bits `mode` and the modification time `mtime`, in seconds since the Unix epoch, where set.
 #[derive(Debug, serde::Deserialize)]
 pub struct FileQuery {
     pub path: String,
+    #[serde(default)]
+    pub offset: Option<u64>,
+    #[serde(default)]
+    pub size: Option<u64>,
+    #[serde(default)]
+    pub mode: Option<u32>,
+    #[serde(default)]
+    pub mtime: Option<u64>,
 }
 
-pub async fn put(query: FileQuery, body: Bytes) -> Result<impl Reply, Infallible> {
+/// Where an upload request left its file.
+enum UploadOutcome {
+    /// The whole file arrived and was committed into storage, with its verified digest.
+    Stored(PathBuf, Digest),
+    /// Part of a resumable upload arrived; the rest can be sent from the reported offset.
+    Partial(UploadStatus),
+}
+
+/// Handles `POST /upload`, streaming the request body into storage.
+///
+/// The body is written chunk by chunk to a `.part` file that is renamed into place once the whole body has arrived, so memory use does not grow with the file size and a failed upload never leaves a truncated file behind.
+///
+/// With an `offset` in the query the body continues a resumable upload instead. The `.part` file is kept when the body ends early, and `202 Accepted` with an [`UploadStatus`] is returned while the file is still incomplete.
+///
+/// The query path is kept as given, so a file can be stored in a subdirectory; it is resolved through [`Storage`], which rejects paths leaving the storage root. Once stored, the file is given the query's mode and modification time, if any, and its digest is recorded in the storage's index for listings.
+///
+/// A body sent with `Content-Encoding: zstd` is decoded as it arrives, and the sizes are checked against the decoded bytes, so a small body cannot expand past the announced `size`. Other encodings are refused with `400`.
+///
+/// The file is hashed as it is written. A digest sent in the [`DIGEST_HEADER`] is checked once the file is complete; on a mismatch the partial file is deleted and `422` is returned. A stored file's digest is echoed back in the same header.
+pub async fn put<S, B>(
+    query: FileQuery,
+    content_length: Option<u64>,
+    content_encoding: Option<String>,
+    digest: Option<String>,
+    storage: Storage,
+    body: S,
+) -> Result<impl Reply, Infallible>
+where
+    S: Stream<Item = Result<B, warp::Error>>,
+    B: Buf,
+{
     if query.path.trim().is_empty() {
         return Ok(Response::builder()
             .status(400)
@@ -24,48 +75,383 @@ pub async fn put(query: FileQuery, body: Bytes) -> Result<impl Reply, Infallible
             .unwrap());
     }
 
-    let dest_dir = &*STORAGE_PATH;
-    if let Err(e) = create_dir_all(&dest_dir).await {
-        return Ok(Response::builder()
-            .status(500)
-            .body(Body::from(format!(
-                "Failed to create destination directory: {}",
-                e
-            )))
-            .unwrap());
-    }
+    let expected = match digest.as_deref().map(str::parse::<Digest>).transpose() {
+        Ok(expected) => expected,
+        Err(e) => return Ok(error_response(&e)),
+    };
 
-    let filename = Path::new(&query.path)
-        .file_name()
-        .and_then(|name| name.to_str())
-        .unwrap_or("file");
+    let compression = match content_encoding
+        .as_deref()
+        .map(str::parse::<Compression>)
+        .transpose()
+    {
+        Ok(compression) => compression.unwrap_or_default(),
+        Err(e) => return Ok(error_response(&e)),
+    };
 
-    let dest_path = dest_dir.join(filename);
+    let outcome = if compression.is_compressed() {
+        let content_length = query
+            .size
+            .map(|size| size.saturating_sub(query.offset.unwrap_or(0)));
+        let decoded = ReaderStream::with_capacity(
+            ZstdDecoder::new(StreamReader::new(
+                body.map(|chunk| chunk.map_err(std::io::Error::other)),
+            )),
+            CHUNK_SIZE,
+        );
+        receive(&query, content_length, expected.as_ref(), &storage, decoded).await
+    } else {
+        receive(&query, content_length, expected.as_ref(), &storage, body).await
+    };
 
-    match fs::write(&dest_path, &body).await {
-        Ok(_) => {
+    match outcome {
+        Ok(UploadOutcome::Stored(dest_path, digest)) => {
+            if let Err(e) = apply_metadata(&dest_path, query.mode, query.mtime) {
+                log::warn!("Failed to apply the metadata of {:?}: {}", dest_path, e);
+            }
+            storage.record_upload(&dest_path, &digest);
             let msg = format!("File saved successfully to {:?}", dest_path);
             Ok(Response::builder()
                 .status(200)
+                .header(DIGEST_HEADER, digest.to_string())
                 .body(Body::from(msg))
                 .unwrap())
         }
-        Err(e) => Ok(Response::builder()
-            .status(500)
-            .body(Body::from(format!("Failed to save file: {}", e)))
+        Ok(UploadOutcome::Partial(status)) => Ok(Response::builder()
+            .status(202)
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::to_vec(&status).unwrap_or_default()))
             .unwrap()),
+        Err(e) => Ok(error_response(&e)),
     }
 }
-pub fn router() -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone {
+
+/// Handles `GET /upload`, reporting how many bytes of a resumable upload the server already holds.
+pub async fn upload_status(query: FileQuery, storage: Storage) -> Result<impl Reply, Infallible> {
+    match storage.partial_len(&query.path) {
+        Ok(offset) => {
+            let status = UploadStatus {
+                path: query.path,
+                offset,
+            };
+            Ok(warp::reply::json(&status).into_response())
+        }
+        Err(e) => Ok(error_response(&e).into_response()),
+    }
+}
+
+/// Handles `POST /upload/manifest`, preparing storage for the directory tree described by the JSON [`Manifest`] in the body.
+///
+/// The client follows up with an upload of each file of the tree to its [`Manifest::remote_path`].
+pub async fn prepare_tree(manifest: Manifest, storage: Storage) -> Result<impl Reply, Infallible> {
+    match storage.prepare_tree(&manifest) {
+        Ok(()) => {
+            log::info!(
+                "Prepared {:?} for {} files ({} bytes)",
+                manifest.root,
+                manifest.files().count(),
+                manifest.total_size()
+            );
+            Ok(Response::builder()
+                .status(200)
+                .body(Body::from(format!("Prepared {}", manifest.root)))
+                .unwrap())
+        }
+        Err(e) => Ok(error_response(&e)),
+    }
+}
+
+/// Receives the decoded `body` of an upload, as a whole file or as part of a resumable one depending on `query`.
+async fn receive<S, B, E>(
+    query: &FileQuery,
+    content_length: Option<u64>,
+    expected: Option<&Digest>,
+    storage: &Storage,
+    body: S,
+) -> Result<UploadOutcome, RequestError>
+where
+    S: Stream<Item = Result<B, E>>,
+    B: Buf,
+    E: Display,
+{
+    let filename = query.path.as_str();
+    match query.offset {
+        Some(offset) => {
+            let chunk = Chunk {
+                offset,
+                size: query.size,
+                content_length,
+            };
+            receive_chunk(filename, chunk, expected, storage, body).await
+        }
+        None => receive_body(filename, content_length, expected, storage, body).await,
+    }
+}
+
+/// Streams `body` into a new file at `filename` and commits it.
+///
+/// # Errors
+/// Returns `TooLarge` if the declared or received length exceeds the storage's upload limit, `QuotaExceeded` if it does not fit the storage's quota, `BadRequest` if the body is shorter or longer than its `Content-Length`, `DigestMismatch` if the body does not match the `expected` digest, or the error of any failed storage operation.
+async fn receive_body<S, B, E>(
+    filename: &str,
+    content_length: Option<u64>,
+    expected: Option<&Digest>,
+    storage: &Storage,
+    body: S,
+) -> Result<UploadOutcome, RequestError>
+where
+    S: Stream<Item = Result<B, E>>,
+    B: Buf,
+    E: Display,
+{
+    if let Some(length) = content_length {
+        storage.check_upload_size(length)?;
+        storage.check_quota(filename, length)?;
+    }
+
+    let mut pending = storage.create_write(filename).await?;
+    let written = write_body(&mut pending, content_length, None, expected, storage, body).await;
+    let (received, digest) = match written.and_then(|(received, digest)| {
+        verify(expected, &digest)?;
+        if content_length.is_none() {
+            storage.check_quota(filename, received)?;
+        }
+        Ok((received, digest))
+    }) {
+        Ok(written) => written,
+        Err(e) => {
+            pending.abort();
+            return Err(e);
+        }
+    };
+    let path = pending.commit().await.map_err(|e| RequestError::from(&e))?;
+    log::info!(
+        "Upload complete: {} bytes received into {:?} ({})",
+        received,
+        path,
+        digest
+    );
+    Ok(UploadOutcome::Stored(path, digest))
+}
+
+/// Position of a resumable upload request's body within the file.
+struct Chunk {
+    offset: u64,
+    size: Option<u64>,
+    content_length: Option<u64>,
+}
+
+/// Appends `body` to the resumable upload of `filename`, which must currently hold exactly `offset` bytes.
+///
+/// # Errors
+/// Returns `OffsetMismatch` if the server holds a different number of bytes, `BadRequest` if the full size is unknown or the body runs past it, plus every error [`receive_body`] can return. The `.part` file is kept in all cases but a digest mismatch.
+async fn receive_chunk<S, B, E>(
+    filename: &str,
+    chunk: Chunk,
+    expected: Option<&Digest>,
+    storage: &Storage,
+    body: S,
+) -> Result<UploadOutcome, RequestError>
+where
+    S: Stream<Item = Result<B, E>>,
+    B: Buf,
+    E: Display,
+{
+    let Chunk {
+        offset,
+        size,
+        content_length,
+    } = chunk;
+    let size = size
+        .or(content_length.map(|length| offset + length))
+        .ok_or_else(|| {
+            RequestError::new(
+                ErrorCode::BadRequest,
+                "Resumable uploads need a size or a Content-Length",
+            )
+        })?;
+    storage.check_upload_size(size)?;
+    storage.check_quota(filename, size)?;
+
+    let mut pending = storage.resume_write(filename, size).await?;
+    if pending.offset() != offset {
+        let held = pending.offset();
+        pending.suspend().await;
+        return Err(RequestError::new(
+            ErrorCode::OffsetMismatch,
+            format!("Server holds {held} bytes of {filename}, not {offset}"),
+        ));
+    }
+
+    let written = write_body(
+        &mut pending,
+        content_length,
+        Some(size),
+        expected,
+        storage,
+        body,
+    )
+    .await;
+    let (received, digest) = match written {
+        Ok(written) => written,
+        Err(e) => {
+            pending.suspend().await;
+            return Err(e);
+        }
+    };
+
+    let offset = offset + received;
+    if offset < size {
+        pending.suspend().await;
+        return Ok(UploadOutcome::Partial(UploadStatus {
+            path: filename.to_string(),
+            offset,
+        }));
+    }
+    if let Err(e) = verify(expected, &digest) {
+        pending.abort();
+        return Err(e);
+    }
+    let path = pending.commit().await.map_err(|e| RequestError::from(&e))?;
+    log::info!(
+        "Resumed upload complete: {} bytes in {:?} ({})",
+        size,
+        path,
+        digest
+    );
+    Ok(UploadOutcome::Stored(path, digest))
+}
+
+fn verify(expected: Option<&Digest>, actual: &Digest) -> Result<(), RequestError> {
+    match expected {
+        Some(expected) => expected.verify(actual),
+        None => Ok(()),
+    }
+}
+
+/// Writes `body` after the bytes `pending` already holds, hashing the whole file with the algorithm of the `expected` digest (BLAKE3 by default).
+///
+/// # Returns
+/// The number of bytes written and the digest of the file so far, or an error if the body exceeds its `Content-Length`, the file's full `size` or the upload limit, or is shorter than its `Content-Length`.
+async fn write_body<S, B, E>(
+    pending: &mut PendingWrite,
+    content_length: Option<u64>,
+    size: Option<u64>,
+    expected: Option<&Digest>,
+    storage: &Storage,
+    body: S,
+) -> Result<(u64, Digest), RequestError>
+where
+    S: Stream<Item = Result<B, E>>,
+    B: Buf,
+    E: Display,
+{
+    let algorithm = expected.map(|digest| digest.algorithm).unwrap_or_default();
+    let hasher = pending
+        .existing_hasher(algorithm)
+        .await
+        .map_err(|e| RequestError::from(&e))?;
+    let offset = pending.offset();
+    let mut writer = HashingWriter::new(&mut pending.file, hasher);
+    let mut body = std::pin::pin!(body);
+    let mut received: u64 = 0;
+    while let Some(chunk) = body.next().await {
+        let mut chunk =
+            chunk.map_err(|e| RequestError::new(ErrorCode::BadRequest, e.to_string()))?;
+        received += chunk.remaining() as u64;
+        if content_length.is_some_and(|length| received > length) {
+            return Err(RequestError::new(
+                ErrorCode::BadRequest,
+                "Body is longer than its Content-Length",
+            ));
+        }
+        let total = offset + received;
+        if size.is_some_and(|size| total > size) {
+            return Err(RequestError::new(
+                ErrorCode::BadRequest,
+                "Body runs past the end of the file",
+            ));
+        }
+        storage.check_upload_size(total)?;
+
+        while chunk.has_remaining() {
+            let bytes = chunk.chunk();
+            let len = bytes.len();
+            writer
+                .write_all(bytes)
+                .await
+                .map_err(|e| RequestError::from(&e))?;
+            chunk.advance(len);
+        }
+    }
+
+    if let Some(length) = content_length.filter(|&length| received != length) {
+        return Err(RequestError::new(
+            ErrorCode::BadRequest,
+            format!("Expected {length} bytes but received {received}"),
+        ));
+    }
+    writer.flush().await.map_err(|e| RequestError::from(&e))?;
+    Ok((received, writer.finalize()))
+}
+
+/// Builds the HTTP response for a failed request, choosing the status from its [`ErrorCode`].
+pub fn error_response(error: &RequestError) -> Response<Body> {
+    let status = match error.code {
+        ErrorCode::BadRequest | ErrorCode::InvalidPath => 400,
+        ErrorCode::Unauthorized => 401,
+        ErrorCode::NotFound => 404,
+        ErrorCode::OffsetMismatch => 409,
+        ErrorCode::TooLarge => 413,
+        ErrorCode::DigestMismatch => 422,
+        ErrorCode::QuotaExceeded => 507,
+        ErrorCode::Busy => 503,
+        ErrorCode::TooManyAttempts => 429,
+        ErrorCode::Cancelled | ErrorCode::Interrupted => 499,
+        ErrorCode::Internal => 500,
+    };
+    Response::builder()
+        .status(status)
+        .body(Body::from(error.message.clone()))
+        .unwrap()
+}
+
+/// The `/upload` routes, serving each request from the storage `storage` extracts for it.
+pub fn router<F>(
+    storage: F,
+) -> impl Filter<Extract = impl warp::Reply, Error = warp::Rejection> + Clone
+where
+    F: Filter<Extract = (Storage,), Error = warp::Rejection> + Clone + Send + Sync + 'static,
+{
     let cors = cors()
         .allow_origin("https://your-allowed-origin.com")
-        .allow_methods(vec!["POST"])
-        .allow_headers(vec!["content-type"]);
+        .allow_methods(vec!["GET", "POST"])
+        .allow_headers(vec!["content-type", "content-encoding", DIGEST_HEADER]);
 
-    warp::path("upload")
+    let manifest = warp::path!("upload" / "manifest")
         .and(warp::post())
+        .and(warp::body::content_length_limit(MAX_PAYLOAD_LEN as u64))
+        .and(warp::body::json::<Manifest>())
+        .and(storage.clone())
+        .and_then(prepare_tree);
+
+    let status = warp::path("upload")
+        .and(warp::path::end())
+        .and(warp::get())
         .and(warp::query::<FileQuery>())
-        .and(warp::body::bytes())
-        .and_then(put)
-        .with(cors)
+        .and(storage.clone())
+        .and_then(upload_status);
+
+    let upload = warp::path("upload")
+        .and(warp::path::end())
+        .and(warp::post())
+        .and(warp::query::<FileQuery>())
+        .and(warp::header::optional::<u64>("content-length"))
+        .and(warp::header::optional::<String>("content-encoding"))
+        .and(warp::header::optional::<String>(DIGEST_HEADER))
+        .and(storage)
+        .and(warp::body::stream())
+        .and_then(put);
+
+    manifest.or(upload).or(status).with(cors)
 }
@@ -0,0 +1,110 @@
+use std::error::Error;
+
+use futures_util::SinkExt;
+use log::{info, warn};
+
+use crate::protocol::codec::FramedStream;
+use crate::protocol::digest::HashingWriter;
+use crate::protocol::error::RequestError;
+use crate::protocol::frame::{Frame, Opcode};
+use crate::protocol::messages::{ErrorCode, OkReply, PutRequest, ReadyReply};
+use crate::protocol::progress::ProgressTracker;
+use crate::protocol::transfer::recv_encoded_body;
+use crate::storage::storage::{Storage, apply_metadata};
+
+/// Handles a `PUT` request frame by receiving the announced file into storage.
+///
+/// The target is opened with [`Storage::create_write`] before the server answers `READY`, so a rejected upload never has to drain a body. The `DATA` frames that follow are streamed to disk, their progress logged every few seconds, and committed into place once all bytes have arrived, then acknowledged with an `OK` frame. The partial file is removed if the transfer fails.
+///
+/// A request with `resume` set is opened with [`Storage::resume_write`] instead: the `READY` reply reports how many bytes an earlier attempt left behind, only the rest is expected, and the partial file is kept if this attempt fails too. A client that cancels the upload with an `ERROR` frame in place of the next `DATA` frame gets the partial file removed either way, unless the frame only says the transfer was `Interrupted` for a retry.
+///
+/// A body the client proposes to compress with zstd is accepted as such, the `READY` reply echoing the encoding, and decoded as it is written.
+///
+/// Once committed, the file is given the mode and modification time the request carries, if any, and its digest is recorded in the storage's index for listings.
+///
+/// The file is hashed while it is written. If the request announces a digest and the stored content does not match it, the partial file is deleted and the upload fails with `DigestMismatch`; otherwise the `OK` reply carries the verified digest.
+///
+/// # Errors
+/// Returns a [`RequestError`] if the announced size exceeds the storage's upload limit or quota, or the path is invalid or names a directory, a `DigestMismatch` if the content does not match the announced digest, or an error if any I/O operation fails during the transfer.
+pub async fn upload_file(
+    stream: &mut FramedStream,
+    request: &Frame,
+    storage: &Storage,
+) -> Result<(), Box<dyn Error>> {
+    let request: PutRequest = request
+        .parse()
+        .map_err(|e| RequestError::new(ErrorCode::BadRequest, e.to_string()))?;
+    storage.check_upload_size(request.size)?;
+    storage.check_quota(&request.path, request.size)?;
+    let mut pending = if request.resume {
+        storage.resume_write(&request.path, request.size).await?
+    } else {
+        storage.create_write(&request.path).await?
+    };
+    let ready = ReadyReply {
+        offset: pending.offset(),
+        compression: request.compression,
+    };
+    stream.send(Frame::json(Opcode::Ready, &ready)?).await?;
+    info!(
+        "Receiving {:?} ({} bytes from offset {})",
+        pending.path(),
+        request.size,
+        ready.offset
+    );
+
+    let algorithm = request
+        .digest
+        .as_ref()
+        .map(|digest| digest.algorithm)
+        .unwrap_or_default();
+    let hasher = pending.existing_hasher(algorithm).await?;
+    let progress = ProgressTracker::logging(format!("Receiving {:?}", request.path));
+    progress.start(ready.offset, request.size);
+    let mut writer = HashingWriter::new(progress.writer(&mut pending.file), hasher);
+    let received = recv_encoded_body(
+        stream,
+        &mut writer,
+        request.size - ready.offset,
+        ready.compression,
+    )
+    .await
+    .map_err(|e| RequestError::from_boxed(e.as_ref()));
+    let digest = writer.finalize();
+    let stats = match received {
+        Ok(stats) => stats,
+        Err(e) => {
+            if request.resume && e.code != ErrorCode::Cancelled {
+                pending.suspend().await;
+            } else {
+                pending.abort();
+            }
+            return Err(e.into());
+        }
+    };
+    if let Some(expected) = &request.digest {
+        if let Err(e) = expected.verify(&digest) {
+            pending.abort();
+            return Err(e.into());
+        }
+    }
+    let path = pending.commit().await?;
+    if let Err(e) = apply_metadata(&path, request.mode, request.mtime) {
+        warn!("Failed to apply the metadata of {:?}: {}", path, e);
+    }
+    storage.record_upload(&path, &digest);
+    info!(
+        "Upload complete: {} received into {:?} ({})",
+        stats, path, digest
+    );
+
+    let reply = OkReply {
+        message: format!("Stored {} ({} bytes)", request.path, request.size),
+        path: request.path,
+        digest: Some(digest),
+        stats: Some(stats),
+    };
+    stream.send(Frame::json(Opcode::Ok, &reply)?).await?;
+
+    Ok(())
+}
@@ -0,0 +1,685 @@
+use core::fmt;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use curve25519_dalek::constants::RISTRETTO_BASEPOINT_POINT;
+use curve25519_dalek::ristretto::{CompressedRistretto, RistrettoPoint};
+use curve25519_dalek::scalar::Scalar;
+use hmac::{Hmac, Mac};
+use sha2::{Digest as _, Sha256, Sha512};
+
+use crate::protocol::error::RequestError;
+use crate::protocol::messages::{ConfirmReply, ConfirmRequest, ErrorCode, PairReply, PairRequest};
+
+/// Number of digits in a pairing code.
+const CODE_DIGITS: u32 = 6;
+
+/// Handshakes the server keeps waiting for their confirmation; the oldest is dropped beyond this.
+const MAX_PENDING: usize = 16;
+
+/// How long a handshake waits for its confirmation before it is dropped.
+const PENDING_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How many handshakes one address may start within [`ATTEMPT_WINDOW`].
+const MAX_ATTEMPTS: usize = 5;
+
+/// The window [`MAX_ATTEMPTS`] is counted over.
+const ATTEMPT_WINDOW: Duration = Duration::from_secs(60);
+
+/// How many wrong confirmations, from any address, a code takes before a new one is displayed.
+const MAX_WRONG_GUESSES: u32 = 3;
+
+/// How long a session stays authorized after pairing, unless [`Pairing::with_session_ttl`] says otherwise.
+pub const SESSION_TTL: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// HTTP header carrying the session token on authorized requests.
+pub const SESSION_HEADER: &str = "x-zync-session";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Which side of the handshake a party plays; the two sides blind their messages with different points.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    /// The sender, who types the code.
+    Client,
+    /// The receiver, who displays the code.
+    Server,
+}
+
+impl Role {
+    fn blinding_point(self) -> RistrettoPoint {
+        let label: &[u8] = match self {
+            Role::Client => b"zync SPAKE2 M",
+            Role::Server => b"zync SPAKE2 N",
+        };
+        RistrettoPoint::hash_from_bytes::<Sha512>(label)
+    }
+
+    fn peer(self) -> Role {
+        match self {
+            Role::Client => Role::Server,
+            Role::Server => Role::Client,
+        }
+    }
+
+    fn label(self) -> &'static [u8] {
+        match self {
+            Role::Client => b"client",
+            Role::Server => b"server",
+        }
+    }
+}
+
+/// One side of a SPAKE2 exchange over the Ristretto group, keyed by a pairing code.
+///
+/// Both sides send one message; if they used the same code and see the same server certificate they derive the same [`SessionKey`], otherwise the keys differ and the key confirmation fails. A passive observer learns nothing about the code, and an active attacker gets a single guess per handshake.
+pub struct Spake2 {
+    role: Role,
+    secret: Scalar,
+    password: Scalar,
+    message: [u8; 32],
+}
+
+impl Spake2 {
+    /// Starts a handshake as `role` with the code both sides share.
+    pub fn start(role: Role, code: &str) -> Self {
+        let password = Scalar::hash_from_bytes::<Sha512>(normalize_code(code).as_bytes());
+        let secret = Scalar::from_bytes_mod_order_wide(&rand::random::<[u8; 64]>());
+        let blinded = RISTRETTO_BASEPOINT_POINT * secret + role.blinding_point() * password;
+        Self {
+            role,
+            secret,
+            password,
+            message: blinded.compress().to_bytes(),
+        }
+    }
+
+    /// The message to send to the peer, as hex.
+    pub fn message(&self) -> String {
+        hex(&self.message)
+    }
+
+    /// Combines the peer's message with this side's secret into the session key, bound to the `certificate` fingerprint of the TLS connection the handshake runs over, if any.
+    ///
+    /// The client passes the fingerprint of the certificate it was shown and the server that of its own, so a machine in the middle that terminates TLS with a certificate of its own cannot relay the handshake.
+    ///
+    /// # Errors
+    /// Returns `BadRequest` if the peer's message is not a valid group element.
+    pub fn finish(
+        self,
+        peer_message: &str,
+        certificate: Option<&str>,
+    ) -> Result<SessionKey, RequestError> {
+        let invalid = || RequestError::new(ErrorCode::BadRequest, "Invalid pairing message");
+        let bytes: [u8; 32] = unhex(peer_message)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(invalid)?;
+        let peer = CompressedRistretto(bytes)
+            .decompress()
+            .ok_or_else(invalid)?;
+        let shared = (peer - self.role.peer().blinding_point() * self.password) * self.secret;
+
+        let (client_message, server_message) = match self.role {
+            Role::Client => (self.message, bytes),
+            Role::Server => (bytes, self.message),
+        };
+        let mut transcript = Sha256::new();
+        transcript.update(b"zync pairing v1");
+        transcript.update(client_message);
+        transcript.update(server_message);
+        transcript.update(shared.compress().as_bytes());
+        transcript.update(self.password.as_bytes());
+        transcript.update(certificate.unwrap_or_default().as_bytes());
+        Ok(SessionKey(transcript.finalize().into()))
+    }
+}
+
+/// The key both sides of a successful pairing share.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SessionKey([u8; 32]);
+
+impl fmt::Debug for SessionKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("SessionKey(..)")
+    }
+}
+
+impl SessionKey {
+    fn mac(&self, label: &[u8]) -> String {
+        let mut mac = HmacSha256::new_from_slice(&self.0).expect("HMAC accepts any key length");
+        mac.update(label);
+        hex(&mac.finalize().into_bytes())
+    }
+
+    /// Proof that `role` derived this key, exchanged before the key is trusted.
+    pub fn confirmation(&self, role: Role) -> String {
+        self.mac(role.label())
+    }
+
+    /// Checks the peer's confirmation in constant time.
+    pub fn verify_confirmation(&self, role: Role, confirmation: &str) -> bool {
+        let mut mac = HmacSha256::new_from_slice(&self.0).expect("HMAC accepts any key length");
+        mac.update(role.label());
+        unhex(confirmation).is_some_and(|bytes| mac.verify_slice(&bytes).is_ok())
+    }
+
+    /// The token that authorizes requests once pairing has completed.
+    pub fn token(&self) -> String {
+        self.mac(b"session token")
+    }
+
+    /// Masks the client's user `secret` for its `CONFIRM`, so only the other side of this handshake can read it.
+    pub fn mask_secret(&self, secret: &[u8; 32]) -> String {
+        hex(&self.xor_pad(secret))
+    }
+
+    /// Reads a user secret masked with [`SessionKey::mask_secret`], or `None` if `masked` is malformed.
+    pub fn unmask_secret(&self, masked: &str) -> Option<[u8; 32]> {
+        let masked: [u8; 32] = unhex(masked)?.try_into().ok()?;
+        Some(self.xor_pad(&masked))
+    }
+
+    fn xor_pad(&self, bytes: &[u8; 32]) -> [u8; 32] {
+        let mut mac = HmacSha256::new_from_slice(&self.0).expect("HMAC accepts any key length");
+        mac.update(b"user secret");
+        let pad = mac.finalize().into_bytes();
+        std::array::from_fn(|i| bytes[i] ^ pad[i])
+    }
+}
+
+/// What the server remembers of a client's user secret: its SHA-256, as hex.
+pub fn credential(secret: &[u8; 32]) -> String {
+    hex(&Sha256::digest(secret))
+}
+
+/// A paired client, as the server remembers it.
+#[derive(Debug, Clone)]
+pub struct PairedClient {
+    pub name: String,
+    /// The [`credential`] of the user secret the client proved, which its user name is bound to.
+    pub credential: String,
+    pub token: String,
+    /// When the session stops being authorized.
+    pub expires: Instant,
+}
+
+/// A handshake waiting for the client's confirmation.
+#[derive(Debug)]
+struct Pending {
+    name: String,
+    key: SessionKey,
+    started: Instant,
+}
+
+#[derive(Debug)]
+struct PairingState {
+    code: String,
+    pending: HashMap<String, Pending>,
+    /// When each address started its recent handshakes, oldest first.
+    attempts: HashMap<IpAddr, VecDeque<Instant>>,
+    /// Wrong confirmations of the current code, from any address.
+    wrong_guesses: u32,
+    sessions: HashMap<String, PairedClient>,
+}
+
+impl PairingState {
+    /// Counts a handshake started by `peer`, refusing it if the address started too many lately.
+    fn throttle(&mut self, peer: IpAddr, now: Instant) -> Result<(), RequestError> {
+        let recent = |started: &Instant| now.duration_since(*started) < ATTEMPT_WINDOW;
+        self.attempts
+            .retain(|_, attempts| attempts.back().is_some_and(recent));
+        let attempts = self.attempts.entry(peer).or_default();
+        while attempts.front().is_some_and(|started| !recent(started)) {
+            attempts.pop_front();
+        }
+        if attempts.len() >= MAX_ATTEMPTS {
+            let wait = attempts.front().map_or(ATTEMPT_WINDOW, |oldest| {
+                ATTEMPT_WINDOW.saturating_sub(now.duration_since(*oldest))
+            });
+            return Err(RequestError::new(
+                ErrorCode::TooManyAttempts,
+                format!(
+                    "Too many pairing attempts, try again in {} seconds",
+                    wait.as_secs().max(1)
+                ),
+            ));
+        }
+        attempts.push_back(now);
+        Ok(())
+    }
+
+    /// Makes room for one more pending handshake, dropping those that timed out and then the oldest.
+    fn prune_pending(&mut self, now: Instant) {
+        self.pending
+            .retain(|_, pending| now.duration_since(pending.started) < PENDING_TIMEOUT);
+        if self.pending.len() >= MAX_PENDING {
+            let oldest = self
+                .pending
+                .iter()
+                .min_by_key(|(_, pending)| pending.started)
+                .map(|(session, _)| session.clone());
+            if let Some(oldest) = oldest {
+                self.pending.remove(&oldest);
+            }
+        }
+    }
+
+    /// Displays a new code; handshakes started with the old one can no longer complete.
+    fn replace_code(&mut self) {
+        self.code = generate_code();
+        self.pending.clear();
+        self.wrong_guesses = 0;
+    }
+}
+
+/// The receiving side's pairing state: the one-time code on display and the sessions it has authorized.
+///
+/// Cloning shares the state, so the TCP and HTTP listeners and the TUI all see the same code and sessions. The server only proves it knows the code after the client has, so starting a handshake reveals nothing and a guess at the code can only be checked by confirming it. A wrong confirmation only ends its own handshake, so a client pairing at the same time is not thrown out, but after [`MAX_WRONG_GUESSES`] of them from any address the code is replaced: every guess costs the attacker a handshake, each code only takes a few, and each address may only start a few handshakes a minute. A successful pairing uses the code up.
+///
+/// Over TLS, handshakes are bound to the fingerprint of the server's certificate given with [`Pairing::with_certificate`], so a client only pairs with the server whose certificate it sees.
+///
+/// Sessions stay authorized for the session TTL after pairing, or until they are revoked.
+#[derive(Debug, Clone)]
+pub struct Pairing {
+    state: Arc<Mutex<PairingState>>,
+    session_ttl: Duration,
+    certificate: Option<String>,
+}
+
+impl Default for Pairing {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pairing {
+    /// Pairing state whose sessions last [`SESSION_TTL`].
+    pub fn new() -> Self {
+        Self::with_session_ttl(SESSION_TTL)
+    }
+
+    /// Pairing state whose sessions stay authorized for `session_ttl` after pairing.
+    pub fn with_session_ttl(session_ttl: Duration) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(PairingState {
+                code: generate_code(),
+                pending: HashMap::new(),
+                attempts: HashMap::new(),
+                wrong_guesses: 0,
+                sessions: HashMap::new(),
+            })),
+            session_ttl,
+            certificate: None,
+        }
+    }
+
+    /// Binds the handshakes to `fingerprint`, that of the certificate the server presents over TLS, which clients pass to [`Spake2::finish`] in turn.
+    pub fn with_certificate(self, fingerprint: impl Into<String>) -> Self {
+        Self {
+            certificate: Some(fingerprint.into()),
+            ..self
+        }
+    }
+
+    /// The code to display, formatted as `123-456`.
+    pub fn code(&self) -> String {
+        let code = self.state.lock().unwrap().code.clone();
+        let (head, tail) = code.split_at(code.len() / 2);
+        format!("{head}-{tail}")
+    }
+
+    /// Answers the first pairing message of a client at `peer` with the server's message, keeping the derived key until the client confirms it.
+    ///
+    /// # Errors
+    /// Returns `TooManyAttempts` if `peer` started too many handshakes lately, or `BadRequest` if the client's message is malformed.
+    pub fn respond(&self, request: &PairRequest, peer: IpAddr) -> Result<PairReply, RequestError> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        state.throttle(peer, now)?;
+        let spake = Spake2::start(Role::Server, &state.code);
+        let message = spake.message();
+        let key = spake.finish(&request.message, self.certificate.as_deref())?;
+        let session = hex(&rand::random::<[u8; 16]>());
+        state.prune_pending(now);
+        state.pending.insert(
+            session.clone(),
+            Pending {
+                name: request.name.clone(),
+                key,
+                started: now,
+            },
+        );
+        Ok(PairReply {
+            session,
+            message,
+            http: None,
+        })
+    }
+
+    /// Completes a pairing started with [`Pairing::respond`], using up the displayed code if it succeeds.
+    ///
+    /// A wrong confirmation only ends this handshake, until the code has taken [`MAX_WRONG_GUESSES`] of them and is replaced along with every pending handshake.
+    ///
+    /// # Returns
+    /// The newly paired client, and the reply carrying the server's own key confirmation.
+    ///
+    /// # Errors
+    /// Returns `Unauthorized` if the session is unknown or timed out, or if the client's confirmation does not match, which means it used the wrong code or saw another certificate, and `BadRequest` if its masked user secret is malformed.
+    pub fn confirm(
+        &self,
+        request: &ConfirmRequest,
+    ) -> Result<(PairedClient, ConfirmReply), RequestError> {
+        let mut state = self.state.lock().unwrap();
+        let pending = state
+            .pending
+            .remove(&request.session)
+            .filter(|pending| pending.started.elapsed() < PENDING_TIMEOUT)
+            .ok_or_else(|| RequestError::new(ErrorCode::Unauthorized, "Unknown pairing session"))?;
+        if !pending
+            .key
+            .verify_confirmation(Role::Client, &request.confirmation)
+        {
+            state.wrong_guesses += 1;
+            if state.wrong_guesses >= MAX_WRONG_GUESSES {
+                log::warn!(
+                    "Pairing attempt from {} used a wrong code, {} wrong guesses at it; displaying a new one",
+                    pending.name,
+                    state.wrong_guesses
+                );
+                state.replace_code();
+            } else {
+                log::warn!("Pairing attempt from {} used a wrong code", pending.name);
+            }
+            return Err(RequestError::new(
+                ErrorCode::Unauthorized,
+                "Wrong pairing code",
+            ));
+        }
+        state.replace_code();
+
+        let secret = pending
+            .key
+            .unmask_secret(&request.secret)
+            .ok_or_else(|| RequestError::new(ErrorCode::BadRequest, "Invalid user secret"))?;
+
+        let client = PairedClient {
+            name: pending.name,
+            credential: credential(&secret),
+            token: pending.key.token(),
+            expires: Instant::now() + self.session_ttl,
+        };
+        log::info!("Paired with {}", client.name);
+        state.sessions.insert(client.token.clone(), client.clone());
+        let reply = ConfirmReply {
+            name: client.name.clone(),
+            message: format!("Paired with {}", client.name),
+            confirmation: pending.key.confirmation(Role::Server),
+        };
+        Ok((client, reply))
+    }
+
+    /// Looks up the client a session token belongs to.
+    ///
+    /// # Errors
+    /// Returns `Unauthorized` if the token is missing, was not issued by this server, has expired or was revoked.
+    pub fn authorize(&self, token: Option<&str>) -> Result<PairedClient, RequestError> {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        state.sessions.retain(|_, client| client.expires > now);
+        token
+            .and_then(|token| state.sessions.get(token))
+            .cloned()
+            .ok_or_else(not_paired)
+    }
+
+    /// Ends the session `token` authorizes; requests carrying it are refused from now on.
+    ///
+    /// # Returns
+    /// `true` if the session existed.
+    pub fn revoke(&self, token: &str) -> bool {
+        self.state.lock().unwrap().sessions.remove(token).is_some()
+    }
+
+    /// Ends every session, so each client has to pair again.
+    pub fn revoke_all(&self) {
+        self.state.lock().unwrap().sessions.clear();
+    }
+}
+
+/// The error returned to clients that send requests before pairing.
+pub fn not_paired() -> RequestError {
+    RequestError::new(
+        ErrorCode::Unauthorized,
+        "Not paired: enter the pairing code shown on the receiving side",
+    )
+}
+
+fn generate_code() -> String {
+    let code = rand::random_range(0..10u32.pow(CODE_DIGITS));
+    format!("{:0width$}", code, width = CODE_DIGITS as usize)
+}
+
+/// Strips the separators people type between the code's digits.
+fn normalize_code(code: &str) -> String {
+    code.chars().filter(char::is_ascii_digit).collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const PEER: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    fn exchange(client_code: &str, server_code: &str) -> (SessionKey, SessionKey) {
+        let client = Spake2::start(Role::Client, client_code);
+        let server = Spake2::start(Role::Server, server_code);
+        let client_message = client.message();
+        let server_message = server.message();
+        (
+            client.finish(&server_message, None).unwrap(),
+            server.finish(&client_message, None).unwrap(),
+        )
+    }
+
+    /// Starts a handshake with `pairing` using `code`, returning the client's key and its confirmation.
+    fn start(pairing: &Pairing, code: &str, peer: IpAddr) -> (SessionKey, ConfirmRequest) {
+        start_over(pairing, code, peer, None)
+    }
+
+    /// Starts a handshake like [`start`] over a TLS connection on which the client was shown `certificate`.
+    fn start_over(
+        pairing: &Pairing,
+        code: &str,
+        peer: IpAddr,
+        certificate: Option<&str>,
+    ) -> (SessionKey, ConfirmRequest) {
+        let client = Spake2::start(Role::Client, code);
+        let request = PairRequest {
+            name: "alice".to_string(),
+            message: client.message(),
+        };
+        let reply = pairing.respond(&request, peer).unwrap();
+        let key = client.finish(&reply.message, certificate).unwrap();
+        let confirm = ConfirmRequest {
+            session: reply.session,
+            confirmation: key.confirmation(Role::Client),
+            secret: key.mask_secret(&[7; 32]),
+        };
+        (key, confirm)
+    }
+
+    #[test]
+    fn the_same_code_derives_matching_keys_and_confirmations() {
+        let (client, server) = exchange("123-456", "123456");
+        assert_eq!(client, server);
+        assert!(server.verify_confirmation(Role::Client, &client.confirmation(Role::Client)));
+        assert!(client.verify_confirmation(Role::Server, &server.confirmation(Role::Server)));
+        assert_eq!(client.token(), server.token());
+    }
+
+    #[test]
+    fn confirmations_are_bound_to_the_role() {
+        let (client, server) = exchange("123456", "123456");
+        assert!(!server.verify_confirmation(Role::Client, &client.confirmation(Role::Server)));
+    }
+
+    #[test]
+    fn different_codes_fail_to_confirm() {
+        let (client, server) = exchange("123456", "654321");
+        assert_ne!(client, server);
+        assert!(!server.verify_confirmation(Role::Client, &client.confirmation(Role::Client)));
+        assert!(!client.verify_confirmation(Role::Server, &server.confirmation(Role::Server)));
+    }
+
+    #[test]
+    fn malformed_peer_messages_are_rejected() {
+        let not_a_point = hex(&[0xff; 32]);
+        for message in ["", "zz", "abc", &"00".repeat(31), &not_a_point] {
+            let error = Spake2::start(Role::Server, "123456")
+                .finish(message, None)
+                .unwrap_err();
+            assert_eq!(error.code, ErrorCode::BadRequest, "{message:?}");
+        }
+    }
+
+    #[test]
+    fn pairs_with_the_displayed_code() {
+        let pairing = Pairing::new();
+        let (key, confirm) = start(&pairing, &pairing.code(), PEER);
+        let (client, reply) = pairing.confirm(&confirm).unwrap();
+        assert_eq!(client.name, "alice");
+        assert_eq!(client.credential, credential(&[7; 32]));
+        assert_eq!(client.token, key.token());
+        assert!(key.verify_confirmation(Role::Server, &reply.confirmation));
+        assert_eq!(pairing.authorize(Some(&key.token())).unwrap().name, "alice");
+        assert!(pairing.authorize(None).is_err());
+    }
+
+    #[test]
+    fn user_secrets_are_only_readable_with_the_same_key() {
+        let (client, server) = exchange("123456", "123456");
+        let (other, _) = exchange("123456", "123456");
+        let masked = client.mask_secret(&[7; 32]);
+        assert_ne!(masked, hex(&[7; 32]));
+        assert_eq!(server.unmask_secret(&masked), Some([7; 32]));
+        assert_ne!(other.unmask_secret(&masked), Some([7; 32]));
+        assert_eq!(server.unmask_secret("abcd"), None);
+    }
+
+    #[test]
+    fn starting_a_handshake_keeps_the_code() {
+        let pairing = Pairing::new();
+        let code = pairing.code();
+        start(&pairing, "000000", PEER);
+        assert_eq!(pairing.code(), code);
+    }
+
+    /// A code other than `code`.
+    fn wrong(code: &str) -> &'static str {
+        if normalize_code(code) == "000000" {
+            "111111"
+        } else {
+            "000000"
+        }
+    }
+
+    #[test]
+    fn a_wrong_confirmation_only_ends_its_own_handshake() {
+        let pairing = Pairing::new();
+        let code = pairing.code();
+        let (_, honest) = start(&pairing, &code, PEER);
+        let (_, guess) = start(&pairing, wrong(&code), PEER);
+
+        let error = pairing.confirm(&guess).unwrap_err();
+        assert_eq!(error.code, ErrorCode::Unauthorized);
+        assert_eq!(pairing.code(), code);
+        assert!(pairing.confirm(&guess).is_err());
+        assert!(pairing.confirm(&honest).is_ok());
+        assert_ne!(pairing.code(), code);
+    }
+
+    #[test]
+    fn the_code_is_replaced_after_too_many_wrong_guesses_from_any_address() {
+        let pairing = Pairing::new();
+        let code = pairing.code();
+        let (_, honest) = start(&pairing, &code, PEER);
+        for i in 0..MAX_WRONG_GUESSES {
+            assert_eq!(pairing.code(), code);
+            let peer = IpAddr::V4(Ipv4Addr::new(10, 0, 0, i as u8 + 2));
+            let (_, guess) = start(&pairing, wrong(&code), peer);
+            assert!(pairing.confirm(&guess).is_err());
+        }
+        assert_ne!(pairing.code(), code);
+        assert!(pairing.confirm(&honest).is_err());
+
+        let (_, confirm) = start(&pairing, &pairing.code(), PEER);
+        assert!(pairing.confirm(&confirm).is_ok());
+    }
+
+    #[test]
+    fn handshakes_are_bound_to_the_server_certificate() {
+        let pairing = Pairing::new().with_certificate("ab".repeat(32));
+        for shown in [None, Some("cd".repeat(32))] {
+            let (_, confirm) = start_over(&pairing, &pairing.code(), PEER, shown.as_deref());
+            let error = pairing.confirm(&confirm).unwrap_err();
+            assert_eq!(error.code, ErrorCode::Unauthorized);
+        }
+        let shown = "ab".repeat(32);
+        let (key, confirm) = start_over(&pairing, &pairing.code(), PEER, Some(&shown));
+        let (_, reply) = pairing.confirm(&confirm).unwrap();
+        assert!(key.verify_confirmation(Role::Server, &reply.confirmation));
+    }
+
+    #[test]
+    fn pairing_attempts_are_limited_per_address() {
+        let pairing = Pairing::new();
+        for _ in 0..MAX_ATTEMPTS {
+            start(&pairing, "123456", PEER);
+        }
+        let request = PairRequest {
+            name: "mallory".to_string(),
+            message: Spake2::start(Role::Client, "123456").message(),
+        };
+        let error = pairing.respond(&request, PEER).unwrap_err();
+        assert_eq!(error.code, ErrorCode::TooManyAttempts);
+
+        let other = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+        let (_, confirm) = start(&pairing, &pairing.code(), other);
+        assert!(pairing.confirm(&confirm).is_ok());
+    }
+
+    #[test]
+    fn expired_sessions_are_refused() {
+        let pairing = Pairing::with_session_ttl(Duration::ZERO);
+        let (key, confirm) = start(&pairing, &pairing.code(), PEER);
+        pairing.confirm(&confirm).unwrap();
+        assert!(pairing.authorize(Some(&key.token())).is_err());
+    }
+
+    #[test]
+    fn revoked_sessions_are_refused() {
+        let pairing = Pairing::new();
+        let (key, confirm) = start(&pairing, &pairing.code(), PEER);
+        pairing.confirm(&confirm).unwrap();
+        assert!(pairing.revoke(&key.token()));
+        assert!(pairing.authorize(Some(&key.token())).is_err());
+        assert!(!pairing.revoke(&key.token()));
+    }
+}
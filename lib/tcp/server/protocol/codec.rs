@@ -0,0 +1,173 @@
+use bytes::{Buf, BufMut, BytesMut};
+use std::io;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::{Decoder, Encoder, Framed};
+
+use super::frame::{Frame, HEADER_LEN, MAX_PAYLOAD_LEN, Opcode, PROTOCOL_VERSION};
+
+/// A byte stream the framed protocol can run over, such as a plain `TcpStream` or a TLS stream wrapping one.
+pub trait Transport: AsyncRead + AsyncWrite + Send + Unpin {}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> Transport for T {}
+
+/// A connection speaking the framed zync protocol, over plain TCP or TLS.
+pub type FramedStream = Framed<Box<dyn Transport>, FrameCodec>;
+
+/// Encodes and decodes [`Frame`]s as `[version: u8][opcode: u8][len: u32 BE][payload]`.
+///
+/// The decoder keeps whatever bytes it has not consumed yet, so a frame split across several
+/// reads is reassembled and several frames arriving in a single read are yielded one by one.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FrameCodec;
+
+impl FrameCodec {
+    pub fn framed<T: Transport + 'static>(stream: T) -> FramedStream {
+        Framed::new(Box::new(stream), FrameCodec)
+    }
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl Decoder for FrameCodec {
+    type Item = Frame;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Frame>, io::Error> {
+        if src.len() < HEADER_LEN {
+            src.reserve(HEADER_LEN - src.len());
+            return Ok(None);
+        }
+
+        let version = src[0];
+        if version != PROTOCOL_VERSION {
+            return Err(invalid_data(format!(
+                "Unsupported protocol version {version} (expected {PROTOCOL_VERSION})"
+            )));
+        }
+
+        let opcode = Opcode::from_u8(src[1])
+            .ok_or_else(|| invalid_data(format!("Unknown opcode 0x{:02x}", src[1])))?;
+
+        let len = u32::from_be_bytes([src[2], src[3], src[4], src[5]]) as usize;
+        if len > MAX_PAYLOAD_LEN {
+            return Err(invalid_data(format!(
+                "Frame payload of {len} bytes exceeds the {MAX_PAYLOAD_LEN} byte limit"
+            )));
+        }
+
+        if src.len() < HEADER_LEN + len {
+            src.reserve(HEADER_LEN + len - src.len());
+            return Ok(None);
+        }
+
+        src.advance(HEADER_LEN);
+        let payload = src.split_to(len).freeze();
+        Ok(Some(Frame { opcode, payload }))
+    }
+}
+
+impl Encoder<Frame> for FrameCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, frame: Frame, dst: &mut BytesMut) -> Result<(), io::Error> {
+        let len = frame.payload.len();
+        if len > MAX_PAYLOAD_LEN {
+            return Err(invalid_data(format!(
+                "Frame payload of {len} bytes exceeds the {MAX_PAYLOAD_LEN} byte limit"
+            )));
+        }
+
+        dst.reserve(HEADER_LEN + len);
+        dst.put_u8(PROTOCOL_VERSION);
+        dst.put_u8(frame.opcode as u8);
+        dst.put_u32(len as u32);
+        dst.extend_from_slice(&frame.payload);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(frame: Frame) -> BytesMut {
+        let mut buf = BytesMut::new();
+        FrameCodec.encode(frame, &mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn round_trips_a_frame() {
+        let frame = Frame::new(Opcode::Get, &b"{\"path\":\"a.txt\"}"[..]);
+        let mut buf = encode(frame.clone());
+
+        assert_eq!(FrameCodec.decode(&mut buf).unwrap(), Some(frame));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_a_partial_frame() {
+        let frame = Frame::new(Opcode::Data, vec![7u8; 300]);
+        let encoded = encode(frame.clone());
+        let mut buf = BytesMut::new();
+
+        for (i, byte) in encoded.iter().enumerate() {
+            buf.put_u8(*byte);
+            let decoded = FrameCodec.decode(&mut buf).unwrap();
+            if i + 1 < encoded.len() {
+                assert_eq!(decoded, None);
+            } else {
+                assert_eq!(decoded, Some(frame.clone()));
+            }
+        }
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn splits_coalesced_frames() {
+        let first = Frame::new(Opcode::Send, &b"{\"path\":\"a\",\"size\":3}"[..]);
+        let second = Frame::new(Opcode::Data, &b"abc"[..]);
+        let third = Frame::empty(Opcode::List);
+
+        let mut buf = encode(first.clone());
+        buf.extend_from_slice(&encode(second.clone()));
+        let third_bytes = encode(third.clone());
+        buf.extend_from_slice(&third_bytes[..3]);
+
+        assert_eq!(FrameCodec.decode(&mut buf).unwrap(), Some(first));
+        assert_eq!(FrameCodec.decode(&mut buf).unwrap(), Some(second));
+        assert_eq!(FrameCodec.decode(&mut buf).unwrap(), None);
+
+        buf.extend_from_slice(&third_bytes[3..]);
+        assert_eq!(FrameCodec.decode(&mut buf).unwrap(), Some(third));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn rejects_unknown_version() {
+        let mut buf = encode(Frame::empty(Opcode::List));
+        buf[0] = PROTOCOL_VERSION + 1;
+
+        assert!(FrameCodec.decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_opcode() {
+        let mut buf = encode(Frame::empty(Opcode::List));
+        buf[1] = 0xff;
+
+        assert!(FrameCodec.decode(&mut buf).is_err());
+    }
+
+    #[test]
+    fn rejects_oversized_payload_length() {
+        let mut buf = BytesMut::new();
+        buf.put_u8(PROTOCOL_VERSION);
+        buf.put_u8(Opcode::Data as u8);
+        buf.put_u32(MAX_PAYLOAD_LEN as u32 + 1);
+
+        assert!(FrameCodec.decode(&mut buf).is_err());
+    }
+}
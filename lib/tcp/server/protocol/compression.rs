@@ -0,0 +1,418 @@
+use core::fmt;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+
+use async_compression::tokio::bufread::ZstdEncoder;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncWrite, BufReader};
+
+use super::error::RequestError;
+use super::messages::ErrorCode;
+
+/// Files smaller than this are sent as they are; compressing them saves too little to be worth it.
+pub const MIN_COMPRESSED_SIZE: u64 = 4 * 1024;
+
+/// Extensions of formats that are already compressed, which zstd cannot shrink any further.
+const COMPRESSED_EXTENSIONS: &[&str] = &[
+    "7z", "aac", "apk", "avi", "br", "bz2", "dmg", "docx", "flac", "gif", "gz", "heic", "iso",
+    "jar", "jpeg", "jpg", "lz4", "m4a", "mkv", "mov", "mp3", "mp4", "odt", "ogg", "pdf", "png",
+    "pptx", "rar", "tgz", "webm", "webp", "woff2", "xlsx", "xz", "zip", "zst",
+];
+
+/// How a transfer's body is encoded on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Compression {
+    /// The file's bytes as they are.
+    #[default]
+    None,
+    /// A single zstd frame of the file's bytes.
+    Zstd,
+}
+
+/// How many bytes of a file a transfer covered, and how many it took on the wire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct TransferStats {
+    /// Bytes of the file that were transferred.
+    pub original: u64,
+    /// Bytes of body sent over the connection for them.
+    pub transferred: u64,
+    pub compression: Compression,
+}
+
+/// An [`AsyncWrite`] adapter that fails once more than `limit` bytes are written through it.
+///
+/// Decompressed bodies are written through it, so a small compressed body cannot expand past the size its transfer announced. Shutting it down only flushes the inner writer, which stays usable.
+#[derive(Debug)]
+pub struct LimitedWriter<W> {
+    inner: W,
+    limit: u64,
+    written: u64,
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Compression::None => write!(f, "identity"),
+            Compression::Zstd => write!(f, "zstd"),
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = RequestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "" | "identity" => Ok(Compression::None),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(RequestError::new(
+                ErrorCode::BadRequest,
+                format!("Unsupported content encoding {s}"),
+            )),
+        }
+    }
+}
+
+impl Compression {
+    /// Picks the compression for sending `size` bytes of the file at `path`.
+    ///
+    /// Small files and files whose extension marks an already compressed format are sent as they are; everything else is compressed with zstd.
+    pub fn for_path(path: impl AsRef<Path>, size: u64) -> Self {
+        if size < MIN_COMPRESSED_SIZE {
+            return Compression::None;
+        }
+        let compressed = path
+            .as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                COMPRESSED_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str())
+            });
+        if compressed {
+            Compression::None
+        } else {
+            Compression::Zstd
+        }
+    }
+
+    /// Narrows what a peer accepts, `self`, down to what is worth using for `size` bytes of `path`.
+    pub fn negotiate(self, path: impl AsRef<Path>, size: u64) -> Self {
+        match self {
+            Compression::None => Compression::None,
+            Compression::Zstd => Compression::for_path(path, size),
+        }
+    }
+
+    pub fn is_compressed(self) -> bool {
+        self != Compression::None
+    }
+}
+
+impl TransferStats {
+    /// Stats of `size` bytes sent as they are.
+    pub fn uncompressed(size: u64) -> Self {
+        Self {
+            original: size,
+            transferred: size,
+            compression: Compression::None,
+        }
+    }
+}
+
+impl fmt::Display for TransferStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.compression.is_compressed() {
+            write!(
+                f,
+                "{} → {} bytes ({})",
+                self.original, self.transferred, self.compression
+            )
+        } else {
+            write!(f, "{} bytes", self.original)
+        }
+    }
+}
+
+impl std::iter::Sum for TransferStats {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(TransferStats::default(), |total, stats| TransferStats {
+            original: total.original + stats.original,
+            transferred: total.transferred + stats.transferred,
+            compression: if stats.compression.is_compressed() {
+                stats.compression
+            } else {
+                total.compression
+            },
+        })
+    }
+}
+
+/// The most bytes a zstd body of `size` decompressed bytes may take on the wire.
+///
+/// zstd grows incompressible input by a small, bounded amount; anything past this bound is not a genuine body.
+pub fn max_compressed_len(size: u64) -> u64 {
+    size + size / 64 + 64 * 1024
+}
+
+/// Wraps `reader` so that reading it yields its bytes encoded with `compression`.
+pub fn encode_reader<R>(reader: R, compression: Compression) -> Box<dyn AsyncRead + Send + Unpin>
+where
+    R: AsyncRead + Send + Unpin + 'static,
+{
+    match compression {
+        Compression::None => Box::new(reader),
+        Compression::Zstd => Box::new(ZstdEncoder::new(BufReader::new(reader))),
+    }
+}
+
+impl<W> LimitedWriter<W> {
+    pub fn new(inner: W, limit: u64) -> Self {
+        Self {
+            inner,
+            limit,
+            written: 0,
+        }
+    }
+
+    /// How many bytes were written through the adapter.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for LimitedWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        if this.written + buf.len() as u64 > this.limit {
+            return Poll::Ready(Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Body decompresses past the announced {} bytes", this.limit),
+            )));
+        }
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            this.written += written as u64;
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::codec::{FrameCodec, FramedStream};
+    use crate::protocol::frame::{Frame, Opcode};
+    use crate::protocol::transfer::{recv_encoded_body, send_encoded_body};
+    use async_compression::tokio::write::ZstdDecoder;
+    use futures_util::SinkExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Compressible bytes: a repeating text pattern.
+    fn text(size: usize) -> Vec<u8> {
+        b"zync compresses text well. "
+            .iter()
+            .copied()
+            .cycle()
+            .take(size)
+            .collect()
+    }
+
+    async fn encode(data: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        encode_reader(io::Cursor::new(data.to_vec()), Compression::Zstd)
+            .read_to_end(&mut encoded)
+            .await
+            .unwrap();
+        encoded
+    }
+
+    fn streams() -> (FramedStream, FramedStream) {
+        let (a, b) = tokio::io::duplex(64 * 1024);
+        (FrameCodec::framed(a), FrameCodec::framed(b))
+    }
+
+    #[test]
+    fn small_files_are_sent_uncompressed() {
+        assert_eq!(
+            Compression::Zstd.negotiate("notes.txt", MIN_COMPRESSED_SIZE - 1),
+            Compression::None
+        );
+        assert_eq!(
+            Compression::Zstd.negotiate("notes.txt", MIN_COMPRESSED_SIZE),
+            Compression::Zstd
+        );
+        assert_eq!(
+            Compression::Zstd.negotiate("notes.txt", 0),
+            Compression::None
+        );
+    }
+
+    #[test]
+    fn skip_listed_extensions_are_sent_uncompressed() {
+        for name in [
+            "photo.jpg",
+            "photo.JPG",
+            "archive.tar.gz",
+            "film.mkv",
+            "a/b/c.zip",
+        ] {
+            assert_eq!(
+                Compression::Zstd.negotiate(name, 1 << 20),
+                Compression::None,
+                "{name}"
+            );
+        }
+        for name in ["notes.txt", "data.csv", "Makefile", "archive.tar"] {
+            assert_eq!(
+                Compression::Zstd.negotiate(name, 1 << 20),
+                Compression::Zstd,
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn a_peer_without_zstd_gets_identity() {
+        assert_eq!(
+            Compression::None.negotiate("notes.txt", 1 << 20),
+            Compression::None
+        );
+    }
+
+    #[test]
+    fn encodings_parse_from_headers() {
+        assert_eq!("zstd".parse::<Compression>().unwrap(), Compression::Zstd);
+        assert_eq!(" ZSTD ".parse::<Compression>().unwrap(), Compression::Zstd);
+        assert_eq!(
+            "identity".parse::<Compression>().unwrap(),
+            Compression::None
+        );
+        assert_eq!("".parse::<Compression>().unwrap(), Compression::None);
+        let error = "gzip".parse::<Compression>().unwrap_err();
+        assert_eq!(error.code, ErrorCode::BadRequest);
+    }
+
+    #[test]
+    fn compressed_bodies_are_bounded() {
+        assert!(max_compressed_len(0) > 0);
+        assert!(max_compressed_len(1 << 20) > 1 << 20);
+        assert!(max_compressed_len(1 << 20) < 2 << 20);
+    }
+
+    #[tokio::test]
+    async fn limited_writer_rejects_writes_past_its_limit() {
+        let mut writer = LimitedWriter::new(Vec::new(), 8);
+        writer.write_all(b"12345678").await.unwrap();
+        assert_eq!(writer.written(), 8);
+        let error = writer.write_all(b"9").await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(writer.written(), 8);
+        assert_eq!(writer.inner, b"12345678");
+    }
+
+    #[tokio::test]
+    async fn decompression_cannot_expand_past_the_announced_size() {
+        let bomb = encode(&vec![0; 1 << 20]).await;
+        assert!(bomb.len() < 4096);
+        let mut decoder = ZstdDecoder::new(LimitedWriter::new(Vec::new(), 1024));
+        let result = async {
+            decoder.write_all(&bomb).await?;
+            decoder.shutdown().await
+        }
+        .await;
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(decoder.get_ref().written() <= 1024);
+    }
+
+    #[tokio::test]
+    async fn compressed_data_round_trips() {
+        let data = text(256 * 1024);
+        let encoded = encode(&data).await;
+        assert!(encoded.len() < data.len() / 10);
+        let mut decoder = ZstdDecoder::new(LimitedWriter::new(Vec::new(), data.len() as u64));
+        decoder.write_all(&encoded).await.unwrap();
+        decoder.shutdown().await.unwrap();
+        assert_eq!(decoder.get_ref().inner, data);
+    }
+
+    #[tokio::test]
+    async fn compressed_bodies_round_trip_over_frames() {
+        let data = text(300 * 1024);
+        let size = data.len() as u64;
+        let (mut sender, mut receiver) = streams();
+        let mut reader = data.as_slice();
+        let mut received = Vec::new();
+        let (sent, got) = tokio::join!(
+            send_encoded_body(&mut sender, &mut reader, size, Compression::Zstd),
+            recv_encoded_body(&mut receiver, &mut received, size, Compression::Zstd)
+        );
+        let (sent, got) = (sent.unwrap(), got.unwrap());
+        assert_eq!(received, data);
+        assert_eq!(sent, got);
+        assert_eq!(got.original, size);
+        assert!(got.transferred < size);
+        assert_eq!(got.compression, Compression::Zstd);
+    }
+
+    #[tokio::test]
+    async fn bodies_that_decode_past_their_size_are_rejected() {
+        let data = text(64 * 1024);
+        let (mut sender, mut receiver) = streams();
+        let mut reader = data.as_slice();
+        let mut received = Vec::new();
+        let (_, got) = tokio::join!(
+            send_encoded_body(
+                &mut sender,
+                &mut reader,
+                data.len() as u64,
+                Compression::Zstd
+            ),
+            recv_encoded_body(&mut receiver, &mut received, 1024, Compression::Zstd)
+        );
+        assert!(got.is_err());
+        assert!(received.len() <= 1024);
+    }
+
+    #[tokio::test]
+    async fn bodies_longer_than_the_bound_on_the_wire_are_rejected() {
+        // A zstd skippable frame carries bytes that decode to nothing, so only the bound on its length stops it.
+        let padding = 100 * 1024u32;
+        let mut encoded = 0x184D_2A50u32.to_le_bytes().to_vec();
+        encoded.extend(padding.to_le_bytes());
+        encoded.resize(encoded.len() + padding as usize, 0);
+        let size = 4096;
+        assert!(encoded.len() as u64 > max_compressed_len(size));
+        let (mut sender, mut receiver) = streams();
+        let send = tokio::spawn(async move {
+            for chunk in encoded.chunks(16 * 1024) {
+                sender
+                    .send(Frame::new(Opcode::Data, chunk.to_vec()))
+                    .await?;
+            }
+            sender.send(Frame::new(Opcode::Data, Vec::new())).await
+        });
+        let mut sink = tokio::io::sink();
+        let error = recv_encoded_body(&mut receiver, &mut sink, size, Compression::Zstd)
+            .await
+            .unwrap_err();
+        drop(receiver);
+        let _ = send.await;
+        assert!(error.to_string().contains("exceeds"), "{error}");
+    }
+}
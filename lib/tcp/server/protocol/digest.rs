@@ -0,0 +1,319 @@
+use core::fmt;
+use std::io;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+
+use serde::{Deserialize, Serialize};
+use sha2::Digest as _;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite};
+
+use super::error::RequestError;
+use super::messages::ErrorCode;
+
+/// HTTP header carrying a [`Digest`] as `<algorithm>=<hex>`, on uploads and on download responses.
+pub const DIGEST_HEADER: &str = "x-content-digest";
+
+/// Hash function used to verify transferred content.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum HashAlgorithm {
+    #[default]
+    #[serde(rename = "blake3")]
+    Blake3,
+    #[serde(rename = "sha-256")]
+    Sha256,
+}
+
+/// The digest of a file's full contents, as a lowercase hex string.
+///
+/// Deserializing goes through the same validation as [`FromStr`], from either the `{algorithm, value}` form it serializes to or the `<algorithm>=<hex>` string form.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "RawDigest")]
+pub struct Digest {
+    pub algorithm: HashAlgorithm,
+    pub value: String,
+}
+
+/// A [`Digest`] as received, before its value is checked to be hex.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDigest {
+    Text(String),
+    Fields {
+        algorithm: HashAlgorithm,
+        value: String,
+    },
+}
+
+/// Incremental hasher for either [`HashAlgorithm`].
+#[derive(Debug, Clone)]
+pub enum Hasher {
+    Blake3(Box<blake3::Hasher>),
+    Sha256(sha2::Sha256),
+}
+
+/// An [`AsyncWrite`] adapter that hashes every byte written through it.
+#[derive(Debug)]
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: Hasher,
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            HashAlgorithm::Blake3 => write!(f, "blake3"),
+            HashAlgorithm::Sha256 => write!(f, "sha-256"),
+        }
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = RequestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "blake3" => Ok(HashAlgorithm::Blake3),
+            "sha-256" | "sha256" => Ok(HashAlgorithm::Sha256),
+            _ => Err(RequestError::new(
+                ErrorCode::BadRequest,
+                format!("Unsupported digest algorithm {s}"),
+            )),
+        }
+    }
+}
+
+impl Digest {
+    /// Builds a digest from its `algorithm` and hex `value`.
+    ///
+    /// # Errors
+    /// Returns `BadRequest` if `value` is empty or not hex encoded.
+    pub fn new(algorithm: HashAlgorithm, value: &str) -> Result<Self, RequestError> {
+        if value.is_empty() || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(RequestError::new(
+                ErrorCode::BadRequest,
+                "Digest value must be hex encoded",
+            ));
+        }
+        Ok(Digest {
+            algorithm,
+            value: value.to_ascii_lowercase(),
+        })
+    }
+
+    /// The first 16 characters of the value, for display in narrow columns.
+    pub fn short(&self) -> &str {
+        let end = self
+            .value
+            .char_indices()
+            .nth(16)
+            .map_or(self.value.len(), |(index, _)| index);
+        &self.value[..end]
+    }
+
+    /// Checks that `actual`, computed over the received bytes, matches this announced digest.
+    ///
+    /// # Errors
+    /// Returns `DigestMismatch` if the algorithms or values differ.
+    pub fn verify(&self, actual: &Digest) -> Result<(), RequestError> {
+        if self == actual {
+            return Ok(());
+        }
+        Err(RequestError::new(
+            ErrorCode::DigestMismatch,
+            format!("Content digest mismatch: expected {self}, got {actual}"),
+        ))
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}={}", self.algorithm, self.value)
+    }
+}
+
+impl FromStr for Digest {
+    type Err = RequestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (algorithm, value) = s.trim().split_once('=').ok_or_else(|| {
+            RequestError::new(
+                ErrorCode::BadRequest,
+                "Digest must look like <algorithm>=<hex>",
+            )
+        })?;
+        Digest::new(algorithm.parse()?, value)
+    }
+}
+
+impl TryFrom<RawDigest> for Digest {
+    type Error = RequestError;
+
+    fn try_from(raw: RawDigest) -> Result<Self, Self::Error> {
+        match raw {
+            RawDigest::Text(text) => text.parse(),
+            RawDigest::Fields { algorithm, value } => Digest::new(algorithm, &value),
+        }
+    }
+}
+
+impl Hasher {
+    pub fn new(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Blake3 => Hasher::Blake3(Box::default()),
+            HashAlgorithm::Sha256 => Hasher::Sha256(sha2::Sha256::new()),
+        }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        match self {
+            Hasher::Blake3(hasher) => {
+                hasher.update(bytes);
+            }
+            Hasher::Sha256(hasher) => hasher.update(bytes),
+        }
+    }
+
+    /// Feeds everything `reader` yields until it ends.
+    ///
+    /// # Returns
+    /// The number of bytes hashed.
+    pub async fn update_reader<R>(&mut self, reader: &mut R) -> io::Result<u64>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let mut buffer = vec![0u8; super::transfer::CHUNK_SIZE];
+        let mut total = 0u64;
+        loop {
+            let read = reader.read(&mut buffer).await?;
+            if read == 0 {
+                return Ok(total);
+            }
+            self.update(&buffer[..read]);
+            total += read as u64;
+        }
+    }
+
+    pub fn finalize(self) -> Digest {
+        match self {
+            Hasher::Blake3(hasher) => Digest {
+                algorithm: HashAlgorithm::Blake3,
+                value: hasher.finalize().to_hex().to_string(),
+            },
+            Hasher::Sha256(hasher) => Digest {
+                algorithm: HashAlgorithm::Sha256,
+                value: hasher
+                    .finalize()
+                    .iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect(),
+            },
+        }
+    }
+}
+
+/// Hashes everything `reader` yields with `algorithm`.
+pub async fn digest_reader<R>(reader: &mut R, algorithm: HashAlgorithm) -> io::Result<Digest>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut hasher = Hasher::new(algorithm);
+    hasher.update_reader(reader).await?;
+    Ok(hasher.finalize())
+}
+
+impl<W> HashingWriter<W> {
+    pub fn new(inner: W, hasher: Hasher) -> Self {
+        Self { inner, hasher }
+    }
+
+    /// Finishes hashing and returns the digest of everything fed to the hasher.
+    pub fn finalize(self) -> Digest {
+        self.hasher.finalize()
+    }
+
+    /// Finishes hashing and hands back the inner writer along with the digest.
+    pub fn into_parts(self) -> (W, Digest) {
+        (self.inner, self.hasher.finalize())
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for HashingWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            this.hasher.update(&buf[..written]);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn blake3_of(bytes: &[u8]) -> Digest {
+        let mut hasher = Hasher::new(HashAlgorithm::Blake3);
+        hasher.update(bytes);
+        hasher.finalize()
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let digest = blake3_of(b"zync");
+        let json = serde_json::to_string(&digest).unwrap();
+
+        assert_eq!(serde_json::from_str::<Digest>(&json).unwrap(), digest);
+    }
+
+    #[test]
+    fn deserializes_the_string_form() {
+        let digest: Digest = serde_json::from_str("\"SHA-256=ABCDEF\"").unwrap();
+
+        assert_eq!(digest.algorithm, HashAlgorithm::Sha256);
+        assert_eq!(digest.value, "abcdef");
+    }
+
+    #[test]
+    fn rejects_values_that_are_not_hex() {
+        for json in [
+            r#"{"algorithm":"blake3","value":"not hex"}"#,
+            r#"{"algorithm":"blake3","value":""}"#,
+            r#"{"algorithm":"blake3","value":"ééééééééééééééééé"}"#,
+            r#""blake3=xyz""#,
+            r#""md5=abcd""#,
+        ] {
+            assert!(serde_json::from_str::<Digest>(json).is_err(), "{json}");
+        }
+    }
+
+    #[test]
+    fn shortens_by_characters() {
+        assert_eq!(blake3_of(b"zync").short().len(), 16);
+
+        let digest = Digest {
+            algorithm: HashAlgorithm::Blake3,
+            value: "é".repeat(20),
+        };
+        assert_eq!(digest.short(), "é".repeat(16));
+
+        let digest = Digest {
+            algorithm: HashAlgorithm::Blake3,
+            value: "abc".to_string(),
+        };
+        assert_eq!(digest.short(), "abc");
+    }
+}
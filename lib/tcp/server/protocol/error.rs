@@ -0,0 +1,76 @@
+use core::fmt;
+use std::{error::Error, io};
+
+use super::messages::{ErrorCode, ErrorReply};
+
+/// A failed request, carrying the [`ErrorCode`] reported to the peer in the `ERROR` reply.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl RequestError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+
+    pub fn invalid_path(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidPath, message)
+    }
+
+    pub fn cancelled() -> Self {
+        Self::new(ErrorCode::Cancelled, "Transfer cancelled")
+    }
+
+    pub fn interrupted() -> Self {
+        Self::new(ErrorCode::Interrupted, "Transfer interrupted")
+    }
+
+    /// Extracts the code and message from any handler error, defaulting to `Internal`.
+    pub fn from_boxed(error: &(dyn Error + 'static)) -> Self {
+        if let Some(request_error) = error.downcast_ref::<RequestError>() {
+            return request_error.clone();
+        }
+        if let Some(io_error) = error.downcast_ref::<io::Error>() {
+            return io_error.into();
+        }
+        Self::new(ErrorCode::Internal, error.to_string())
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl Error for RequestError {}
+
+impl From<&io::Error> for RequestError {
+    fn from(error: &io::Error) -> Self {
+        let code = match error.kind() {
+            io::ErrorKind::NotFound => ErrorCode::NotFound,
+            _ => ErrorCode::Internal,
+        };
+        Self::new(code, error.to_string())
+    }
+}
+
+impl From<ErrorReply> for RequestError {
+    fn from(reply: ErrorReply) -> Self {
+        Self::new(reply.code, reply.message)
+    }
+}
+
+impl From<&RequestError> for ErrorReply {
+    fn from(error: &RequestError) -> Self {
+        Self {
+            code: error.code,
+            message: error.message.clone(),
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use bytes::Bytes;
+use core::fmt;
+use serde::{Serialize, de::DeserializeOwned};
+
+/// Version byte written at the start of every frame header.
+pub const PROTOCOL_VERSION: u8 = 1;
+
+/// The zync release this build belongs to, exchanged in `HELLO`/`WELCOME`.
+pub const ZYNC_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Size of the fixed frame header: version (1) + opcode (1) + payload length (4).
+pub const HEADER_LEN: usize = 6;
+
+/// Upper bound on a single frame payload, so a bogus length can't make us allocate gigabytes.
+pub const MAX_PAYLOAD_LEN: usize = 16 * 1024 * 1024;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[repr(u8)]
+pub enum Opcode {
+    List = 0x01,
+    Get = 0x02,
+    Put = 0x03,
+    Delete = 0x04,
+    Pair = 0x05,
+    Confirm = 0x06,
+    Manifest = 0x07,
+    Hello = 0x08,
+    Send = 0x10,
+    Data = 0x11,
+    Listing = 0x12,
+    Ready = 0x13,
+    Challenge = 0x14,
+    Welcome = 0x15,
+    Ok = 0x20,
+    Error = 0x21,
+}
+
+impl Opcode {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        match value {
+            0x01 => Some(Opcode::List),
+            0x02 => Some(Opcode::Get),
+            0x03 => Some(Opcode::Put),
+            0x04 => Some(Opcode::Delete),
+            0x05 => Some(Opcode::Pair),
+            0x06 => Some(Opcode::Confirm),
+            0x07 => Some(Opcode::Manifest),
+            0x08 => Some(Opcode::Hello),
+            0x10 => Some(Opcode::Send),
+            0x11 => Some(Opcode::Data),
+            0x12 => Some(Opcode::Listing),
+            0x13 => Some(Opcode::Ready),
+            0x14 => Some(Opcode::Challenge),
+            0x15 => Some(Opcode::Welcome),
+            0x20 => Some(Opcode::Ok),
+            0x21 => Some(Opcode::Error),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Opcode::List => write!(f, "LIST"),
+            Opcode::Get => write!(f, "GET"),
+            Opcode::Put => write!(f, "PUT"),
+            Opcode::Delete => write!(f, "DELETE"),
+            Opcode::Pair => write!(f, "PAIR"),
+            Opcode::Confirm => write!(f, "CONFIRM"),
+            Opcode::Manifest => write!(f, "MANIFEST"),
+            Opcode::Hello => write!(f, "HELLO"),
+            Opcode::Send => write!(f, "SEND"),
+            Opcode::Data => write!(f, "DATA"),
+            Opcode::Listing => write!(f, "LISTING"),
+            Opcode::Ready => write!(f, "READY"),
+            Opcode::Challenge => write!(f, "CHALLENGE"),
+            Opcode::Welcome => write!(f, "WELCOME"),
+            Opcode::Ok => write!(f, "OK"),
+            Opcode::Error => write!(f, "ERROR"),
+        }
+    }
+}
+
+/// A single message on the wire: an opcode plus its length-prefixed payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub opcode: Opcode,
+    pub payload: Bytes,
+}
+
+impl Frame {
+    pub fn new(opcode: Opcode, payload: impl Into<Bytes>) -> Self {
+        Self {
+            opcode,
+            payload: payload.into(),
+        }
+    }
+
+    pub fn empty(opcode: Opcode) -> Self {
+        Self::new(opcode, Bytes::new())
+    }
+
+    /// Builds a frame whose payload is `value` serialized as JSON.
+    pub fn json<T: Serialize>(opcode: Opcode, value: &T) -> Result<Self, serde_json::Error> {
+        Ok(Self::new(opcode, serde_json::to_vec(value)?))
+    }
+
+    /// Deserializes the JSON payload of this frame.
+    pub fn parse<T: DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_slice(&self.payload)
+    }
+}
@@ -0,0 +1,268 @@
+use std::fs::Metadata;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+
+/// What a [`ManifestEntry`] describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    File,
+    Directory,
+}
+
+/// A file or directory of a [`Manifest`], with the metadata the server recreates it with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Path relative to the manifest's root, with `/` separators.
+    pub path: String,
+    pub kind: EntryKind,
+    /// Size of a file in bytes; 0 for directories.
+    #[serde(default)]
+    pub size: u64,
+    /// Unix permission bits.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    /// Modification time in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<u64>,
+}
+
+/// Payload of a `MANIFEST` request: a directory tree about to be uploaded under `root`.
+///
+/// Entries are listed so that every directory comes before its contents. The server creates the directories and checks the whole tree against its limits, after which each file is sent with a `PUT` to [`Manifest::remote_path`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub root: String,
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Walks the directory `dir` and describes it as a tree to be stored under `root`.
+    ///
+    /// Symlinks are skipped rather than followed, so a link cannot pull files from outside `dir` into the upload.
+    ///
+    /// # Errors
+    /// Returns an error if `dir` is not a directory or part of the tree cannot be read.
+    pub fn scan(dir: &Path, root: &str) -> io::Result<Self> {
+        if !dir.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a directory", dir.display()),
+            ));
+        }
+        let mut entries = Vec::new();
+        scan_dir(dir, "", &mut entries)?;
+        Ok(Self {
+            root: root.to_string(),
+            entries,
+        })
+    }
+
+    /// The files of the tree, in upload order.
+    pub fn files(&self) -> impl Iterator<Item = &ManifestEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| entry.kind == EntryKind::File)
+    }
+
+    /// Total size of the tree's files in bytes.
+    pub fn total_size(&self) -> u64 {
+        self.files().map(|entry| entry.size).sum()
+    }
+
+    /// Where `entry` is stored, relative to the storage root.
+    pub fn remote_path(&self, entry: &ManifestEntry) -> String {
+        let root = self.root.trim_end_matches('/');
+        if root.is_empty() {
+            entry.path.clone()
+        } else {
+            format!("{root}/{}", entry.path)
+        }
+    }
+}
+
+impl ManifestEntry {
+    /// Where the entry lives below the local directory `dir` it was scanned from.
+    pub fn local_path(&self, dir: &Path) -> PathBuf {
+        self.path
+            .split('/')
+            .fold(dir.to_path_buf(), |path, part| path.join(part))
+    }
+}
+
+/// The permission bits of `metadata`, or `None` on platforms without Unix modes.
+pub fn mode_of(metadata: &Metadata) -> Option<u32> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        Some(metadata.permissions().mode() & 0o777)
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = metadata;
+        None
+    }
+}
+
+/// The modification time of `metadata` in seconds since the Unix epoch, if the platform reports one.
+pub fn mtime_of(metadata: &Metadata) -> Option<u64> {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|elapsed| elapsed.as_secs())
+}
+
+fn scan_dir(dir: &Path, prefix: &str, entries: &mut Vec<ManifestEntry>) -> io::Result<()> {
+    let mut children = std::fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    children.sort_by_key(|child| child.file_name());
+    for child in children {
+        let name = child.file_name().to_string_lossy().into_owned();
+        let path = if prefix.is_empty() {
+            name
+        } else {
+            format!("{prefix}/{name}")
+        };
+        let metadata = std::fs::symlink_metadata(child.path())?;
+        let kind = if metadata.is_dir() {
+            EntryKind::Directory
+        } else if metadata.is_file() {
+            EntryKind::File
+        } else {
+            log::warn!("Skipping {}: not a regular file", child.path().display());
+            continue;
+        };
+        entries.push(ManifestEntry {
+            path: path.clone(),
+            kind,
+            size: if kind == EntryKind::File {
+                metadata.len()
+            } else {
+                0
+            },
+            mode: mode_of(&metadata),
+            mtime: mtime_of(&metadata),
+        });
+        if kind == EntryKind::Directory {
+            scan_dir(&child.path(), &path, entries)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tree() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("b/nested")).unwrap();
+        std::fs::write(dir.path().join("a.txt"), b"12345").unwrap();
+        std::fs::write(dir.path().join("b/c.txt"), b"123").unwrap();
+        std::fs::write(dir.path().join("b/nested/d.bin"), b"1234567").unwrap();
+        dir
+    }
+
+    fn entry(path: &str, kind: EntryKind) -> ManifestEntry {
+        ManifestEntry {
+            path: path.to_string(),
+            kind,
+            size: 0,
+            mode: None,
+            mtime: None,
+        }
+    }
+
+    #[test]
+    fn scan_lists_directories_before_their_contents() {
+        let dir = tree();
+        let manifest = Manifest::scan(dir.path(), "photos").unwrap();
+        let paths: Vec<_> = manifest.entries.iter().map(|e| e.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            ["a.txt", "b", "b/c.txt", "b/nested", "b/nested/d.bin"]
+        );
+        assert_eq!(manifest.root, "photos");
+        assert_eq!(manifest.entries[1].kind, EntryKind::Directory);
+        assert_eq!(manifest.entries[1].size, 0);
+    }
+
+    #[test]
+    fn files_and_total_size_skip_directories() {
+        let dir = tree();
+        let manifest = Manifest::scan(dir.path(), "").unwrap();
+        let files: Vec<_> = manifest
+            .files()
+            .map(|e| (e.path.as_str(), e.size))
+            .collect();
+        assert_eq!(files, [("a.txt", 5), ("b/c.txt", 3), ("b/nested/d.bin", 7)]);
+        assert_eq!(manifest.total_size(), 15);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn scan_records_modes_and_skips_symlinks() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tree();
+        let outside = tempfile::tempdir().unwrap();
+        std::fs::write(outside.path().join("secret"), b"secret").unwrap();
+        std::os::unix::fs::symlink(outside.path().join("secret"), dir.path().join("link")).unwrap();
+        std::os::unix::fs::symlink(outside.path(), dir.path().join("b/linked_dir")).unwrap();
+        std::fs::set_permissions(
+            dir.path().join("a.txt"),
+            std::fs::Permissions::from_mode(0o640),
+        )
+        .unwrap();
+
+        let manifest = Manifest::scan(dir.path(), "").unwrap();
+        assert!(manifest.entries.iter().all(|e| !e.path.contains("link")));
+        assert_eq!(manifest.entries[0].mode, Some(0o640));
+        assert!(manifest.entries[0].mtime.is_some());
+    }
+
+    #[test]
+    fn scan_rejects_files() {
+        let dir = tree();
+        let error = Manifest::scan(&dir.path().join("a.txt"), "").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn remote_paths_sit_under_the_root() {
+        let file = entry("b/c.txt", EntryKind::File);
+        let manifest = |root: &str| Manifest {
+            root: root.to_string(),
+            entries: vec![file.clone()],
+        };
+        assert_eq!(manifest("photos").remote_path(&file), "photos/b/c.txt");
+        assert_eq!(manifest("photos/").remote_path(&file), "photos/b/c.txt");
+        assert_eq!(manifest("").remote_path(&file), "b/c.txt");
+    }
+
+    #[test]
+    fn local_paths_sit_under_the_scanned_directory() {
+        let file = entry("b/nested/d.bin", EntryKind::File);
+        assert_eq!(
+            file.local_path(Path::new("/home/me/upload")),
+            Path::new("/home/me/upload")
+                .join("b")
+                .join("nested")
+                .join("d.bin")
+        );
+    }
+
+    #[test]
+    fn manifests_round_trip_through_json() {
+        let dir = tree();
+        let manifest = Manifest::scan(dir.path(), "photos").unwrap();
+        let json = serde_json::to_string(&manifest).unwrap();
+        assert_eq!(serde_json::from_str::<Manifest>(&json).unwrap(), manifest);
+        let sparse: ManifestEntry =
+            serde_json::from_str(r#"{"path":"x","kind":"directory"}"#).unwrap();
+        assert_eq!(sparse, entry("x", EntryKind::Directory));
+    }
+}
@@ -0,0 +1,300 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::compression::{Compression, TransferStats};
+use super::digest::{Digest, HashAlgorithm};
+use super::manifest::EntryKind;
+
+/// Payload of `DELETE` requests.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathRequest {
+    pub path: String,
+}
+
+/// Payload of a `GET` request. The file is sent starting at byte `offset`, so an interrupted download can be resumed.
+///
+/// `compression` is what the client accepts; the server announces in its `SEND` header whether it actually compressed the body.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetRequest {
+    pub path: String,
+    #[serde(default)]
+    pub offset: u64,
+    /// Hash function the server announces the file's digest with.
+    #[serde(default)]
+    pub algorithm: HashAlgorithm,
+    #[serde(default)]
+    pub compression: Compression,
+}
+
+/// Payload of a `PUT` request. `size` is the full file size.
+///
+/// With `resume` set, the server keeps what it already received of an earlier attempt and reports it in the `READY` reply; otherwise it starts from zero. A `digest` of the full file is checked against what the server ends up with. The stored file is given the Unix permission bits `mode` and the modification time `mtime`, in seconds since the Unix epoch, where set.
+///
+/// `compression` is how the client proposes to encode the body; the `READY` reply says which encoding the server agreed to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PutRequest {
+    pub path: String,
+    pub size: u64,
+    #[serde(default)]
+    pub resume: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<Digest>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<u64>,
+    #[serde(default)]
+    pub compression: Compression,
+}
+
+/// Payload of the `READY` reply to a `PUT`. The client sends the `size - offset` bytes from `offset` on as `DATA` frames, encoded with `compression`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct ReadyReply {
+    #[serde(default)]
+    pub offset: u64,
+    #[serde(default)]
+    pub compression: Compression,
+}
+
+/// Payload of the `SEND` reply to a `GET`. `size` is the full file size; the `size - offset` bytes from `offset` on follow as `DATA` frames, encoded with `compression`. `digest` covers the full file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SendHeader {
+    pub path: String,
+    pub size: u64,
+    #[serde(default)]
+    pub offset: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<Digest>,
+    #[serde(default)]
+    pub compression: Compression,
+}
+
+/// Payload of a `LIST` request. An empty payload lists the top of the storage root.
+///
+/// `path` names the directory to list, relative to the storage root; with `recursive` set everything below it is listed too. `pattern` is a glob that keeps only matching entries: a pattern containing `/` is matched against the entry's whole path, any other against its name alone. The matching entries are paged: the reply holds at most `limit` of them, starting at the `offset`-th.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct ListRequest {
+    #[serde(default)]
+    pub path: String,
+    #[serde(default)]
+    pub recursive: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+}
+
+/// A file or directory in the server's storage, as described in a `LISTING` reply.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteEntry {
+    /// Path relative to the storage root, with `/` separators.
+    pub path: String,
+    pub kind: EntryKind,
+    /// Size of a file in bytes; 0 for directories.
+    #[serde(default)]
+    pub size: u64,
+    /// Modification time in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<u64>,
+    /// The digest the file was verified against when it was uploaded, if the server recorded one and the file has not changed since.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<Digest>,
+    /// The user who uploaded the file, if the server recorded one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uploader: Option<String>,
+}
+
+/// Payload of the `LISTING` reply to a `LIST`: one page of the matching entries.
+///
+/// `total` counts every matching entry. `next_offset` is where the next page starts, or `None` on the last page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct ListingReply {
+    pub entries: Vec<RemoteEntry>,
+    #[serde(default)]
+    pub total: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_offset: Option<usize>,
+}
+
+/// How much of a resumable HTTP upload the server already holds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UploadStatus {
+    pub path: String,
+    pub offset: u64,
+}
+
+/// Payload of a `HELLO` request: the name of the client and the zync release it runs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HelloRequest {
+    pub name: String,
+    pub version: String,
+}
+
+/// Something a server supports beyond the requests it handles, listed in its `WELCOME` reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// Clients must pair with the code the server displays before they are served.
+    Pairing,
+    /// Connections are wrapped in TLS.
+    Tls,
+    /// The same server serves the HTTP routes, at the endpoint it reports while pairing.
+    Http,
+    /// Interrupted transfers resume from a byte offset.
+    Resume,
+    /// Transfer bodies may be compressed with zstd.
+    Zstd,
+    /// Files are verified with BLAKE3 digests.
+    Blake3,
+    /// Files are verified with SHA-256 digests.
+    Sha256,
+    /// A feature of a newer server that this build does not know.
+    #[serde(other)]
+    Unknown,
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Feature::Pairing => write!(f, "pairing"),
+            Feature::Tls => write!(f, "tls"),
+            Feature::Http => write!(f, "http"),
+            Feature::Resume => write!(f, "resume"),
+            Feature::Zstd => write!(f, "zstd"),
+            Feature::Blake3 => write!(f, "blake3"),
+            Feature::Sha256 => write!(f, "sha-256"),
+            Feature::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Payload of the `WELCOME` reply to `HELLO`: which server the client reached and what it supports.
+///
+/// `version` is the zync release the server runs and `protocol` the frame protocol version it speaks. `free_space` is how many more bytes the paired user may upload, if the server can tell; it is left out before the connection is paired. `commands` names the requests the server handles.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WelcomeReply {
+    pub name: String,
+    pub version: String,
+    pub protocol: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub free_space: Option<u64>,
+    #[serde(default)]
+    pub commands: Vec<String>,
+    #[serde(default)]
+    pub features: Vec<Feature>,
+}
+
+/// Payload of a `PAIR` request: the client's name and its SPAKE2 message, as hex.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PairRequest {
+    pub name: String,
+    pub message: String,
+}
+
+/// Payload of a `CHALLENGE` reply to `PAIR`: the server's SPAKE2 message, for the pairing `session`.
+///
+/// The server's key confirmation only follows in the [`ConfirmReply`], once the client has proven it knows the code. A TCP server that also serves the HTTP routes tells the client where in `http`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PairReply {
+    pub session: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub http: Option<HttpEndpoint>,
+}
+
+/// Whether HTTP routes are served in the clear or over TLS.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Scheme {
+    #[default]
+    Http,
+    Https,
+}
+
+impl fmt::Display for Scheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Scheme::Http => write!(f, "http"),
+            Scheme::Https => write!(f, "https"),
+        }
+    }
+}
+
+/// Where a server serves its HTTP routes: the `port` on the same host as its TCP listener, spoken to with `scheme`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpEndpoint {
+    pub port: u16,
+    #[serde(default)]
+    pub scheme: Scheme,
+}
+
+/// Payload of a `CONFIRM` request, proving the client derived the same key as the server.
+///
+/// `secret` is the client's user secret, masked with the session key: the server binds the client's user name to it the first time the name pairs and refuses the name to clients that cannot prove the same secret later.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfirmRequest {
+    pub session: String,
+    pub confirmation: String,
+    pub secret: String,
+}
+
+/// Payload of the `OK` reply to `CONFIRM`: the `name` the client is paired as and the server's key confirmation, proving the server derived the same key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConfirmReply {
+    pub name: String,
+    pub message: String,
+    pub confirmation: String,
+}
+
+/// Machine-readable reason attached to an `ERROR` reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The request payload could not be understood.
+    BadRequest,
+    /// The path is absolute, contains `..`, or escapes the storage root.
+    InvalidPath,
+    /// Nothing exists at the requested path.
+    NotFound,
+    /// The upload is larger than the server accepts.
+    TooLarge,
+    /// A resumed upload does not start where the server's partial file ends.
+    OffsetMismatch,
+    /// The received content does not match its announced digest.
+    DigestMismatch,
+    /// The upload would take the user's storage over its quota.
+    QuotaExceeded,
+    /// The client has not paired with the server, or used the wrong pairing code.
+    Unauthorized,
+    /// The server is at its connection limit and no slot freed up in time.
+    Busy,
+    /// The client started too many pairing handshakes lately and has to wait before the next.
+    TooManyAttempts,
+    /// The client cancelled the transfer in flight.
+    Cancelled,
+    /// The client broke off the transfer in flight to retry it; a resumable upload keeps what arrived of it.
+    Interrupted,
+    #[default]
+    Internal,
+}
+
+/// Payload of an `ERROR` reply.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorReply {
+    #[serde(default)]
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+/// Payload of an `OK` reply to a request that does not return data. After a `PUT`, `digest` is the verified digest of the stored file and `stats` tells how many bytes its body took on the wire.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OkReply {
+    pub path: String,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub digest: Option<Digest>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<TransferStats>,
+}
@@ -0,0 +1,9 @@
+pub mod codec;
+pub mod compression;
+pub mod digest;
+pub mod error;
+pub mod frame;
+pub mod manifest;
+pub mod messages;
+pub mod progress;
+pub mod transfer;
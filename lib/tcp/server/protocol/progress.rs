@@ -0,0 +1,278 @@
+use core::fmt;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// How often a tracker reports a transfer that is still running, unless it is given another interval.
+pub const REPORT_INTERVAL: Duration = Duration::from_millis(200);
+/// How often a tracker made with [`ProgressTracker::logging`] writes a running transfer to the log.
+pub const LOG_INTERVAL: Duration = Duration::from_secs(2);
+/// Weight of the latest interval's rate in the smoothed rate; the rest comes from the rate before it.
+const RATE_SMOOTHING: f64 = 0.3;
+
+/// How far a transfer has come.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Progress {
+    /// Bytes of the file in place so far, including any an earlier attempt left behind.
+    pub done: u64,
+    /// Size of the whole file, or of every file of a tree.
+    pub total: u64,
+    /// Smoothed transfer rate in bytes per second.
+    pub rate: f64,
+    /// Estimated time left, once a rate is known.
+    pub eta: Option<Duration>,
+}
+
+/// Receives the reports of a [`ProgressTracker`].
+///
+/// Reports arrive on whatever task moves the bytes, so a sink should hand them on, e.g. over a channel, rather than do slow work itself.
+pub trait ProgressSink: Send {
+    fn report(&mut self, progress: &Progress);
+}
+
+/// Measures a transfer as its bytes pass through and reports rate and ETA to a [`ProgressSink`].
+///
+/// Reports are throttled to one per interval, plus one when the transfer starts and one when it completes. Clones share the same transfer, so the readers and writers of a whole tree can feed a single tracker. A disabled tracker, the default, measures nothing.
+#[derive(Clone, Default)]
+pub struct ProgressTracker {
+    shared: Option<Arc<Mutex<TrackerState>>>,
+}
+
+struct TrackerState {
+    sink: Box<dyn ProgressSink>,
+    interval: Duration,
+    progress: Progress,
+    sampled_at: Instant,
+    sampled_done: u64,
+}
+
+/// An [`AsyncRead`] adapter that counts the bytes read through it towards a [`ProgressTracker`].
+#[derive(Debug)]
+pub struct ProgressReader<R> {
+    inner: R,
+    tracker: ProgressTracker,
+}
+
+/// An [`AsyncWrite`] adapter that counts the bytes written through it towards a [`ProgressTracker`].
+#[derive(Debug)]
+pub struct ProgressWriter<W> {
+    inner: W,
+    tracker: ProgressTracker,
+}
+
+struct LogProgress {
+    name: String,
+}
+
+impl Progress {
+    /// The completed share of the transfer, between 0 and 1.
+    pub fn ratio(&self) -> f64 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        (self.done as f64 / self.total as f64).min(1.0)
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.done >= self.total
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} bytes ({:.0}%) at {:.0} B/s",
+            self.done,
+            self.total,
+            self.ratio() * 100.0,
+            self.rate
+        )?;
+        if let Some(eta) = self.eta {
+            write!(f, ", {}s left", eta.as_secs())?;
+        }
+        Ok(())
+    }
+}
+
+impl<F> ProgressSink for F
+where
+    F: FnMut(&Progress) + Send,
+{
+    fn report(&mut self, progress: &Progress) {
+        self(progress)
+    }
+}
+
+impl ProgressSink for LogProgress {
+    fn report(&mut self, progress: &Progress) {
+        log::info!("{}: {}", self.name, progress);
+    }
+}
+
+impl fmt::Debug for ProgressTracker {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let progress = self
+            .shared
+            .as_ref()
+            .map(|shared| shared.lock().unwrap().progress);
+        f.debug_struct("ProgressTracker")
+            .field("progress", &progress)
+            .finish()
+    }
+}
+
+impl ProgressTracker {
+    /// A tracker reporting to `sink` every [`REPORT_INTERVAL`].
+    pub fn new(sink: impl ProgressSink + 'static) -> Self {
+        Self {
+            shared: Some(Arc::new(Mutex::new(TrackerState {
+                sink: Box::new(sink),
+                interval: REPORT_INTERVAL,
+                progress: Progress::default(),
+                sampled_at: Instant::now(),
+                sampled_done: 0,
+            }))),
+        }
+    }
+
+    /// A tracker that measures nothing, for transfers nobody watches.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// A tracker writing the transfer to the log under `name` every [`LOG_INTERVAL`].
+    pub fn logging(name: impl Into<String>) -> Self {
+        Self::new(LogProgress { name: name.into() }).with_interval(LOG_INTERVAL)
+    }
+
+    /// Reports a running transfer every `interval` instead.
+    pub fn with_interval(self, interval: Duration) -> Self {
+        if let Some(shared) = &self.shared {
+            shared.lock().unwrap().interval = interval;
+        }
+        self
+    }
+
+    /// Starts measuring a transfer of `total` bytes of which `done` are already in place, and reports it at once.
+    pub fn start(&self, done: u64, total: u64) {
+        let Some(shared) = &self.shared else {
+            return;
+        };
+        let mut state = shared.lock().unwrap();
+        state.progress = Progress {
+            done,
+            total,
+            rate: 0.0,
+            eta: None,
+        };
+        state.sampled_at = Instant::now();
+        state.sampled_done = done;
+        let progress = state.progress;
+        state.sink.report(&progress);
+    }
+
+    /// Counts `bytes` more towards the transfer, reporting it if the interval has passed or the transfer is complete.
+    pub fn advance(&self, bytes: u64) {
+        let Some(shared) = &self.shared else {
+            return;
+        };
+        if bytes == 0 {
+            return;
+        }
+        let mut state = shared.lock().unwrap();
+        state.progress.done += bytes;
+        let now = Instant::now();
+        let elapsed = now.duration_since(state.sampled_at);
+        if elapsed < state.interval && !state.progress.is_complete() {
+            return;
+        }
+
+        let sampled = (state.progress.done - state.sampled_done) as f64;
+        let latest = sampled / elapsed.as_secs_f64().max(f64::EPSILON);
+        state.progress.rate = if state.progress.rate > 0.0 {
+            RATE_SMOOTHING * latest + (1.0 - RATE_SMOOTHING) * state.progress.rate
+        } else {
+            latest
+        };
+        let remaining = state.progress.total.saturating_sub(state.progress.done);
+        state.progress.eta = (state.progress.rate > 0.0)
+            .then(|| Duration::from_secs_f64(remaining as f64 / state.progress.rate));
+        state.sampled_at = now;
+        state.sampled_done = state.progress.done;
+        let progress = state.progress;
+        state.sink.report(&progress);
+    }
+
+    /// The latest measurement, or `None` for a disabled tracker.
+    pub fn progress(&self) -> Option<Progress> {
+        self.shared
+            .as_ref()
+            .map(|shared| shared.lock().unwrap().progress)
+    }
+
+    /// Wraps `inner` so that the bytes read from it count towards this transfer.
+    pub fn reader<R>(&self, inner: R) -> ProgressReader<R> {
+        ProgressReader {
+            inner,
+            tracker: self.clone(),
+        }
+    }
+
+    /// Wraps `inner` so that the bytes written to it count towards this transfer.
+    pub fn writer<W>(&self, inner: W) -> ProgressWriter<W> {
+        ProgressWriter {
+            inner,
+            tracker: self.clone(),
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ProgressReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = &mut *self;
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut this.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            this.tracker.advance((buf.filled().len() - before) as u64);
+        }
+        poll
+    }
+}
+
+impl<W> ProgressWriter<W> {
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for ProgressWriter<W> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = &mut *self;
+        let poll = Pin::new(&mut this.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            this.tracker.advance(written as u64);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
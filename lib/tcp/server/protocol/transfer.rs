@@ -0,0 +1,264 @@
+use async_compression::tokio::bufread::ZstdEncoder;
+use async_compression::tokio::write::ZstdDecoder;
+use futures_util::{FutureExt, SinkExt, StreamExt};
+use std::error::Error;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+
+use super::codec::FramedStream;
+use super::compression::{Compression, LimitedWriter, TransferStats, max_compressed_len};
+use super::error::RequestError;
+use super::frame::{Frame, Opcode};
+use super::messages::ErrorReply;
+
+/// Size of the chunks a body is split into when it is sent as `DATA` frames.
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Reads the next frame, treating a closed connection as an error.
+pub async fn next_frame(stream: &mut FramedStream) -> Result<Frame, Box<dyn Error>> {
+    match stream.next().await {
+        Some(frame) => Ok(frame?),
+        None => Err("Connection closed by peer".into()),
+    }
+}
+
+/// Turns an `ERROR` frame into an `Err` carrying the peer's [`RequestError`].
+pub fn reject_error(frame: &Frame) -> Result<(), Box<dyn Error>> {
+    if frame.opcode == Opcode::Error {
+        let reply: ErrorReply = frame.parse()?;
+        return Err(Box::new(RequestError::from(reply)));
+    }
+    Ok(())
+}
+
+pub async fn send_error(
+    stream: &mut FramedStream,
+    error: &RequestError,
+) -> Result<(), Box<dyn Error>> {
+    let reply = ErrorReply::from(error);
+    stream.send(Frame::json(Opcode::Error, &reply)?).await?;
+    Ok(())
+}
+
+/// Checks, without waiting, whether the peer has sent anything while a body is being sent to it.
+///
+/// The peer only speaks up mid-body to cancel the transfer or to report that it failed, so any frame it sent ends the transfer.
+///
+/// # Errors
+/// Returns the peer's [`RequestError`] if it sent an `ERROR` frame, or an error if it sent any other frame or closed the connection.
+fn check_peer(stream: &mut FramedStream) -> Result<(), Box<dyn Error>> {
+    match stream.next().now_or_never() {
+        None => Ok(()),
+        Some(None) => Err("Connection closed by peer".into()),
+        Some(Some(frame)) => {
+            let frame = frame?;
+            reject_error(&frame)?;
+            Err(format!("Unexpected {} frame during a transfer", frame.opcode).into())
+        }
+    }
+}
+
+/// Cancels the transfer in flight on `stream`, once the future driving it has been dropped.
+///
+/// Sends an `ERROR` frame with the `Cancelled` code and skips whatever the peer sent for the transfer until it acknowledges the cancellation with an `ERROR` frame of its own carrying that code, so the connection is ready for the next request. A peer that had already finished the transfer acknowledges the cancellation as a request of its own.
+///
+/// # Errors
+/// Returns an error if the connection fails before the peer acknowledges the cancellation.
+pub async fn cancel_transfer(stream: &mut FramedStream) -> Result<(), Box<dyn Error>> {
+    end_transfer(stream, &RequestError::cancelled()).await
+}
+
+/// Breaks off the transfer in flight on `stream` like [`cancel_transfer`], but with the `Interrupted` code, so the peer keeps what a resumable upload received for the retry.
+///
+/// # Errors
+/// Returns an error if the connection fails before the peer acknowledges the interruption.
+pub async fn interrupt_transfer(stream: &mut FramedStream) -> Result<(), Box<dyn Error>> {
+    end_transfer(stream, &RequestError::interrupted()).await
+}
+
+/// Sends `error` to end the transfer in flight and skips frames until the peer acknowledges it with an `ERROR` frame carrying the same code.
+async fn end_transfer(
+    stream: &mut FramedStream,
+    error: &RequestError,
+) -> Result<(), Box<dyn Error>> {
+    stream.flush().await?;
+    send_error(stream, error).await?;
+    loop {
+        let frame = next_frame(stream).await?;
+        if frame.opcode == Opcode::Error {
+            let reply: ErrorReply = frame.parse()?;
+            if reply.code == error.code {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Streams `size` bytes from `reader` to the peer as a sequence of `DATA` frames.
+///
+/// The peer can cancel the transfer with an `ERROR` frame, which is checked for between chunks.
+///
+/// # Returns
+/// The number of bytes sent, or an error if the reader ends early, the peer cancels or the connection fails.
+pub async fn send_body<R>(
+    stream: &mut FramedStream,
+    reader: &mut R,
+    size: u64,
+) -> Result<u64, Box<dyn Error>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut total_sent = 0u64;
+
+    while total_sent < size {
+        let to_read = std::cmp::min(size - total_sent, buffer.len() as u64) as usize;
+        check_peer(stream)?;
+        let bytes_read = reader.read(&mut buffer[..to_read]).await?;
+        if bytes_read == 0 {
+            return Err(format!("Source ended after {total_sent} of {size} bytes").into());
+        }
+        stream
+            .feed(Frame::new(Opcode::Data, buffer[..bytes_read].to_vec()))
+            .await?;
+
+        total_sent += bytes_read as u64;
+    }
+    stream.flush().await?;
+
+    Ok(total_sent)
+}
+
+/// Receives `size` bytes of `DATA` frames from the peer and writes them to `writer`.
+///
+/// # Returns
+/// The number of bytes written, or an error if the peer sends anything other than `DATA` frames,
+/// sends more than `size` bytes, or the connection closes early.
+pub async fn recv_body<W>(
+    stream: &mut FramedStream,
+    writer: &mut W,
+    size: u64,
+) -> Result<u64, Box<dyn Error>>
+where
+    W: AsyncWrite + Unpin,
+{
+    let mut received = 0u64;
+
+    while received < size {
+        let frame = next_frame(stream).await?;
+        reject_error(&frame)?;
+        if frame.opcode != Opcode::Data {
+            return Err(format!("Expected DATA frame, got {}", frame.opcode).into());
+        }
+        received += frame.payload.len() as u64;
+        if received > size {
+            return Err(format!("Peer sent more than the announced {size} bytes").into());
+        }
+        writer.write_all(&frame.payload).await?;
+    }
+    writer.flush().await?;
+
+    Ok(received)
+}
+
+/// Streams `size` bytes from `reader` to the peer, encoded with `compression`.
+///
+/// An uncompressed body is sent exactly as [`send_body`] sends it. A compressed body's length is not known up front, so its encoded bytes are sent as `DATA` frames followed by an empty `DATA` frame marking its end. Either can be cancelled by the peer.
+///
+/// # Returns
+/// How many bytes of the file were sent and how many bytes they took on the wire, or an error if the reader fails, the peer cancels or the connection fails.
+pub async fn send_encoded_body<R>(
+    stream: &mut FramedStream,
+    reader: &mut R,
+    size: u64,
+    compression: Compression,
+) -> Result<TransferStats, Box<dyn Error>>
+where
+    R: AsyncRead + Unpin,
+{
+    if !compression.is_compressed() {
+        return Ok(TransferStats::uncompressed(
+            send_body(stream, reader, size).await?,
+        ));
+    }
+
+    let mut encoder = ZstdEncoder::new(BufReader::new(reader.take(size)));
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut transferred = 0u64;
+    loop {
+        check_peer(stream)?;
+        let bytes_read = encoder.read(&mut buffer).await?;
+        if bytes_read == 0 {
+            break;
+        }
+        stream
+            .feed(Frame::new(Opcode::Data, buffer[..bytes_read].to_vec()))
+            .await?;
+        transferred += bytes_read as u64;
+    }
+    stream.feed(Frame::new(Opcode::Data, Vec::new())).await?;
+    stream.flush().await?;
+    log::info!(
+        "Sent {} bytes as {} bytes of {}",
+        size,
+        transferred,
+        compression
+    );
+
+    Ok(TransferStats {
+        original: size,
+        transferred,
+        compression,
+    })
+}
+
+/// Receives a body of `size` bytes sent by [`send_encoded_body`] with `compression`, decoding it into `writer`.
+///
+/// A compressed body is decoded as it arrives and may neither decode to more than `size` bytes nor take much more than `size` bytes on the wire, so a small body cannot expand without bound.
+///
+/// # Returns
+/// How many bytes of the file were received and how many bytes they took on the wire, or an error if the peer sends anything other than `DATA` frames, the body does not decode to exactly `size` bytes, or the connection closes early.
+pub async fn recv_encoded_body<W>(
+    stream: &mut FramedStream,
+    writer: &mut W,
+    size: u64,
+    compression: Compression,
+) -> Result<TransferStats, Box<dyn Error>>
+where
+    W: AsyncWrite + Unpin,
+{
+    if !compression.is_compressed() {
+        return Ok(TransferStats::uncompressed(
+            recv_body(stream, writer, size).await?,
+        ));
+    }
+
+    let mut decoder = ZstdDecoder::new(LimitedWriter::new(writer, size));
+    let limit = max_compressed_len(size);
+    let mut transferred = 0u64;
+    loop {
+        let frame = next_frame(stream).await?;
+        reject_error(&frame)?;
+        if frame.opcode != Opcode::Data {
+            return Err(format!("Expected DATA frame, got {}", frame.opcode).into());
+        }
+        if frame.payload.is_empty() {
+            break;
+        }
+        transferred += frame.payload.len() as u64;
+        if transferred > limit {
+            return Err(format!("Compressed body of {size} bytes exceeds {limit} bytes").into());
+        }
+        decoder.write_all(&frame.payload).await?;
+    }
+    decoder.shutdown().await?;
+    let received = decoder.get_ref().written();
+    if received != size {
+        return Err(format!("Body decoded to {received} of the announced {size} bytes").into());
+    }
+
+    Ok(TransferStats {
+        original: size,
+        transferred,
+        compression,
+    })
+}
@@ -0,0 +1,494 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use log::{error, info, warn};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, mpsc, watch};
+use tokio_stream::wrappers::ReceiverStream;
+
+/// How many admitted connections may wait to be handed to warp at once.
+const INCOMING_BACKLOG: usize = 64;
+
+/// Limits a [`Scheduler`] enforces on the connections it admits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedulerConfig {
+    /// How many clients may be served at the same time.
+    pub max_clients: usize,
+    /// How many connections, served or queued, a single IP address may hold.
+    pub max_per_ip: usize,
+    /// How long a connection may wait for a free slot before it is refused.
+    pub queue_timeout: Duration,
+    /// How long a shutdown waits for in-flight transfers before giving up on them.
+    pub drain_timeout: Duration,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            max_clients: 32,
+            max_per_ip: 4,
+            queue_timeout: Duration::from_secs(30),
+            drain_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// A snapshot of a scheduler's connection counts.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SchedulerMetrics {
+    /// Connections currently being served.
+    pub active: usize,
+    /// Connections waiting for a free slot.
+    pub queued: usize,
+    /// Connections that were served and have since closed.
+    pub served: u64,
+    /// Connections refused for hitting a limit, timing out in the queue or arriving during shutdown.
+    pub rejected: u64,
+    pub max_clients: usize,
+    /// Whether the scheduler has stopped admitting connections.
+    pub draining: bool,
+}
+
+/// Why a connection was not admitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdmissionError {
+    /// The connection's IP address already holds `max_per_ip` connections.
+    PerIpLimit,
+    /// No slot freed up within `queue_timeout`.
+    QueueTimeout,
+    /// The server is shutting down.
+    ShuttingDown,
+}
+
+impl fmt::Display for AdmissionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AdmissionError::PerIpLimit => write!(f, "Too many connections from this address"),
+            AdmissionError::QueueTimeout => {
+                write!(f, "Server is busy, timed out waiting for a slot")
+            }
+            AdmissionError::ShuttingDown => write!(f, "Server is shutting down"),
+        }
+    }
+}
+
+impl std::error::Error for AdmissionError {}
+
+/// Bounds how many clients the TCP and HTTP servers serve at once.
+///
+/// Each connection must be admitted before it is served. Admission counts the connection against its IP address, then queues it until one of `max_clients` slots frees up or `queue_timeout` passes. A shutdown stops admitting connections, refuses the queued ones and lets the admitted ones finish their transfers. Cloning shares the scheduler, so the TUI can read its metrics while the servers use it.
+#[derive(Debug, Clone)]
+pub struct Scheduler {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    config: SchedulerConfig,
+    slots: Arc<Semaphore>,
+    per_ip: Mutex<HashMap<IpAddr, usize>>,
+    queued: AtomicUsize,
+    served: AtomicU64,
+    rejected: AtomicU64,
+    active: watch::Sender<usize>,
+    draining: watch::Sender<bool>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(SchedulerConfig::default())
+    }
+}
+
+impl Scheduler {
+    /// Creates a scheduler enforcing `config`. Limits of zero are raised to one.
+    pub fn new(config: SchedulerConfig) -> Self {
+        let config = SchedulerConfig {
+            max_clients: config.max_clients.max(1),
+            max_per_ip: config.max_per_ip.max(1),
+            ..config
+        };
+        Self {
+            inner: Arc::new(Inner {
+                config,
+                slots: Arc::new(Semaphore::new(config.max_clients)),
+                per_ip: Mutex::new(HashMap::new()),
+                queued: AtomicUsize::new(0),
+                served: AtomicU64::new(0),
+                rejected: AtomicU64::new(0),
+                active: watch::channel(0).0,
+                draining: watch::channel(false).0,
+            }),
+        }
+    }
+
+    pub fn config(&self) -> SchedulerConfig {
+        self.inner.config
+    }
+
+    pub fn metrics(&self) -> SchedulerMetrics {
+        SchedulerMetrics {
+            active: *self.inner.active.borrow(),
+            queued: self.inner.queued.load(Ordering::Relaxed),
+            served: self.inner.served.load(Ordering::Relaxed),
+            rejected: self.inner.rejected.load(Ordering::Relaxed),
+            max_clients: self.inner.config.max_clients,
+            draining: self.is_draining(),
+        }
+    }
+
+    pub fn is_draining(&self) -> bool {
+        *self.inner.draining.borrow()
+    }
+
+    /// Admits a connection from `peer`, waiting for a free slot if every slot is taken.
+    ///
+    /// # Parameters
+    /// - `peer`: The IP address the connection comes from.
+    ///
+    /// # Returns
+    /// A [`Permit`] that holds the connection's slot until it is dropped.
+    ///
+    /// # Errors
+    /// Returns an [`AdmissionError`] if `peer` is over its connection limit, no slot frees up within the queue timeout, or the scheduler is shutting down.
+    pub async fn admit(&self, peer: IpAddr) -> Result<Permit, AdmissionError> {
+        let result = self.try_admit(peer).await;
+        if result.is_err() {
+            self.inner.rejected.fetch_add(1, Ordering::Relaxed);
+        }
+        result
+    }
+
+    async fn try_admit(&self, peer: IpAddr) -> Result<Permit, AdmissionError> {
+        if self.is_draining() {
+            return Err(AdmissionError::ShuttingDown);
+        }
+        {
+            let mut per_ip = self.inner.per_ip.lock().unwrap();
+            let count = per_ip.entry(peer).or_insert(0);
+            if *count >= self.inner.config.max_per_ip {
+                return Err(AdmissionError::PerIpLimit);
+            }
+            *count += 1;
+        }
+
+        self.inner.queued.fetch_add(1, Ordering::Relaxed);
+        let slot = tokio::time::timeout(
+            self.inner.config.queue_timeout,
+            self.inner.slots.clone().acquire_owned(),
+        )
+        .await;
+        self.inner.queued.fetch_sub(1, Ordering::Relaxed);
+
+        let slot = match slot {
+            Ok(Ok(slot)) => slot,
+            Ok(Err(_)) => {
+                self.release_ip(peer);
+                return Err(AdmissionError::ShuttingDown);
+            }
+            Err(_) => {
+                self.release_ip(peer);
+                return Err(AdmissionError::QueueTimeout);
+            }
+        };
+        self.inner.active.send_modify(|active| *active += 1);
+        Ok(Permit {
+            scheduler: self.clone(),
+            peer,
+            _slot: slot,
+        })
+    }
+
+    fn release_ip(&self, peer: IpAddr) {
+        let mut per_ip = self.inner.per_ip.lock().unwrap();
+        if let Some(count) = per_ip.get_mut(&peer) {
+            *count -= 1;
+            if *count == 0 {
+                per_ip.remove(&peer);
+            }
+        }
+    }
+
+    /// Stops admitting connections and refuses the queued ones. Connections already admitted keep being served.
+    pub fn shutdown(&self) {
+        self.inner.draining.send_replace(true);
+        self.inner.slots.close();
+    }
+
+    /// Resolves once [`shutdown`](Self::shutdown) has been called.
+    pub async fn closed(&self) {
+        let mut draining = self.inner.draining.subscribe();
+        let _ = draining.wait_for(|draining| *draining).await;
+    }
+
+    /// Shuts the scheduler down and waits for the admitted connections to close.
+    ///
+    /// # Parameters
+    /// - `grace`: How long to wait for in-flight transfers to finish.
+    ///
+    /// # Returns
+    /// `true` if every connection closed within `grace`, `false` if some were still open.
+    pub async fn drain(&self, grace: Duration) -> bool {
+        self.shutdown();
+        let mut active = self.inner.active.subscribe();
+        let drained = tokio::time::timeout(grace, active.wait_for(|active| *active == 0)).await;
+        drained.is_ok()
+    }
+}
+
+/// A connection's slot in a [`Scheduler`], released when dropped.
+#[derive(Debug)]
+pub struct Permit {
+    scheduler: Scheduler,
+    peer: IpAddr,
+    _slot: OwnedSemaphorePermit,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let inner = &self.scheduler.inner;
+        self.scheduler.release_ip(self.peer);
+        inner.served.fetch_add(1, Ordering::Relaxed);
+        inner.active.send_modify(|active| *active -= 1);
+    }
+}
+
+/// A stream that holds its connection's [`Permit`] for as long as it is open.
+#[derive(Debug)]
+pub struct Scheduled<T> {
+    stream: T,
+    _permit: Permit,
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Scheduled<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Scheduled<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+/// Accepts connections from `listener`, admits each through `scheduler` and yields them once `handshake` has turned them into the stream to serve.
+///
+/// Connections are admitted before the handshake, so queued clients do not hold TLS state, and each runs in its own task so a slow client does not hold up the others. The stream ends once the scheduler shuts down.
+///
+/// # Parameters
+/// - `listener`: The bound listener to accept connections from.
+/// - `scheduler`: The scheduler every connection is admitted through.
+/// - `handshake`: Turns an admitted TCP stream into the stream handed to the server, such as a TLS acceptor.
+///
+/// # Returns
+/// A stream of admitted connections, suitable for `warp::serve(..).run_incoming`.
+pub fn scheduled_incoming<T, F, Fut>(
+    listener: TcpListener,
+    scheduler: Scheduler,
+    handshake: F,
+) -> ReceiverStream<io::Result<Scheduled<T>>>
+where
+    T: Send + 'static,
+    F: Fn(TcpStream) -> Fut + Send + 'static,
+    Fut: Future<Output = io::Result<T>> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(INCOMING_BACKLOG);
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        error!("Connection failed: {}", e);
+                        continue;
+                    }
+                },
+                _ = scheduler.closed() => break,
+                _ = tx.closed() => break,
+            };
+            let scheduler = scheduler.clone();
+            let handshake = handshake(stream);
+            let sender = tx.clone();
+            tokio::spawn(async move {
+                let permit = match scheduler.admit(peer.ip()).await {
+                    Ok(permit) => permit,
+                    Err(e) => {
+                        warn!("Refused connection from {}: {}", peer, e);
+                        return;
+                    }
+                };
+                match handshake.await {
+                    Ok(stream) => {
+                        let _ = sender
+                            .send(Ok(Scheduled {
+                                stream,
+                                _permit: permit,
+                            }))
+                            .await;
+                    }
+                    Err(e) => warn!("Handshake with {} failed: {}", peer, e),
+                }
+            });
+        }
+        info!("Stopped accepting connections");
+    });
+    ReceiverStream::new(rx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use std::time::Instant;
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(10, 0, 0, last))
+    }
+
+    fn scheduler(max_clients: usize, max_per_ip: usize, queue_timeout: Duration) -> Scheduler {
+        Scheduler::new(SchedulerConfig {
+            max_clients,
+            max_per_ip,
+            queue_timeout,
+            drain_timeout: Duration::from_secs(5),
+        })
+    }
+
+    #[tokio::test]
+    async fn caps_connections_per_address() {
+        let scheduler = scheduler(8, 2, Duration::from_secs(5));
+        let first = scheduler.admit(ip(1)).await.unwrap();
+        let _second = scheduler.admit(ip(1)).await.unwrap();
+        assert_eq!(
+            scheduler.admit(ip(1)).await.unwrap_err(),
+            AdmissionError::PerIpLimit
+        );
+        let _other = scheduler.admit(ip(2)).await.unwrap();
+
+        drop(first);
+        let _third = scheduler.admit(ip(1)).await.unwrap();
+        assert_eq!(scheduler.metrics().rejected, 1);
+    }
+
+    #[tokio::test]
+    async fn queues_until_the_timeout_then_refuses_as_busy() {
+        let scheduler = scheduler(1, 4, Duration::from_millis(100));
+        let _held = scheduler.admit(ip(1)).await.unwrap();
+
+        let started = Instant::now();
+        let waiting = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.admit(ip(2)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert_eq!(scheduler.metrics().queued, 1);
+
+        let refused = waiting.await.unwrap().unwrap_err();
+        assert_eq!(refused, AdmissionError::QueueTimeout);
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert_eq!(scheduler.metrics().queued, 0);
+
+        // The refused connection no longer counts against its address.
+        let per_ip = scheduler.inner.per_ip.lock().unwrap();
+        assert_eq!(per_ip.get(&ip(2)), None);
+    }
+
+    #[tokio::test]
+    async fn admits_a_queued_connection_once_a_slot_frees_up() {
+        let scheduler = scheduler(1, 4, Duration::from_secs(5));
+        let held = scheduler.admit(ip(1)).await.unwrap();
+        let waiting = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.admit(ip(2)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(!waiting.is_finished());
+
+        drop(held);
+        assert!(waiting.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn releases_the_slot_when_the_permit_is_dropped() {
+        let scheduler = scheduler(1, 1, Duration::from_millis(50));
+        let permit = scheduler.admit(ip(1)).await.unwrap();
+        assert_eq!(scheduler.metrics().active, 1);
+
+        drop(permit);
+        let metrics = scheduler.metrics();
+        assert_eq!(metrics.active, 0);
+        assert_eq!(metrics.served, 1);
+        assert!(scheduler.inner.per_ip.lock().unwrap().is_empty());
+        assert!(scheduler.admit(ip(1)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn drain_finishes_once_the_permits_are_gone() {
+        let scheduler = scheduler(2, 2, Duration::from_secs(5));
+        let permit = scheduler.admit(ip(1)).await.unwrap();
+        let draining = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.drain(Duration::from_secs(5)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        assert!(scheduler.is_draining());
+        assert!(!draining.is_finished());
+        assert_eq!(
+            scheduler.admit(ip(2)).await.unwrap_err(),
+            AdmissionError::ShuttingDown
+        );
+
+        drop(permit);
+        assert!(draining.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn drain_gives_up_after_its_grace_period() {
+        let scheduler = scheduler(1, 1, Duration::from_secs(5));
+        let _held = scheduler.admit(ip(1)).await.unwrap();
+        assert!(!scheduler.drain(Duration::from_millis(50)).await);
+    }
+
+    #[tokio::test]
+    async fn shutdown_refuses_queued_connections() {
+        let scheduler = scheduler(1, 4, Duration::from_secs(5));
+        let _held = scheduler.admit(ip(1)).await.unwrap();
+        let waiting = tokio::spawn({
+            let scheduler = scheduler.clone();
+            async move { scheduler.admit(ip(2)).await }
+        });
+        tokio::time::sleep(Duration::from_millis(30)).await;
+
+        scheduler.shutdown();
+        assert_eq!(
+            waiting.await.unwrap().unwrap_err(),
+            AdmissionError::ShuttingDown
+        );
+    }
+}
@@ -1,8 +1,31 @@
 use simple_logger::SimpleLogger;
-use tcp::tcp::tcp::TCP;
+use tcp_server::pairing::pairing::Pairing;
+use tcp_server::scheduler::scheduler::Scheduler;
+use tcp_server::storage::storage::Storage;
+use tcp_server::tcp::tcp::{DEFAULT_PORT, TCP};
+use tcp_server::users::users::UserRegistry;
 
 #[tokio::main]
 async fn main() {
     SimpleLogger::new().init().unwrap();
-    TCP::run("localhost:8080").await.unwrap();
+    let pairing = Pairing::new();
+    log::info!("Pairing code: {}", pairing.code());
+    let users = UserRegistry::open(Storage::default(), None).unwrap();
+    let scheduler = Scheduler::default();
+    let on_interrupt = scheduler.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            on_interrupt.shutdown();
+        }
+    });
+    TCP::run(
+        &format!("localhost:{}", DEFAULT_PORT),
+        users,
+        None,
+        pairing,
+        scheduler,
+        None,
+    )
+    .await
+    .unwrap();
 }
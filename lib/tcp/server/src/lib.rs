@@ -1,11 +1,23 @@
 #[path = "../tcp/mod.rs"]
 pub mod tcp;
 
-#[path = "../Threadpool/mod.rs"]
-pub mod threadpool;
+#[path = "../scheduler/mod.rs"]
+pub mod scheduler;
 
 #[path = "../http/mod.rs"]
 pub mod http;
 
 #[path = "../users/mod.rs"]
 pub mod users;
+
+#[path = "../protocol/mod.rs"]
+pub mod protocol;
+
+#[path = "../storage/mod.rs"]
+pub mod storage;
+
+#[path = "../tls/mod.rs"]
+pub mod tls;
+
+#[path = "../pairing/mod.rs"]
+pub mod pairing;
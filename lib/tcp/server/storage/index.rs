@@ -0,0 +1,111 @@
+use std::collections::BTreeMap;
+use std::fs::Metadata;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::digest::Digest;
+use crate::protocol::manifest::mtime_of;
+
+/// Directory under a storage root holding the server's own bookkeeping. Client paths may not enter it.
+pub const METADATA_DIR: &str = ".zync";
+const INDEX_FILE: &str = "index.json";
+
+/// Serializes the read-modify-write cycles on every index, since several connections may store files for the same user at once.
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+/// What the server recorded about a stored file when it was uploaded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// The digest the upload was verified against.
+    pub digest: Digest,
+    /// The user who uploaded the file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uploader: Option<String>,
+    /// Size of the file when it was recorded.
+    pub size: u64,
+    /// Modification time of the file when it was recorded, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mtime: Option<u64>,
+}
+
+/// The digests and uploaders of the files in a storage, kept in `.zync/index.json` under its root.
+///
+/// Entries are keyed by the file's path relative to the root, with `/` separators. A file changed behind the server's back no longer matches the size and modification time it was recorded with, and its entry is ignored rather than trusted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileIndex {
+    #[serde(default)]
+    files: BTreeMap<String, IndexEntry>,
+}
+
+impl FileIndex {
+    /// Loads the index of the storage rooted at `root`. A missing or unreadable index is treated as empty, since it only caches what can be recomputed.
+    pub fn load(root: &Path) -> Self {
+        let contents = match std::fs::read_to_string(index_path(root)) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Self::default(),
+            Err(e) => {
+                log::warn!("Failed to read the file index of {:?}: {}", root, e);
+                return Self::default();
+            }
+        };
+        serde_json::from_str(&contents).unwrap_or_else(|e| {
+            log::warn!("Ignoring the corrupt file index of {:?}: {}", root, e);
+            Self::default()
+        })
+    }
+
+    /// The entry recorded for the file at `key`, if it still describes the file's current `metadata`.
+    pub fn get(&self, key: &str, metadata: &Metadata) -> Option<&IndexEntry> {
+        self.files
+            .get(key)
+            .filter(|entry| entry.size == metadata.len() && entry.mtime == mtime_of(metadata))
+    }
+
+    fn save(&self, root: &Path) -> io::Result<()> {
+        let path = index_path(root);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let staged = path.with_extension("json.tmp");
+        std::fs::write(&staged, contents)?;
+        std::fs::rename(staged, path)
+    }
+}
+
+/// Records `entry` for the file at `key` in the index of the storage rooted at `root`.
+///
+/// # Errors
+/// Returns an error if the index cannot be written.
+pub fn record(root: &Path, key: &str, entry: IndexEntry) -> io::Result<()> {
+    let _guard = INDEX_LOCK.lock().unwrap();
+    let mut index = FileIndex::load(root);
+    index.files.insert(key.to_string(), entry);
+    index.save(root)
+}
+
+/// Drops the entries of the file or directory at `key`, and of everything below it, from the index of the storage rooted at `root`.
+///
+/// # Errors
+/// Returns an error if the index cannot be written.
+pub fn forget(root: &Path, key: &str) -> io::Result<()> {
+    let _guard = INDEX_LOCK.lock().unwrap();
+    let mut index = FileIndex::load(root);
+    let prefix = format!("{key}/");
+    let before = index.files.len();
+    index
+        .files
+        .retain(|path, _| path != key && !path.starts_with(&prefix));
+    if index.files.len() == before {
+        return Ok(());
+    }
+    index.save(root)
+}
+
+fn index_path(root: &Path) -> PathBuf {
+    root.join(METADATA_DIR).join(INDEX_FILE)
+}
@@ -0,0 +1,950 @@
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+
+use tokio::fs::{self, File};
+use tokio::io::AsyncReadExt;
+
+use super::index::{self, FileIndex, IndexEntry, METADATA_DIR};
+use crate::http::storage::STORAGE_PATH;
+use crate::protocol::digest::{Digest, HashAlgorithm, Hasher};
+use crate::protocol::error::RequestError;
+use crate::protocol::manifest::{EntryKind, Manifest, mtime_of};
+use crate::protocol::messages::{ErrorCode, RemoteEntry};
+
+/// The directory tree a server stores uploaded files in.
+///
+/// Every handler, raw TCP and HTTP alike, goes through this type, so files uploaded through one transport are visible to the other. All client-supplied paths are resolved relative to [`Storage::root`] and rejected if they try to leave it.
+#[derive(Debug, Clone)]
+pub struct Storage {
+    root: PathBuf,
+    max_upload_size: Option<u64>,
+    quota: Option<u64>,
+    owner: Option<String>,
+}
+
+/// A file being written into storage.
+///
+/// Data is written to a hidden `.part` file next to the target, which only replaces the target once [`PendingWrite::commit`] is called.
+#[derive(Debug)]
+pub struct PendingWrite {
+    pub file: File,
+    part_path: PathBuf,
+    path: PathBuf,
+    offset: u64,
+}
+
+impl Default for Storage {
+    fn default() -> Self {
+        Self::new(STORAGE_PATH.clone())
+    }
+}
+
+impl Storage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            max_upload_size: None,
+            quota: None,
+            owner: None,
+        }
+    }
+
+    /// Sets the largest file, in bytes, that may be uploaded; `None` accepts any size.
+    pub fn with_max_upload_size(mut self, max_upload_size: Option<u64>) -> Self {
+        self.max_upload_size = max_upload_size;
+        self
+    }
+
+    /// Sets how many bytes the files in this storage may take up in total; `None` means no quota.
+    pub fn with_quota(mut self, quota: Option<u64>) -> Self {
+        self.quota = quota;
+        self
+    }
+
+    /// Sets the user the storage belongs to, who is recorded as the uploader of the files stored in it.
+    pub fn with_owner(mut self, owner: impl Into<String>) -> Self {
+        self.owner = Some(owner.into());
+        self
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn max_upload_size(&self) -> Option<u64> {
+        self.max_upload_size
+    }
+
+    pub fn quota(&self) -> Option<u64> {
+        self.quota
+    }
+
+    pub fn owner(&self) -> Option<&str> {
+        self.owner.as_deref()
+    }
+
+    /// Checks an upload of `size` bytes against the configured limit.
+    ///
+    /// # Errors
+    /// Returns `TooLarge` if `size` exceeds the limit.
+    pub fn check_upload_size(&self, size: u64) -> Result<(), RequestError> {
+        match self.max_upload_size {
+            Some(limit) if size > limit => Err(RequestError::new(
+                ErrorCode::TooLarge,
+                format!("Upload of {size} bytes exceeds the {limit} byte limit"),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that storing `size` bytes at `relative` keeps the storage within its quota.
+    ///
+    /// Whatever the entry and its `.part` file hold already is not counted, since the upload replaces it.
+    ///
+    /// # Errors
+    /// Returns `QuotaExceeded` if the upload would take the storage over its quota, or an error if the storage cannot be measured.
+    pub fn check_quota(&self, relative: &str, size: u64) -> Result<(), RequestError> {
+        if self.quota.is_none() {
+            return Ok(());
+        }
+        self.check_quota_replacing(self.replaced_len(relative), size)
+    }
+
+    /// Checks that `size` more bytes fit the quota once `replaced` bytes already in storage are overwritten.
+    fn check_quota_replacing(&self, replaced: u64, size: u64) -> Result<(), RequestError> {
+        let Some(quota) = self.quota else {
+            return Ok(());
+        };
+        let used = self
+            .usage()
+            .map_err(|e| RequestError::from(&e))?
+            .saturating_sub(replaced);
+        if used + size > quota {
+            return Err(RequestError::new(
+                ErrorCode::QuotaExceeded,
+                format!(
+                    "Upload of {size} bytes exceeds the {quota} byte quota ({used} bytes in use)"
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// How many bytes the entry at `relative` and its `.part` file hold, which an upload to it replaces.
+    fn replaced_len(&self, relative: &str) -> u64 {
+        self.resolve_entry(relative)
+            .map(|path| file_len(&path) + file_len(&part_path_for(&path)))
+            .unwrap_or(0)
+    }
+
+    /// Prepares storage for the directory tree described by `manifest`, before any of its files are sent.
+    ///
+    /// Every path is checked first, along with each file's size against the upload limit and the whole tree against the quota, so a tree that cannot be stored is rejected before anything is created. Then the tree's directories are created with their modes, never granting less than full access to the owner so the files can still be written into them.
+    ///
+    /// # Errors
+    /// Returns `InvalidPath` if a path escapes storage or a directory in the tree exists as a file, `TooLarge` or `QuotaExceeded` if the files do not fit, or an error if a directory cannot be created.
+    pub fn prepare_tree(&self, manifest: &Manifest) -> Result<(), RequestError> {
+        check_relative(Path::new(&manifest.root))?;
+        let mut replaced = 0;
+        for entry in &manifest.entries {
+            let relative = manifest.remote_path(entry);
+            check_relative(Path::new(&relative))?;
+            if entry.kind == EntryKind::File {
+                self.check_upload_size(entry.size)?;
+                replaced += self.replaced_len(&relative);
+            }
+        }
+        self.check_quota_replacing(replaced, manifest.total_size())?;
+
+        for entry in &manifest.entries {
+            let relative = manifest.remote_path(entry);
+            let path = self.resolve_new_entry(&relative)?;
+            match entry.kind {
+                EntryKind::Directory => {
+                    if !path.exists() {
+                        std::fs::create_dir(&path).map_err(|e| RequestError::from(&e))?;
+                    } else if !path.is_dir() {
+                        return Err(RequestError::invalid_path(format!(
+                            "{relative} exists and is not a directory"
+                        )));
+                    }
+                    apply_metadata(&path, entry.mode.map(|mode| mode | 0o700), None)
+                        .map_err(|e| RequestError::from(&e))?;
+                }
+                EntryKind::File if path.is_dir() => {
+                    return Err(RequestError::invalid_path(format!(
+                        "{relative} is a directory"
+                    )));
+                }
+                EntryKind::File => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the total size in bytes of the files under the storage root, including partial uploads.
+    pub fn usage(&self) -> std::io::Result<u64> {
+        self.ensure_root()?;
+        dir_usage(&self.root)
+    }
+
+    /// Returns how many more bytes the storage can take: the space left on the file system holding its root, capped by what remains of its quota.
+    ///
+    /// `None` if there is no quota and the file system's free space cannot be read, which is always the case outside Unix.
+    pub fn free_space(&self) -> Option<u64> {
+        let available = available_space(&self.root);
+        let remaining = self
+            .quota
+            .map(|quota| quota.saturating_sub(self.usage().unwrap_or(0)));
+        match (available, remaining) {
+            (Some(available), Some(remaining)) => Some(available.min(remaining)),
+            (available, remaining) => available.or(remaining),
+        }
+    }
+
+    /// Creates the storage root if it does not exist yet.
+    pub fn ensure_root(&self) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.root)
+    }
+
+    /// Describes the files and directories in the directory at `relative`, or at the storage root if it is empty, and with `recursive` everything below them.
+    ///
+    /// Entries are sorted by name, each directory followed by its contents. Paths are relative to the storage root. Digests and uploaders come from the storage's [`FileIndex`], for files that have not changed since they were uploaded. Partial uploads, symlinks and the server's own `.zync` directory are left out.
+    ///
+    /// # Errors
+    /// Returns `InvalidPath` if the path escapes storage or is not a directory, `NotFound` if it does not exist, or an error if a directory cannot be read.
+    pub fn entries(
+        &self,
+        relative: &str,
+        recursive: bool,
+    ) -> Result<Vec<RemoteEntry>, RequestError> {
+        let root = self.canonical_root()?;
+        let dir = if relative.trim_matches('/').is_empty() {
+            root.clone()
+        } else {
+            self.resolve_existing(relative)?
+        };
+        if !dir.is_dir() {
+            return Err(RequestError::invalid_path(format!(
+                "{relative} is not a directory"
+            )));
+        }
+        let prefix = index_key(&root, &dir).unwrap_or_default();
+        let index = FileIndex::load(&root);
+        let mut entries = Vec::new();
+        collect_entries(&dir, &prefix, recursive, &index, &mut entries)
+            .map_err(|e| RequestError::from(&e))?;
+        Ok(entries)
+    }
+
+    /// Records the digest a file committed to `path` was verified against, along with the storage's owner as its uploader.
+    ///
+    /// The index only caches what can be recomputed, so a failure to update it is logged rather than failing the upload.
+    pub fn record_upload(&self, path: &Path, digest: &Digest) {
+        let recorded = self
+            .canonical_root()
+            .map_err(std::io::Error::other)
+            .and_then(|root| {
+                let metadata = std::fs::metadata(path)?;
+                let key = index_key(&root, path)
+                    .ok_or_else(|| std::io::Error::other("File is outside the storage root"))?;
+                let entry = IndexEntry {
+                    digest: digest.clone(),
+                    uploader: self.owner.clone(),
+                    size: metadata.len(),
+                    mtime: mtime_of(&metadata),
+                };
+                index::record(&root, &key, entry)
+            });
+        if let Err(e) = recorded {
+            log::warn!("Failed to record the digest of {:?}: {}", path, e);
+        }
+    }
+
+    /// Opens the file at `relative` for reading.
+    ///
+    /// # Returns
+    /// The open file and its size in bytes.
+    ///
+    /// # Errors
+    /// Returns `InvalidPath` if the path escapes storage or names a directory, or `NotFound` if it does not exist.
+    pub async fn open_read(&self, relative: &str) -> Result<(File, u64), RequestError> {
+        let path = self.resolve_existing(relative)?;
+        if path.is_dir() {
+            return Err(RequestError::invalid_path(format!(
+                "{relative} is a directory"
+            )));
+        }
+        let file = File::open(&path)
+            .await
+            .map_err(|e| RequestError::from(&e))?;
+        let size = file
+            .metadata()
+            .await
+            .map_err(|e| RequestError::from(&e))?
+            .len();
+        Ok((file, size))
+    }
+
+    /// Starts writing a file at `relative`, creating missing parent directories.
+    ///
+    /// # Errors
+    /// Returns `InvalidPath` if the path escapes storage or names a directory, or an error if the `.part` file cannot be created.
+    pub async fn create_write(&self, relative: &str) -> Result<PendingWrite, RequestError> {
+        let path = self.resolve_new_entry(relative)?;
+        if path.is_dir() {
+            return Err(RequestError::invalid_path(format!(
+                "{relative} is a directory"
+            )));
+        }
+
+        let part_path = part_path_for(&path);
+        let file = File::create(&part_path)
+            .await
+            .map_err(|e| RequestError::from(&e))?;
+        Ok(PendingWrite {
+            file,
+            part_path,
+            path,
+            offset: 0,
+        })
+    }
+
+    /// Continues writing a file of `size` bytes at `relative`, keeping whatever an earlier, interrupted write left in its `.part` file.
+    ///
+    /// A `.part` file longer than `size` cannot belong to this upload and is truncated. [`PendingWrite::offset`] reports how many bytes are already there.
+    ///
+    /// # Errors
+    /// Returns `InvalidPath` if the path escapes storage or names a directory, or an error if the `.part` file cannot be opened.
+    pub async fn resume_write(
+        &self,
+        relative: &str,
+        size: u64,
+    ) -> Result<PendingWrite, RequestError> {
+        let path = self.resolve_new_entry(relative)?;
+        if path.is_dir() {
+            return Err(RequestError::invalid_path(format!(
+                "{relative} is a directory"
+            )));
+        }
+
+        let part_path = part_path_for(&path);
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&part_path)
+            .await
+            .map_err(|e| RequestError::from(&e))?;
+        let mut offset = file
+            .metadata()
+            .await
+            .map_err(|e| RequestError::from(&e))?
+            .len();
+        if offset > size {
+            file.set_len(0).await.map_err(|e| RequestError::from(&e))?;
+            offset = 0;
+        }
+        Ok(PendingWrite {
+            file,
+            part_path,
+            path,
+            offset,
+        })
+    }
+
+    /// Returns how many bytes an interrupted write to `relative` has left in its `.part` file, or 0 if there is none.
+    ///
+    /// # Errors
+    /// Returns `InvalidPath` if the path escapes storage.
+    pub fn partial_len(&self, relative: &str) -> Result<u64, RequestError> {
+        let path = match self.resolve_entry(relative) {
+            Ok(path) => path,
+            Err(e) if e.code == ErrorCode::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        Ok(std::fs::metadata(part_path_for(&path))
+            .map(|metadata| metadata.len())
+            .unwrap_or(0))
+    }
+
+    /// Removes the entry at `relative`.
+    ///
+    /// Files and symlinks are unlinked (a symlink's target is never touched) and directories are removed recursively.
+    ///
+    /// # Errors
+    /// Returns `InvalidPath` if the path escapes storage, `NotFound` if nothing exists there, or an error if removal fails.
+    pub async fn delete(&self, relative: &str) -> Result<PathBuf, RequestError> {
+        let path = self.resolve_entry(relative)?;
+        let metadata = fs::symlink_metadata(&path).await.map_err(|_| {
+            RequestError::new(ErrorCode::NotFound, format!("{relative} does not exist"))
+        })?;
+        let removed = if metadata.is_dir() {
+            fs::remove_dir_all(&path).await
+        } else {
+            fs::remove_file(&path).await
+        };
+        removed.map_err(|e| RequestError::from(&e))?;
+        let root = self.canonical_root()?;
+        if let Some(key) = index_key(&root, &path) {
+            if let Err(e) = index::forget(&root, &key) {
+                log::warn!("Failed to drop {:?} from the file index: {}", path, e);
+            }
+        }
+        Ok(path)
+    }
+
+    /// Resolves a client-supplied `relative` path to an entry inside the root, without following the entry itself.
+    ///
+    /// Rejects empty paths, absolute paths and `..` components outright, then canonicalizes the entry's parent directory so a symlinked directory cannot lead outside of the root.
+    ///
+    /// # Errors
+    /// Returns an `InvalidPath` error if the path is not allowed, or `NotFound` if its parent directory does not exist.
+    pub fn resolve_entry(&self, relative: &str) -> Result<PathBuf, RequestError> {
+        let relative = Path::new(relative);
+        check_relative(relative)?;
+
+        let name = relative
+            .file_name()
+            .ok_or_else(|| RequestError::invalid_path("Path must name an entry inside storage"))?;
+        let root = self.canonical_root()?;
+        let parent = match relative.parent() {
+            Some(parent) => root.join(parent).canonicalize().map_err(|_| {
+                RequestError::new(
+                    ErrorCode::NotFound,
+                    format!("{} does not exist", parent.display()),
+                )
+            })?,
+            None => root.clone(),
+        };
+        if !parent.starts_with(&root) {
+            return Err(RequestError::invalid_path("Path escapes the storage root"));
+        }
+
+        Ok(parent.join(name))
+    }
+
+    /// Resolves `relative` like [`Storage::resolve_entry`] and then follows the entry itself, for requests that read its contents.
+    ///
+    /// # Errors
+    /// Returns `NotFound` if nothing exists at the path, or `InvalidPath` if it is a symlink pointing outside of the root.
+    pub fn resolve_existing(&self, relative: &str) -> Result<PathBuf, RequestError> {
+        let entry = self.resolve_entry(relative)?;
+        let resolved = entry.canonicalize().map_err(|_| {
+            RequestError::new(ErrorCode::NotFound, format!("{relative} does not exist"))
+        })?;
+        if !resolved.starts_with(self.canonical_root()?) {
+            return Err(RequestError::invalid_path("Path escapes the storage root"));
+        }
+        Ok(resolved)
+    }
+
+    /// Resolves `relative` like [`Storage::resolve_entry`] for an entry about to be written, creating its missing parent directories.
+    ///
+    /// Every directory on the way is checked to stay inside the root before the next one is created, so a symlink inside storage cannot be used to create directories elsewhere.
+    ///
+    /// # Errors
+    /// Returns `InvalidPath` if the path is not allowed, or an `Internal` error if a directory cannot be created.
+    pub fn resolve_new_entry(&self, relative: &str) -> Result<PathBuf, RequestError> {
+        let relative_path = Path::new(relative);
+        check_relative(relative_path)?;
+
+        let canonical_root = self.canonical_root()?;
+        let mut current = canonical_root.clone();
+        if let Some(parent) = relative_path.parent() {
+            for component in parent.components() {
+                current.push(component);
+                if !current.exists() {
+                    std::fs::create_dir(&current).map_err(|e| RequestError::from(&e))?;
+                }
+                current = current.canonicalize().map_err(|e| RequestError::from(&e))?;
+                if !current.starts_with(&canonical_root) {
+                    return Err(RequestError::invalid_path("Path escapes the storage root"));
+                }
+            }
+        }
+
+        self.resolve_entry(relative)
+    }
+
+    fn canonical_root(&self) -> Result<PathBuf, RequestError> {
+        self.ensure_root().map_err(|e| RequestError::from(&e))?;
+        self.root.canonicalize().map_err(|e| RequestError::from(&e))
+    }
+}
+
+impl PendingWrite {
+    /// The final location of the file inside storage.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Flushes the `.part` file to disk and atomically renames it over the target.
+    pub async fn commit(self) -> std::io::Result<PathBuf> {
+        self.file.sync_all().await?;
+        drop(self.file);
+        fs::rename(&self.part_path, &self.path).await?;
+        Ok(self.path)
+    }
+
+    /// How many bytes the `.part` file held when the write was opened; new data is appended after them.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns a hasher that has already consumed the [`PendingWrite::offset`] bytes an earlier write left behind, so the digest covers the whole file once the rest is written.
+    pub async fn existing_hasher(&self, algorithm: HashAlgorithm) -> std::io::Result<Hasher> {
+        let mut hasher = Hasher::new(algorithm);
+        if self.offset > 0 {
+            let file = File::open(&self.part_path).await?;
+            hasher.update_reader(&mut file.take(self.offset)).await?;
+        }
+        Ok(hasher)
+    }
+
+    /// Flushes the `.part` file and leaves it in place so a later [`Storage::resume_write`] can continue it.
+    pub async fn suspend(self) {
+        if let Err(e) = self.file.sync_all().await {
+            log::warn!("Failed to flush partial file {:?}: {}", self.part_path, e);
+        }
+        log::info!("Keeping partial file {:?} for resume", self.part_path);
+    }
+
+    /// Discards everything written so far.
+    pub fn abort(self) {
+        drop(self.file);
+        if let Err(e) = std::fs::remove_file(&self.part_path) {
+            log::warn!("Failed to remove partial file {:?}: {}", self.part_path, e);
+        }
+    }
+}
+
+/// Gives the entry at `path` the permission bits `mode` and the modification time `mtime`, in seconds since the Unix epoch, where set.
+///
+/// Only the read, write and execute bits of `mode` are applied, so a client cannot create setuid files; modes are ignored on platforms without them.
+pub fn apply_metadata(path: &Path, mode: Option<u32>, mtime: Option<u64>) -> std::io::Result<()> {
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode & 0o777))?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    if let Some(mtime) = mtime {
+        let modified = UNIX_EPOCH + Duration::from_secs(mtime);
+        std::fs::File::open(path)?.set_modified(modified)?;
+    }
+    Ok(())
+}
+
+/// The bytes an unprivileged user may still write to the file system holding `path`.
+#[cfg(unix)]
+// The field types of `statvfs` differ between platforms.
+#[allow(clippy::unnecessary_cast)]
+fn available_space(path: &Path) -> Option<u64> {
+    let stats = rustix::fs::statvfs(path).ok()?;
+    Some((stats.f_bavail as u64).saturating_mul(stats.f_frsize as u64))
+}
+
+#[cfg(not(unix))]
+fn available_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+fn file_len(path: &Path) -> u64 {
+    std::fs::symlink_metadata(path)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0)
+}
+
+/// Sums the sizes of the files below `dir`, without following symlinks or counting the server's own `.zync` directory.
+fn dir_usage(dir: &Path) -> std::io::Result<u64> {
+    let mut total = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() && entry.file_name() == METADATA_DIR {
+            continue;
+        } else if file_type.is_dir() {
+            total += dir_usage(&entry.path())?;
+        } else if file_type.is_file() {
+            total += entry.metadata()?.len();
+        }
+    }
+    Ok(total)
+}
+
+/// The [`FileIndex`] key of the entry at `path`: its path relative to the canonical `root`, with `/` separators.
+fn index_key(root: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(root).ok()?;
+    Some(
+        relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+/// Describes the entries of `dir`, whose path relative to the storage root is `prefix`, descending into subdirectories if `recursive` is set.
+fn collect_entries(
+    dir: &Path,
+    prefix: &str,
+    recursive: bool,
+    index: &FileIndex,
+    entries: &mut Vec<RemoteEntry>,
+) -> std::io::Result<()> {
+    let mut children = std::fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    children.sort_by_key(|child| child.file_name());
+    for child in children {
+        let name = child.file_name().to_string_lossy().into_owned();
+        if name == METADATA_DIR || is_part_file(&name) {
+            continue;
+        }
+        let path = if prefix.is_empty() {
+            name
+        } else {
+            format!("{prefix}/{name}")
+        };
+        let metadata = std::fs::symlink_metadata(child.path())?;
+        let kind = if metadata.is_dir() {
+            EntryKind::Directory
+        } else if metadata.is_file() {
+            EntryKind::File
+        } else {
+            continue;
+        };
+        let recorded = match kind {
+            EntryKind::File => index.get(&path, &metadata),
+            EntryKind::Directory => None,
+        };
+        entries.push(RemoteEntry {
+            path: path.clone(),
+            kind,
+            size: if kind == EntryKind::File {
+                metadata.len()
+            } else {
+                0
+            },
+            mtime: mtime_of(&metadata),
+            digest: recorded.map(|entry| entry.digest.clone()),
+            uploader: recorded.and_then(|entry| entry.uploader.clone()),
+        });
+        if recursive && kind == EntryKind::Directory {
+            collect_entries(&child.path(), &path, recursive, index, entries)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether `name` is the hidden `.part` file of an upload in progress.
+fn is_part_file(name: &str) -> bool {
+    name.starts_with('.') && name.ends_with(".part")
+}
+
+/// The hidden `.part` file an entry at `path` is written to before it is committed.
+fn part_path_for(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{file_name}.part"))
+}
+
+/// Rejects paths that are absolute, climb out with `..` or enter the server's `.zync` directory.
+fn check_relative(relative: &Path) -> Result<(), RequestError> {
+    for component in relative.components() {
+        match component {
+            Component::Normal(name) if name == METADATA_DIR => {
+                return Err(RequestError::invalid_path(format!(
+                    "Path must not enter {METADATA_DIR}"
+                )));
+            }
+            Component::Normal(_) | Component::CurDir => {}
+            Component::ParentDir => {
+                return Err(RequestError::invalid_path("Path must not contain '..'"));
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                return Err(RequestError::invalid_path("Path must be relative"));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    fn storage() -> (tempfile::TempDir, Storage) {
+        let dir = tempfile::tempdir().unwrap();
+        let storage = Storage::new(dir.path().join("root"));
+        storage.ensure_root().unwrap();
+        (dir, storage)
+    }
+
+    fn rejected(result: Result<PathBuf, RequestError>) -> ErrorCode {
+        result.unwrap_err().code
+    }
+
+    #[test]
+    fn rejects_parent_components() {
+        let (_dir, storage) = storage();
+
+        for path in ["..", "../outside.txt", "a/../../outside.txt", "a/.."] {
+            assert_eq!(
+                rejected(storage.resolve_entry(path)),
+                ErrorCode::InvalidPath
+            );
+            assert_eq!(
+                rejected(storage.resolve_new_entry(path)),
+                ErrorCode::InvalidPath
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_absolute_paths() {
+        let (_dir, storage) = storage();
+
+        assert_eq!(
+            rejected(storage.resolve_entry("/etc/passwd")),
+            ErrorCode::InvalidPath
+        );
+        assert_eq!(
+            rejected(storage.resolve_new_entry("/tmp/new.txt")),
+            ErrorCode::InvalidPath
+        );
+        assert_eq!(
+            rejected(storage.resolve_existing("/etc/passwd")),
+            ErrorCode::InvalidPath
+        );
+    }
+
+    #[test]
+    fn rejects_the_metadata_directory() {
+        let (_dir, storage) = storage();
+
+        for path in [".zync", ".zync/index.json", "a/.zync/x"] {
+            assert_eq!(
+                rejected(storage.resolve_entry(path)),
+                ErrorCode::InvalidPath
+            );
+            assert_eq!(
+                rejected(storage.resolve_new_entry(path)),
+                ErrorCode::InvalidPath
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_empty_paths() {
+        let (_dir, storage) = storage();
+
+        assert_eq!(rejected(storage.resolve_entry("")), ErrorCode::InvalidPath);
+    }
+
+    #[test]
+    fn accepts_nested_relative_paths() {
+        let (_dir, storage) = storage();
+        let root = storage.root().canonicalize().unwrap();
+
+        let created = storage.resolve_new_entry("a/b/c.txt").unwrap();
+        assert_eq!(created, root.join("a/b/c.txt"));
+        assert!(root.join("a/b").is_dir());
+
+        std::fs::write(&created, b"data").unwrap();
+        assert_eq!(storage.resolve_entry("./a/b/c.txt").unwrap(), created);
+        assert_eq!(storage.resolve_existing("a/b/c.txt").unwrap(), created);
+    }
+
+    #[test]
+    fn reports_missing_parents() {
+        let (_dir, storage) = storage();
+
+        assert_eq!(
+            rejected(storage.resolve_entry("missing/c.txt")),
+            ErrorCode::NotFound
+        );
+        assert_eq!(
+            rejected(storage.resolve_existing("missing.txt")),
+            ErrorCode::NotFound
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_pointing_outside_the_root() {
+        let (dir, storage) = storage();
+        let outside = dir.path().join("outside");
+        std::fs::create_dir(&outside).unwrap();
+        std::fs::write(outside.join("secret.txt"), b"secret").unwrap();
+        std::os::unix::fs::symlink(&outside, storage.root().join("link")).unwrap();
+        std::os::unix::fs::symlink(
+            outside.join("secret.txt"),
+            storage.root().join("secret-link"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            rejected(storage.resolve_entry("link/secret.txt")),
+            ErrorCode::InvalidPath
+        );
+        assert_eq!(
+            rejected(storage.resolve_existing("link")),
+            ErrorCode::InvalidPath
+        );
+        assert_eq!(
+            rejected(storage.resolve_existing("secret-link")),
+            ErrorCode::InvalidPath
+        );
+        assert_eq!(
+            rejected(storage.resolve_new_entry("link/sub/new.txt")),
+            ErrorCode::InvalidPath
+        );
+        assert!(!outside.join("sub").exists());
+    }
+
+    fn manifest(root: &str, entries: &[(&str, EntryKind, u64)]) -> Manifest {
+        Manifest {
+            root: root.to_string(),
+            entries: entries
+                .iter()
+                .map(
+                    |&(path, kind, size)| crate::protocol::manifest::ManifestEntry {
+                        path: path.to_string(),
+                        kind,
+                        size,
+                        mode: None,
+                        mtime: None,
+                    },
+                )
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn prepare_tree_creates_the_directories() {
+        let (_dir, storage) = storage();
+        let tree = manifest(
+            "upload",
+            &[
+                ("a", EntryKind::Directory, 0),
+                ("a/b", EntryKind::Directory, 0),
+                ("a/b/c.txt", EntryKind::File, 10),
+            ],
+        );
+
+        storage.prepare_tree(&tree).unwrap();
+        assert!(storage.root().join("upload/a/b").is_dir());
+        assert!(!storage.root().join("upload/a/b/c.txt").exists());
+        storage.prepare_tree(&tree).unwrap();
+    }
+
+    #[test]
+    fn prepare_tree_rejects_trees_before_creating_anything() {
+        let (_dir, storage) = storage();
+        let escaping = manifest(
+            "upload",
+            &[
+                ("a", EntryKind::Directory, 0),
+                ("../../outside", EntryKind::Directory, 0),
+            ],
+        );
+        assert_eq!(
+            storage.prepare_tree(&escaping).unwrap_err().code,
+            ErrorCode::InvalidPath
+        );
+        assert!(!storage.root().join("upload").exists());
+
+        let storage = storage
+            .with_max_upload_size(Some(100))
+            .with_quota(Some(150));
+        let large = manifest(
+            "upload",
+            &[
+                ("a", EntryKind::Directory, 0),
+                ("a/big", EntryKind::File, 101),
+            ],
+        );
+        assert_eq!(
+            storage.prepare_tree(&large).unwrap_err().code,
+            ErrorCode::TooLarge
+        );
+        let over_quota = manifest(
+            "upload",
+            &[
+                ("a", EntryKind::Directory, 0),
+                ("a/one", EntryKind::File, 80),
+                ("a/two", EntryKind::File, 80),
+            ],
+        );
+        assert_eq!(
+            storage.prepare_tree(&over_quota).unwrap_err().code,
+            ErrorCode::QuotaExceeded
+        );
+        assert!(!storage.root().join("upload").exists());
+    }
+
+    #[test]
+    fn prepare_tree_rejects_files_in_place_of_directories() {
+        let (_dir, storage) = storage();
+        std::fs::write(storage.root().join("a"), b"file").unwrap();
+        let tree = manifest("", &[("a", EntryKind::Directory, 0)]);
+        assert_eq!(
+            storage.prepare_tree(&tree).unwrap_err().code,
+            ErrorCode::InvalidPath
+        );
+    }
+
+    #[tokio::test]
+    async fn resume_write_continues_after_the_partial_file() {
+        let (_dir, storage) = storage();
+        assert_eq!(storage.partial_len("dir/file.bin").unwrap(), 0);
+
+        let mut write = storage.create_write("dir/file.bin").await.unwrap();
+        assert_eq!(write.offset(), 0);
+        write.file.write_all(b"hello ").await.unwrap();
+        write.suspend().await;
+        assert_eq!(storage.partial_len("dir/file.bin").unwrap(), 6);
+
+        let mut write = storage.resume_write("dir/file.bin", 11).await.unwrap();
+        assert_eq!(write.offset(), 6);
+        let mut hasher = write.existing_hasher(HashAlgorithm::Sha256).await.unwrap();
+        write.file.write_all(b"world").await.unwrap();
+        hasher.update(b"world");
+        let path = write.commit().await.unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello world");
+        let mut whole = Hasher::new(HashAlgorithm::Sha256);
+        whole.update(b"hello world");
+        assert_eq!(hasher.finalize(), whole.finalize());
+        assert_eq!(storage.partial_len("dir/file.bin").unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn resume_write_discards_partial_files_longer_than_the_upload() {
+        let (_dir, storage) = storage();
+        let mut write = storage.create_write("file.bin").await.unwrap();
+        write.file.write_all(b"far too long").await.unwrap();
+        write.suspend().await;
+
+        let write = storage.resume_write("file.bin", 4).await.unwrap();
+        assert_eq!(write.offset(), 0);
+        assert_eq!(storage.partial_len("file.bin").unwrap(), 0);
+        write.abort();
+    }
+
+    #[tokio::test]
+    async fn resume_write_starts_from_zero_without_a_partial_file() {
+        let (_dir, storage) = storage();
+        let write = storage.resume_write("new.bin", 100).await.unwrap();
+        assert_eq!(write.offset(), 0);
+        write.abort();
+        assert_eq!(storage.partial_len("new.bin").unwrap(), 0);
+        assert_eq!(
+            storage.partial_len("../escape").unwrap_err().code,
+            ErrorCode::InvalidPath
+        );
+    }
+}
@@ -1,11 +1,17 @@
 use core::fmt;
 
+use crate::protocol::frame::Opcode;
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum AllowedRequest {
     Get,
     Put,
     Delete,
     List,
+    Pair,
+    Confirm,
+    Manifest,
+    Hello,
 }
 
 impl fmt::Display for AllowedRequest {
@@ -15,17 +21,40 @@ impl fmt::Display for AllowedRequest {
             AllowedRequest::Put => write!(f, "PUT"),
             AllowedRequest::Delete => write!(f, "DELETE"),
             AllowedRequest::List => write!(f, "LIST"),
+            AllowedRequest::Pair => write!(f, "PAIR"),
+            AllowedRequest::Confirm => write!(f, "CONFIRM"),
+            AllowedRequest::Manifest => write!(f, "MANIFEST"),
+            AllowedRequest::Hello => write!(f, "HELLO"),
         }
     }
 }
 
 impl AllowedRequest {
-    pub fn from_str_slice(request: &str) -> Option<Self> {
-        match request {
-            req if req.starts_with("PUT") => Some(AllowedRequest::Put),
-            req if req.starts_with("LIST") => Some(AllowedRequest::List),
-            req if req.starts_with("DELETE") => Some(AllowedRequest::Delete),
-            req if req.starts_with("GET") => Some(AllowedRequest::Get),
+    /// Every request the server handles.
+    pub const ALL: [AllowedRequest; 8] = [
+        AllowedRequest::Hello,
+        AllowedRequest::Pair,
+        AllowedRequest::Confirm,
+        AllowedRequest::List,
+        AllowedRequest::Get,
+        AllowedRequest::Put,
+        AllowedRequest::Delete,
+        AllowedRequest::Manifest,
+    ];
+
+    /// Maps a request frame's opcode to the command it carries.
+    ///
+    /// Reply opcodes (`SEND`, `DATA`, `OK`, ...) are not requests and yield `None`.
+    pub fn from_opcode(opcode: Opcode) -> Option<Self> {
+        match opcode {
+            Opcode::Put => Some(AllowedRequest::Put),
+            Opcode::List => Some(AllowedRequest::List),
+            Opcode::Delete => Some(AllowedRequest::Delete),
+            Opcode::Get => Some(AllowedRequest::Get),
+            Opcode::Pair => Some(AllowedRequest::Pair),
+            Opcode::Confirm => Some(AllowedRequest::Confirm),
+            Opcode::Manifest => Some(AllowedRequest::Manifest),
+            Opcode::Hello => Some(AllowedRequest::Hello),
             _ => None,
         }
     }
@@ -1,103 +1,430 @@
 use super::allowed_request::AllowedRequest;
+use crate::http::delete::delete_file;
 use crate::http::get::get_file;
+use crate::http::manifest::receive_manifest;
 use crate::http::methods::list;
-use crate::http::put::router;
-use crate::threadpool::thread::Threadpool;
+use crate::http::upload::upload_file;
+use crate::http::{download, hello, pair, put};
+use crate::pairing::pairing::{PairedClient, Pairing, not_paired};
+use crate::protocol::codec::{FrameCodec, Transport};
+use crate::protocol::error::RequestError;
+use crate::protocol::frame::Opcode;
+use crate::protocol::messages::{ErrorCode, ErrorReply, Feature, HttpEndpoint, Scheme};
+use crate::protocol::transfer::send_error;
+use crate::scheduler::scheduler::{Scheduler, scheduled_incoming};
+use crate::storage::storage::Storage;
+use crate::tls::tls::tls_incoming;
+use crate::users::users::UserRegistry;
+use futures_util::{SinkExt, StreamExt};
 use log::{error, info, warn};
 use std::error::Error;
-use std::process::exit;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
-
-const BUFFER_SIZE: usize = 8 * 1024; // 8KB
+use std::net::{IpAddr, SocketAddr};
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use warp::Filter;
 
 pub struct TCP;
 
+/// The port a zync server's TCP listener is on when an address leaves it out.
+pub const DEFAULT_PORT: u16 = 8080;
+
+/// A running server, used to stop it.
+///
+/// Stopping goes through the server's [`Scheduler`]: the listeners close at once, idle connections are dropped and the transfers in flight are given the scheduler's drain timeout to finish. Cloning shares the handle.
+#[derive(Debug, Clone)]
+pub struct ServerHandle {
+    addr: SocketAddr,
+    http: Option<SocketAddr>,
+    scheduler: Scheduler,
+}
+
+impl ServerHandle {
+    /// The address the server is bound to: its TCP listener when it has one, its HTTP listener otherwise.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The address the server's HTTP routes are served on, if it serves them.
+    pub fn http_addr(&self) -> Option<SocketAddr> {
+        self.http
+    }
+
+    pub fn scheduler(&self) -> &Scheduler {
+        &self.scheduler
+    }
+
+    /// Stops accepting connections without waiting for the ones in flight.
+    pub fn shutdown(&self) {
+        self.scheduler.shutdown();
+    }
+
+    /// Stops accepting connections and waits for the transfers in flight to finish.
+    ///
+    /// # Returns
+    /// `true` if every connection closed within the scheduler's drain timeout, `false` if some were still open.
+    pub async fn stop(&self) -> bool {
+        self.scheduler
+            .drain(self.scheduler.config().drain_timeout)
+            .await
+    }
+}
+
 impl TCP {
+    /// Starts the HTTP listener on `addr`, serving the `/upload` and `/download` routes from each paired user's directory in `users`.
+    ///
+    /// With a `tls` acceptor the routes are served over HTTPS instead, each connection completing its TLS handshake before it reaches warp.
+    ///
+    /// Only clients paired through `pairing` may use the routes, and since their session tokens would travel in the clear otherwise, only over HTTPS: without `tls` only the `/pair` routes, open to everyone, are of any use. Connections are admitted through `scheduler`.
+    ///
+    /// # Returns
+    /// A [`ServerHandle`] that stops the server. Once stopped, the listener is closed and idle connections are dropped, while requests in flight run to completion.
     pub fn accept_connection_sync(
         addr: &str,
+        users: UserRegistry,
+        tls: Option<TlsAcceptor>,
+        pairing: Pairing,
+        scheduler: Scheduler,
         global_rt: &tokio::runtime::Runtime,
-    ) -> Result<(), Box<dyn Error>> {
+    ) -> Result<ServerHandle, Box<dyn Error>> {
         let socket_addr: std::net::SocketAddr = addr.parse()?;
+        users.storage().ensure_root()?;
+        let storage = pair::user_storage(pairing.clone(), users.clone(), tls.is_some());
+        let routes = pair::router(pairing, users)
+            .or(put::router(storage.clone()))
+            .or(download::router(storage))
+            .recover(pair::recover);
+        let listener = std::net::TcpListener::bind(socket_addr)?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let handle = ServerHandle {
+            addr,
+            http: Some(addr),
+            scheduler: scheduler.clone(),
+        };
         global_rt.spawn(async move {
-            warp::serve(router()).run(socket_addr).await;
+            let listener = match TcpListener::from_std(listener) {
+                Ok(listener) => listener,
+                Err(e) => {
+                    error!("Failed to start the HTTP listener: {}", e);
+                    return;
+                }
+            };
+            let shutdown = {
+                let scheduler = scheduler.clone();
+                async move { scheduler.closed().await }
+            };
+            match tls {
+                Some(acceptor) => {
+                    let incoming = tls_incoming(listener, acceptor, scheduler);
+                    warp::serve(routes)
+                        .serve_incoming_with_graceful_shutdown(incoming, shutdown)
+                        .await;
+                }
+                None => {
+                    let incoming = scheduled_incoming(listener, scheduler, |stream| async {
+                        Ok::<_, std::io::Error>(stream)
+                    });
+                    warp::serve(routes)
+                        .serve_incoming_with_graceful_shutdown(incoming, shutdown)
+                        .await;
+                }
+            }
+            info!("HTTP server stopped");
         });
-        Ok(())
+        Ok(handle)
+    }
+
+    /// Starts the TCP server on `addr` and, with a `tls` acceptor, the HTTPS listener on `http_addr` alongside it, both serving each paired user's directory in `users` and admitting connections through the same `scheduler`.
+    ///
+    /// Clients pairing over TCP are told which port the HTTP routes ended up on, so `http_addr` may leave the port to the system with port 0. Without `tls` no HTTP listener is started, since session tokens are only accepted over HTTPS, and clients transfer everything over their TCP connection.
+    ///
+    /// # Returns
+    /// A [`ServerHandle`] that stops every listener.
+    ///
+    /// # Errors
+    /// Returns an error if either address cannot be bound.
+    pub fn start_sync(
+        addr: &str,
+        http_addr: &str,
+        users: UserRegistry,
+        tls: Option<TlsAcceptor>,
+        pairing: Pairing,
+        scheduler: Scheduler,
+        global_rt: &tokio::runtime::Runtime,
+    ) -> Result<ServerHandle, Box<dyn Error>> {
+        let socket_addr: SocketAddr = addr.parse()?;
+        users.storage().ensure_root()?;
+        let listener = std::net::TcpListener::bind(socket_addr)?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let http = match &tls {
+            Some(_) => Some(
+                TCP::accept_connection_sync(
+                    http_addr,
+                    users.clone(),
+                    tls.clone(),
+                    pairing.clone(),
+                    scheduler.clone(),
+                    global_rt,
+                )?
+                .addr(),
+            ),
+            None => None,
+        };
+        let endpoint = http.map(|http| HttpEndpoint {
+            port: http.port(),
+            scheme: Scheme::Https,
+        });
+        let handle = ServerHandle {
+            addr,
+            http,
+            scheduler: scheduler.clone(),
+        };
+        global_rt.spawn(async move {
+            match TcpListener::from_std(listener) {
+                Ok(listener) => {
+                    TCP::serve(listener, users, tls, pairing, scheduler, endpoint).await
+                }
+                Err(e) => error!("Failed to start the TCP listener: {}", e),
+            }
+        });
+        Ok(handle)
     }
 
     /// Runs the TCP server, accepting incoming connections and handling each client concurrently.
     ///
-    /// Binds to the specified address, listens for incoming TCP connections, and processes each client using a thread pool and asynchronous tasks. Logs server status and connection errors.
+    /// Binds to the specified address and admits each incoming connection through `scheduler` before handling it in its own task. Connections over the scheduler's limits wait in its queue and are answered with a `Busy` error if no slot frees up in time. Once the scheduler shuts down the server stops accepting connections and waits for in-flight transfers to finish.
     ///
     /// # Parameters
     /// - `addr`: The address to bind the TCP server to (e.g., "127.0.0.1:8080").
+    /// - `users`: The registry of users, each served from their own directory of its storage.
+    /// - `tls`: When set, every connection must complete a TLS handshake with this acceptor before any frame is read.
+    /// - `pairing`: The pairing state clients must pair through before their requests are served.
+    /// - `scheduler`: Bounds how many clients are served at once and signals shutdown.
+    /// - `http`: Where the same server serves its HTTP routes, told to clients as they pair; `None` if it does not.
     ///
     /// # Returns
-    /// `Ok(())` if the server runs without binding errors; otherwise, returns an error if binding fails. The function runs indefinitely unless a critical error occurs during thread pool creation.
-    pub async fn run(addr: &str) -> Result<(), Box<dyn Error>> {
+    /// `Ok(())` once the scheduler has shut down and the in-flight connections have drained; otherwise, returns an error if binding fails.
+    pub async fn run(
+        addr: &str,
+        users: UserRegistry,
+        tls: Option<TlsAcceptor>,
+        pairing: Pairing,
+        scheduler: Scheduler,
+        http: Option<HttpEndpoint>,
+    ) -> Result<(), Box<dyn Error>> {
+        users.storage().ensure_root()?;
         let listener = TcpListener::bind(addr).await?;
-        info!("Server listening on {}", addr);
+        TCP::serve(listener, users, tls, pairing, scheduler, http).await;
+        Ok(())
+    }
 
-        let pool = Threadpool::build(6).unwrap_or_else(|_| {
-            error!("Failed to create thread pool");
-            exit(1);
-        });
+    /// Accepts connections on `listener` until `scheduler` shuts down, as [`TCP::run`] does, then waits for the in-flight transfers to drain.
+    async fn serve(
+        listener: TcpListener,
+        users: UserRegistry,
+        tls: Option<TlsAcceptor>,
+        pairing: Pairing,
+        scheduler: Scheduler,
+        http: Option<HttpEndpoint>,
+    ) {
+        let addr = listener.local_addr().map_or_else(
+            |_| "an unknown address".to_string(),
+            |addr| addr.to_string(),
+        );
+        info!(
+            "Server listening on {}{}",
+            addr,
+            if tls.is_some() { " (TLS)" } else { "" }
+        );
+        let features = hello::features(tls.is_some(), http.is_some());
 
         loop {
-            match listener.accept().await {
-                Ok((stream, _)) => {
-                    pool.execute(|| {
-                        tokio::spawn(async move {
-                            if let Err(e) = TCP::handle_client(stream).await {
-                                error!("Error handling client: {}", e);
-                            }
-                        });
-                    });
+            let (stream, peer) = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        error!("Connection failed: {}", e);
+                        continue;
+                    }
+                },
+                _ = scheduler.closed() => break,
+            };
+            let users = users.clone();
+            let tls = tls.clone();
+            let pairing = pairing.clone();
+            let scheduler = scheduler.clone();
+            let features = features.clone();
+            tokio::spawn(async move {
+                let _permit = match scheduler.admit(peer.ip()).await {
+                    Ok(permit) => permit,
+                    Err(e) => {
+                        warn!("Refused connection from {}: {}", peer, e);
+                        if tls.is_none() {
+                            let refusal = RequestError::new(ErrorCode::Busy, e.to_string());
+                            let _ = send_error(&mut FrameCodec::framed(stream), &refusal).await;
+                        }
+                        return;
+                    }
+                };
+                let result = match tls {
+                    Some(acceptor) => match acceptor.accept(stream).await {
+                        Ok(stream) => {
+                            TCP::handle_client(
+                                stream,
+                                peer.ip(),
+                                users,
+                                pairing,
+                                &scheduler,
+                                http,
+                                features,
+                            )
+                            .await
+                        }
+                        Err(e) => {
+                            warn!("TLS handshake with {} failed: {}", peer, e);
+                            return;
+                        }
+                    },
+                    None => {
+                        TCP::handle_client(
+                            stream,
+                            peer.ip(),
+                            users,
+                            pairing,
+                            &scheduler,
+                            http,
+                            features,
+                        )
+                        .await
+                    }
+                };
+                if let Err(e) = result {
+                    error!("Error handling client: {}", e);
                 }
-                Err(e) => error!("Connection failed: {}", e),
-            }
+            });
+        }
+
+        info!("Server stopped accepting connections, draining in-flight transfers");
+        if !scheduler.drain(scheduler.config().drain_timeout).await {
+            warn!("Gave up waiting for in-flight transfers to finish");
         }
     }
 
     /// Handles a single TCP client connection asynchronously.
     ///
-    /// Reads requests from the client, parses them, and processes supported commands such as listing storage or retrieving files. Logs and handles unknown or unsupported requests. The connection is closed when the client disconnects.
+    /// Decodes length-prefixed frames from the client and dispatches each request frame to its handler, such as listing storage, retrieving, uploading or deleting files. Frames split across reads or coalesced with file data are reassembled by the codec before dispatch. Failed requests are answered with an `ERROR` frame and the connection stays open until the client disconnects.
+    ///
+    /// Once `scheduler` shuts down the connection is closed before its next request, while a request already being handled runs to completion.
+    ///
+    /// A `HELLO` is answered at any point with a `WELCOME` describing the server and its `features`; clients send one first thing.
+    ///
+    /// Until the client has completed a `PAIR`/`CONFIRM` handshake every other request is answered with an `Unauthorized` error. After it, requests operate on the storage directory of the user the client paired as, for as long as its session is neither expired nor revoked.
     ///
     /// # Parameters
-    /// - `stream`: The TCP stream representing the client connection.
+    /// - `stream`: The client connection, either a plain TCP stream or a TLS stream wrapping one.
+    /// - `peer`: The client's address, which its pairing attempts are counted against.
+    /// - `users`: The registry the client's user is looked up or registered in.
+    /// - `pairing`: The pairing state the client pairs through.
+    /// - `scheduler`: The scheduler the connection was admitted through.
+    /// - `http`: Where the server's HTTP routes are, told to the client as it pairs.
+    /// - `features`: What the server supports, told to the client in its `WELCOME`.
     ///
     /// # Returns
     /// `Ok(())` if the client was handled successfully, or an error if an I/O or protocol error occurs.
-    async fn handle_client(mut stream: TcpStream) -> Result<(), Box<dyn Error>> {
-        let mut buffer = vec![0u8; BUFFER_SIZE];
+    async fn handle_client<T: Transport + 'static>(
+        stream: T,
+        peer: IpAddr,
+        users: UserRegistry,
+        pairing: Pairing,
+        scheduler: &Scheduler,
+        http: Option<HttpEndpoint>,
+        features: Vec<Feature>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut stream = FrameCodec::framed(stream);
+        let mut session: Option<(PairedClient, Storage)> = None;
         loop {
-            let n = stream.read(&mut buffer).await?;
-            if n == 0 {
-                warn!("Connection closed by client");
+            let frame = tokio::select! {
+                frame = stream.next() => frame,
+                _ = scheduler.closed() => {
+                    info!("Closing connection, the server is shutting down");
+                    return Ok(());
+                }
+            };
+            let Some(frame) = frame else {
                 break;
-            }
-            let request = String::from_utf8_lossy(&buffer[..n])
-                .trim_matches(char::from(0))
-                .trim()
-                .to_string();
-            info!("Received request: {}", request);
+            };
+            let frame = frame?;
+            info!("Received request: {}", frame.opcode);
 
-            match AllowedRequest::from_str_slice(&request) {
-                Some(AllowedRequest::List) => {
-                    list::list_storage(&mut stream).await?;
+            let ended =
+                session.take_if(|(client, _)| pairing.authorize(Some(&client.token)).is_err());
+            if let Some((client, _)) = ended {
+                info!("Session of {} expired or was revoked", client.name);
+            }
+            let user_storage = session.as_ref().map(|(_, storage)| storage);
+            let result = match (AllowedRequest::from_opcode(frame.opcode), user_storage) {
+                (Some(AllowedRequest::Hello), storage) => {
+                    hello::hello(&mut stream, &frame, storage, &features).await
+                }
+                (Some(AllowedRequest::Pair), _) => {
+                    pair::pair(&mut stream, &frame, &pairing, http, peer).await
                 }
-                Some(AllowedRequest::Delete) => {
-                    info!("Processing DELETE request");
+                (Some(AllowedRequest::Confirm), _) => {
+                    pair::confirm(&mut stream, &frame, &pairing, &users)
+                        .await
+                        .map(|paired| session = Some(paired))
                 }
-                Some(AllowedRequest::Get) => {
-                    get_file(&mut stream, &mut buffer).await?;
+                (Some(_), None) => Err(not_paired().into()),
+                (Some(AllowedRequest::List), Some(storage)) => {
+                    list::list_storage(&mut stream, &frame, storage).await
+                }
+                (Some(AllowedRequest::Delete), Some(storage)) => {
+                    delete_file(&mut stream, &frame, storage).await
+                }
+                (Some(AllowedRequest::Get), Some(storage)) => {
+                    get_file(&mut stream, &frame, storage).await
+                }
+                (Some(AllowedRequest::Put), Some(storage)) => {
+                    upload_file(&mut stream, &frame, storage).await
+                }
+                (Some(AllowedRequest::Manifest), Some(storage)) => {
+                    receive_manifest(&mut stream, &frame, storage).await
+                }
+                (None, _) if frame.opcode == Opcode::Error => {
+                    // A cancellation or interruption that crossed the end of its transfer still gets acknowledged.
+                    let interrupted = frame
+                        .parse::<ErrorReply>()
+                        .is_ok_and(|reply| reply.code == ErrorCode::Interrupted);
+                    if interrupted {
+                        Err(RequestError::interrupted().into())
+                    } else {
+                        Err(RequestError::cancelled().into())
+                    }
+                }
+                (None, _) => {
+                    warn!("Unknown request: {}", frame.opcode);
+                    Err(RequestError::new(
+                        ErrorCode::BadRequest,
+                        format!("Unexpected {} frame", frame.opcode),
+                    )
+                    .into())
+                }
+            };
+
+            let failure = result.err().map(|e| RequestError::from_boxed(e.as_ref()));
+            if let Some(failure) = failure {
+                if matches!(failure.code, ErrorCode::Cancelled | ErrorCode::Interrupted) {
+                    info!("{} request ended by the client: {}", frame.opcode, failure);
+                } else {
+                    error!("{} request failed: {}", frame.opcode, failure);
                 }
-                None => warn!("Unknown request: {request}"),
-                _ => {}
+                send_error(&mut stream, &failure).await?;
             }
 
             stream.flush().await?;
         }
+        warn!("Connection closed by client");
         Ok(())
     }
 }
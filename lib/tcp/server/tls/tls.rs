@@ -0,0 +1,131 @@
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use rustls::ServerConfig;
+use rustls::crypto::CryptoProvider;
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use sha2::{Digest as _, Sha256};
+use tokio::net::{TcpListener, TcpStream};
+pub use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::scheduler::scheduler::{Scheduled, Scheduler, scheduled_incoming};
+
+const CERT_FILE: &str = "cert.pem";
+const KEY_FILE: &str = "key.pem";
+
+/// The self-signed certificate and private key a server presents in TLS mode.
+#[derive(Debug)]
+pub struct ServerIdentity {
+    pub cert: CertificateDer<'static>,
+    pub key: PrivateKeyDer<'static>,
+}
+
+/// Directory the server's certificate and key live in: `<config dir>/zync/tls`.
+pub fn default_tls_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("zync")
+        .join("tls")
+}
+
+/// The crypto provider every TLS config in zync is built with.
+pub fn crypto_provider() -> Arc<CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+/// Returns the SHA-256 fingerprint of a DER-encoded certificate as lowercase hex.
+///
+/// This is what clients pin on first use and what a server operator compares out of band.
+pub fn fingerprint(cert: &[u8]) -> String {
+    Sha256::digest(cert)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+impl ServerIdentity {
+    /// Loads the certificate and key from `dir`, generating and saving a new self-signed pair the first time.
+    ///
+    /// The certificate names `localhost` and `127.0.0.1`; clients on other machines authenticate it by fingerprint rather than by name.
+    ///
+    /// # Errors
+    /// Returns an error if the files cannot be read or written, or hold no usable certificate or key.
+    pub fn load_or_create(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let cert_path = dir.join(CERT_FILE);
+        let key_path = dir.join(KEY_FILE);
+        if !cert_path.exists() || !key_path.exists() {
+            Self::generate(&cert_path, &key_path)?;
+        }
+
+        Ok(Self {
+            cert: CertificateDer::from_pem_file(&cert_path)?,
+            key: PrivateKeyDer::from_pem_file(&key_path)?,
+        })
+    }
+
+    fn generate(cert_path: &Path, key_path: &Path) -> Result<(), Box<dyn Error>> {
+        let names = vec!["localhost".to_string(), "127.0.0.1".to_string()];
+        let certified = rcgen::generate_simple_self_signed(names)?;
+
+        if let Some(parent) = cert_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(cert_path, certified.cert.pem())?;
+        write_private(key_path, certified.key_pair.serialize_pem().as_bytes())?;
+        log::info!("Generated a self-signed TLS certificate in {:?}", cert_path);
+        Ok(())
+    }
+
+    pub fn fingerprint(&self) -> String {
+        fingerprint(self.cert.as_ref())
+    }
+
+    /// Builds an acceptor that wraps incoming connections in TLS with this identity.
+    ///
+    /// # Errors
+    /// Returns an error if the key does not match the certificate or uses an unsupported algorithm.
+    pub fn acceptor(&self) -> Result<TlsAcceptor, Box<dyn Error>> {
+        let config = ServerConfig::builder_with_provider(crypto_provider())
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(vec![self.cert.clone()], self.key.clone_key())?;
+        Ok(TlsAcceptor::from(Arc::new(config)))
+    }
+}
+
+/// Writes `contents` to `path`, readable and writable by its owner only where the platform allows.
+#[cfg(unix)]
+pub fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(contents)
+}
+
+/// Writes `contents` to `path`, readable and writable by its owner only where the platform allows.
+#[cfg(not(unix))]
+pub fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    fs::write(path, contents)
+}
+
+/// Accepts connections from `listener`, admits each through `scheduler` and yields it once its TLS handshake has completed.
+///
+/// Handshakes run in their own tasks, so a slow or failing client does not hold up the others; failed handshakes are logged and dropped.
+pub fn tls_incoming(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    scheduler: Scheduler,
+) -> ReceiverStream<std::io::Result<Scheduled<TlsStream<TcpStream>>>> {
+    scheduled_incoming(listener, scheduler, move |stream| acceptor.accept(stream))
+}
@@ -1 +1,293 @@
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::error::RequestError;
+use crate::protocol::messages::ErrorCode;
+use crate::storage::index::METADATA_DIR;
+use crate::storage::storage::Storage;
+
+const REGISTRY_FILE: &str = "users.json";
+
+/// Longest user name the server accepts.
+const MAX_NAME_LEN: usize = 64;
+
+/// A user known to the server, identified by the name its client pairs with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct User {
+    pub name: String,
+    /// The credential of the user secret the user's client proved when it first paired; only a client proving the same secret may pair as this user. Removing it lets the next client pairing with the name claim it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub credential: Option<String>,
+    /// How many bytes the user's files may take up in total; `None` means no quota.
+    #[serde(default)]
+    pub quota: Option<u64>,
+    /// When the user first paired, in seconds since the Unix epoch.
+    pub registered_at: u64,
+}
+
+/// The users a server has seen, each with a storage directory of its own.
+///
+/// Users are registered the first time they pair and persisted to `.zync/users.json` under the storage root, where an operator can edit their quotas. A user name is bound to the secret of the client that registered it, so knowing the pairing code is not enough to pair as someone else. Each user's files live in `<root>/<name>`, so a user only ever sees and touches their own files. Cloning shares the registry.
+#[derive(Debug, Clone)]
+pub struct UserRegistry {
+    storage: Storage,
+    default_quota: Option<u64>,
+    users: Arc<Mutex<Vec<User>>>,
+}
+
+impl UserRegistry {
+    /// Loads the registry kept under `storage`'s root, starting an empty one if there is none yet.
+    ///
+    /// # Parameters
+    /// - `storage`: The storage the user directories are created in; its upload limit applies to every user.
+    /// - `default_quota`: The quota given to users registered from now on.
+    ///
+    /// # Errors
+    /// Returns an error if the storage root cannot be created or the registry file exists but cannot be read or parsed.
+    pub fn open(storage: Storage, default_quota: Option<u64>) -> io::Result<Self> {
+        storage.ensure_root()?;
+        let path = registry_path(&storage);
+        let users = match std::fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        Ok(Self {
+            storage,
+            default_quota,
+            users: Arc::new(Mutex::new(users)),
+        })
+    }
+
+    /// The storage the user directories live in.
+    pub fn storage(&self) -> &Storage {
+        &self.storage
+    }
+
+    pub fn users(&self) -> Vec<User> {
+        self.users.lock().unwrap().clone()
+    }
+
+    /// Returns the user called `name` for a client that proved the user secret behind `credential`, registering and persisting them with the default quota and bound to `credential` if they are new.
+    ///
+    /// A user registered before names were bound, or whose credential the operator removed, is bound to `credential` now.
+    ///
+    /// # Errors
+    /// Returns `BadRequest` if the name cannot be used as a directory name, `Unauthorized` if the name is bound to another client's secret, or an error if the registry cannot be saved.
+    pub fn register(&self, name: &str, credential: &str) -> Result<User, RequestError> {
+        check_name(name)?;
+        let mut users = self.users.lock().unwrap();
+        if let Some(user) = users.iter_mut().find(|user| user.name == name) {
+            match &user.credential {
+                Some(bound) if bound == credential => return Ok(user.clone()),
+                Some(_) => {
+                    log::warn!("Refused a client pairing as {} with another secret", name);
+                    return Err(RequestError::new(
+                        ErrorCode::Unauthorized,
+                        format!("The user name {name} belongs to another client"),
+                    ));
+                }
+                None => {
+                    user.credential = Some(credential.to_string());
+                    let user = user.clone();
+                    self.save(&users).map_err(|e| RequestError::from(&e))?;
+                    log::info!("Bound user {} to the secret of its client", name);
+                    return Ok(user);
+                }
+            }
+        }
+
+        let user = User {
+            name: name.to_string(),
+            credential: Some(credential.to_string()),
+            quota: self.default_quota,
+            registered_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or(0),
+        };
+        users.push(user.clone());
+        self.save(&users).map_err(|e| RequestError::from(&e))?;
+        log::info!("Registered user {}", name);
+        Ok(user)
+    }
+
+    /// Returns the storage of the user called `name`, checking `credential` and registering them first like [`UserRegistry::register`].
+    ///
+    /// # Errors
+    /// Returns every error [`UserRegistry::register`] can return, or an error if the user's directory cannot be created.
+    pub fn storage_for(&self, name: &str, credential: &str) -> Result<Storage, RequestError> {
+        let user = self.register(name, credential)?;
+        let storage = Storage::new(self.storage.root().join(&user.name))
+            .with_max_upload_size(self.storage.max_upload_size())
+            .with_quota(user.quota)
+            .with_owner(&user.name);
+        storage.ensure_root().map_err(|e| RequestError::from(&e))?;
+        Ok(storage)
+    }
+
+    fn save(&self, users: &[User]) -> io::Result<()> {
+        let path = registry_path(&self.storage);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let contents = serde_json::to_string_pretty(users)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        std::fs::write(path, contents)
+    }
+}
+
+/// Where the registry is kept: in the server's metadata directory, which never clashes with a user directory since user names may not start with a dot.
+fn registry_path(storage: &Storage) -> PathBuf {
+    storage.root().join(METADATA_DIR).join(REGISTRY_FILE)
+}
+
+/// Rejects names that could not be used as a single, visible directory name.
+fn check_name(name: &str) -> Result<(), RequestError> {
+    let invalid = |reason: &str| {
+        Err(RequestError::new(
+            ErrorCode::BadRequest,
+            format!("Invalid user name {name:?}: {reason}"),
+        ))
+    };
+    if name.trim().is_empty() {
+        return invalid("it is empty");
+    }
+    if name.len() > MAX_NAME_LEN {
+        return invalid("it is too long");
+    }
+    if name.starts_with('.') {
+        return invalid("it starts with a dot");
+    }
+    if name
+        .chars()
+        .any(|c| c == '/' || c == '\\' || c.is_control())
+    {
+        return invalid("it contains a path separator or control character");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry(default_quota: Option<u64>) -> (tempfile::TempDir, UserRegistry) {
+        let dir = tempfile::tempdir().unwrap();
+        let users =
+            UserRegistry::open(Storage::new(dir.path().join("root")), default_quota).unwrap();
+        (dir, users)
+    }
+
+    #[test]
+    fn accepts_ordinary_names() {
+        for name in [
+            "alice",
+            "Bob Smith",
+            "élodie",
+            "a.b",
+            &"x".repeat(MAX_NAME_LEN),
+        ] {
+            assert!(check_name(name).is_ok(), "{name:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_names_that_are_not_a_single_visible_directory() {
+        let too_long = "x".repeat(MAX_NAME_LEN + 1);
+        for name in [
+            "",
+            "  ",
+            ".zync",
+            "..",
+            "a/b",
+            "a\\b",
+            "tab\there",
+            &too_long,
+        ] {
+            let error = check_name(name).unwrap_err();
+            assert_eq!(error.code, ErrorCode::BadRequest, "{name:?}");
+        }
+    }
+
+    #[test]
+    fn binds_a_new_user_to_its_credential() {
+        let (_dir, users) = registry(None);
+        assert_eq!(
+            users
+                .register("alice", "one")
+                .unwrap()
+                .credential
+                .as_deref(),
+            Some("one")
+        );
+        assert!(users.register("alice", "one").is_ok());
+
+        let error = users.register("alice", "two").unwrap_err();
+        assert_eq!(error.code, ErrorCode::Unauthorized);
+        assert!(users.storage_for("alice", "two").is_err());
+    }
+
+    #[test]
+    fn binds_unbound_users_on_their_next_pairing() {
+        let (_dir, users) = registry(None);
+        users.register("alice", "one").unwrap();
+        users.users.lock().unwrap()[0].credential = None;
+
+        assert!(users.register("alice", "two").is_ok());
+        assert!(users.register("alice", "one").is_err());
+    }
+
+    #[test]
+    fn persists_users_with_their_credentials() {
+        let (dir, users) = registry(Some(10));
+        users.register("alice", "one").unwrap();
+        let reopened = UserRegistry::open(Storage::new(dir.path().join("root")), None).unwrap();
+        assert_eq!(reopened.users(), users.users());
+        assert!(reopened.register("alice", "two").is_err());
+    }
+
+    #[test]
+    fn gives_new_users_the_default_quota() {
+        let (_dir, users) = registry(Some(1000));
+        let storage = users.storage_for("alice", "one").unwrap();
+        assert_eq!(storage.quota(), Some(1000));
+        assert_eq!(storage.owner(), Some("alice"));
+        assert!(storage.root().ends_with("root/alice"));
+    }
+
+    #[test]
+    fn enforces_each_users_quota_on_their_own_files() {
+        let (_dir, users) = registry(Some(1000));
+        let alice = users.storage_for("alice", "one").unwrap();
+        std::fs::write(alice.root().join("a.bin"), vec![0; 800]).unwrap();
+
+        let error = alice.check_quota("b.bin", 300).unwrap_err();
+        assert_eq!(error.code, ErrorCode::QuotaExceeded);
+        assert!(alice.check_quota("a.bin", 900).is_ok());
+
+        let bob = users.storage_for("bob", "two").unwrap();
+        assert!(bob.check_quota("b.bin", 900).is_ok());
+    }
+
+    #[test]
+    fn keeps_quotas_an_operator_edited() {
+        let (dir, users) = registry(Some(1000));
+        users.register("alice", "one").unwrap();
+        let path = registry_path(users.storage());
+        let edited = std::fs::read_to_string(&path).unwrap().replace("1000", "5");
+        std::fs::write(&path, edited).unwrap();
+
+        let reopened = UserRegistry::open(Storage::new(dir.path().join("root")), None).unwrap();
+        assert_eq!(
+            reopened.storage_for("alice", "one").unwrap().quota(),
+            Some(5)
+        );
+        assert_eq!(reopened.storage_for("bob", "two").unwrap().quota(), None);
+    }
+}
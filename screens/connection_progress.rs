@@ -9,10 +9,18 @@ use ratatui::{
     style::{Color, Style},
     widgets::{Block, Borders, Clear, Paragraph},
 };
+use tcp_server::pairing::pairing::Pairing;
+use tcp_server::tcp::tcp::ServerHandle;
 
 #[derive(Debug)]
 pub struct ConnectionProgress {
     pub state: ConnectionState,
+    /// Pairing state of the server started from this screen, whose current code is displayed.
+    pub pairing: Option<Pairing>,
+    /// The server started from this screen, whose connection counts are displayed.
+    pub server: Option<ServerHandle>,
+    /// Ports the server's UPnP mappings were created for, removed again when the server stops.
+    pub forwarded_ports: Vec<u16>,
     event: SyncEvent<ConnectionState>,
 }
 
@@ -26,6 +34,9 @@ impl ConnectionProgress {
     pub fn new() -> Self {
         Self {
             state: ConnectionState::NoConnection,
+            pairing: None,
+            server: None,
+            forwarded_ports: Vec::new(),
             event: SyncEvent::new(),
         }
     }
@@ -62,7 +73,7 @@ impl ConnectionProgress {
 
         let (message, style): (&str, Style) = match &self.state {
             ConnectionState::Connecting => (
-                "Connecting to server...",
+                "Connecting to server... (Esc to cancel)",
                 Style::default().fg(Color::Yellow),
             ),
             ConnectionState::Connected => {
@@ -74,6 +85,22 @@ impl ConnectionProgress {
 
         let paragraph = Paragraph::new(message).style(style);
         f.render_widget(paragraph, text_area[0]);
+
+        if let (ConnectionState::Connected, Some(pairing)) = (&self.state, &self.pairing) {
+            let code = Paragraph::new(format!("Pairing code: {}", pairing.code()))
+                .style(Style::default().fg(Color::Cyan));
+            f.render_widget(code, text_area[2]);
+        }
+
+        if let (ConnectionState::Connected, Some(server)) = (&self.state, &self.server) {
+            let metrics = server.scheduler().metrics();
+            let clients = Paragraph::new(format!(
+                "Clients: {}/{} active, {} queued",
+                metrics.active, metrics.max_clients, metrics.queued
+            ))
+            .style(Style::default().fg(Color::Cyan));
+            f.render_widget(clients, text_area[3]);
+        }
     }
 
     pub fn get_event_sender(&self) -> &SyncEvent<ConnectionState> {
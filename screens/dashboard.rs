@@ -3,18 +3,49 @@ use ratatui::{
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style, Stylize},
     text::{Line, Span, Text},
-    widgets::{Block, Borders, Cell, Clear, Paragraph, Row, Table, Wrap},
+    widgets::{Block, Borders, Cell, Clear, LineGauge, Paragraph, Row, Table, Wrap},
 };
 use std::sync::{Arc, Mutex};
 
 use crate::core_mod::widgets::{Item, TableWidget};
+use crate::internal::session_store::TransferRecord;
+use crate::utils::format::{format_eta, format_rate};
+use tcp_server::protocol::progress::Progress;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Debug, Clone)]
 pub struct Data {
+    /// Identifies a transfer queued in this run, which its progress and outcome updates refer to; `None` for past transfers.
+    pub id: Option<u64>,
     pub name: String,
     pub status: Line<'static>,
     pub destination: String,
     pub time: String,
+    /// Verified content digest as `<algorithm>=<hex>`.
+    pub digest: Option<String>,
+    /// Progress of the transfer while it runs; `None` once it is done or for past transfers.
+    pub progress: Option<Progress>,
+    /// Cancels the transfer while it runs; `None` once it is done or for past transfers.
+    pub cancel: Option<CancellationToken>,
+}
+
+impl From<&TransferRecord> for Data {
+    fn from(record: &TransferRecord) -> Self {
+        let status = match record.digest {
+            Some(_) => Line::from(record.status.clone()).green(),
+            None => Line::from(record.status.clone()),
+        };
+        Self {
+            id: None,
+            name: record.name.clone(),
+            status,
+            destination: record.destination.clone(),
+            time: record.time.clone(),
+            digest: record.digest.clone(),
+            progress: None,
+            cancel: None,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -88,6 +119,7 @@ pub fn table_ui(
     let stateful_table = draw_table(table);
     f.render_stateful_widget(stateful_table, main_chunks[0], &mut table_state);
     table.state = table_state;
+    draw_gauges(f, table, main_chunks[1]);
 
     let connect_state = {
         let lock = progress.lock().unwrap();
@@ -127,7 +159,7 @@ fn draw_table(table: &TableWidget) -> Table<'_> {
     let selected_style = Style::default()
         .add_modifier(Modifier::REVERSED)
         .fg(table.colors.selected_style_fg);
-    let header = ["Name", "Status", "Destination", "Time"]
+    let header = ["Name", "Status", "Destination", "Time", "Hash"]
         .iter()
         .map(|&s| Cell::from(s))
         .collect::<Row>()
@@ -141,11 +173,21 @@ fn draw_table(table: &TableWidget) -> Table<'_> {
                 _ => table.colors.alt_row_color,
             };
 
+            let status = match &data.progress {
+                Some(progress) => Line::from(format!(
+                    "{:.0}% · {}",
+                    progress.ratio() * 100.0,
+                    format_rate(progress.rate)
+                ))
+                .yellow(),
+                None => data.status.clone(),
+            };
             let cells = vec![
                 Cell::from(data.name.clone()),
-                Cell::from(data.status.clone()),
+                Cell::from(status),
                 Cell::from(data.destination.clone()),
                 Cell::from(data.time.clone()),
+                Cell::from(short_digest(data.digest.as_deref())),
             ];
 
             Some(
@@ -166,6 +208,7 @@ fn draw_table(table: &TableWidget) -> Table<'_> {
             Constraint::Min(table.longest_item_lens[1]),
             Constraint::Min(table.longest_item_lens[2]),
             Constraint::Min(table.longest_item_lens[3]),
+            Constraint::Min(table.longest_item_lens[4]),
         ],
     )
     .header(header)
@@ -179,3 +222,49 @@ fn draw_table(table: &TableWidget) -> Table<'_> {
     .bg(table.colors.buffer_bg)
     .highlight_spacing(ratatui::widgets::HighlightSpacing::Always)
 }
+
+/// Draws a gauge with rate and ETA for every running transfer into the Activity Logs panel, one per line.
+fn draw_gauges(f: &mut Frame, table: &TableWidget, area: Rect) {
+    let area = Rect {
+        x: area.x + 2,
+        y: area.y,
+        width: area.width.saturating_sub(3),
+        height: area.height.saturating_sub(1),
+    };
+    let running = table.items.iter().filter_map(|item| match item {
+        Item::Data(data) => data.progress.map(|progress| (data, progress)),
+        _ => None,
+    });
+    for (line, (data, progress)) in running.enumerate().take(area.height as usize) {
+        let eta = progress
+            .eta
+            .map(format_eta)
+            .unwrap_or_else(|| "--:--".to_string());
+        let label = format!(
+            "{} {:.0}% {} ETA {}",
+            data.name,
+            progress.ratio() * 100.0,
+            format_rate(progress.rate),
+            eta
+        );
+        let gauge = LineGauge::default()
+            .filled_style(Style::default().fg(table.colors.selected_style_fg))
+            .unfilled_style(Style::default().fg(Color::DarkGray))
+            .label(label)
+            .ratio(progress.ratio());
+        let row = Rect {
+            y: area.y + line as u16,
+            height: 1,
+            ..area
+        };
+        f.render_widget(gauge, row);
+    }
+}
+
+/// Shortens `algorithm=hex` to the algorithm and the first 12 hex characters.
+fn short_digest(digest: Option<&str>) -> String {
+    match digest.and_then(|digest| digest.split_once('=')) {
+        Some((algorithm, value)) => format!("{}:{}", algorithm, &value[..value.len().min(12)]),
+        None => "-".to_string(),
+    }
+}
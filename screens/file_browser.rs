@@ -0,0 +1,585 @@
+use crate::core_mod::widgets::TableColors;
+use crate::utils::format::format_size;
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Layout},
+    prelude::Stylize,
+    style::{Modifier, Style, palette::tailwind},
+    text::{Line, Text},
+    widgets::{
+        Block, BorderType, Borders, Cell, HighlightSpacing, Paragraph, Row, Table, TableState,
+    },
+};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+use tcp_server::protocol::messages::RemoteEntry;
+
+/// Column the local listing is sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortKey {
+    #[default]
+    Name,
+    Size,
+    Modified,
+}
+
+impl SortKey {
+    fn next(self) -> Self {
+        match self {
+            SortKey::Name => SortKey::Size,
+            SortKey::Size => SortKey::Modified,
+            SortKey::Modified => SortKey::Name,
+        }
+    }
+}
+
+/// A file or directory listed by the file browser.
+#[derive(Debug, Clone)]
+pub struct LocalEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub is_dir: bool,
+    pub size: u64,
+    /// Modification time in seconds since the Unix epoch, if the file system reports one.
+    pub mtime: Option<u64>,
+}
+
+/// The Files screen: one local directory listed in a sortable table, filtered as the user types, whose entries can be selected for upload. Selections are kept while moving between directories.
+///
+/// Without a desktop for the folder dialog, the same screen picks the folder remote entries are downloaded into.
+#[derive(Debug)]
+pub struct FileBrowser {
+    pub cwd: PathBuf,
+    /// Every entry of the listed directory, hidden ones included.
+    entries: Vec<LocalEntry>,
+    /// Indices into `entries` of the rows shown, in display order.
+    view: Vec<usize>,
+    /// Paths of the entries marked for the next upload.
+    pub selected: BTreeSet<PathBuf>,
+    pub show_hidden: bool,
+    /// The fuzzy filter the shown rows have to match; empty to show all.
+    pub filter: String,
+    /// Whether typed characters go into the filter.
+    pub filtering: bool,
+    pub state: TableState,
+    pub sort: SortKey,
+    pub descending: bool,
+    /// Outcome of the last action, shown under the table.
+    pub status: Option<String>,
+    /// Remote entries waiting for the folder to download them into; while set, the listed directory is picked as that folder instead of files being selected for upload.
+    pub download: Option<Vec<RemoteEntry>>,
+    pub colors: TableColors,
+}
+
+impl FileBrowser {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        let cwd = std::env::current_dir()
+            .ok()
+            .or_else(dirs::home_dir)
+            .unwrap_or_else(|| PathBuf::from("/"));
+        Self {
+            cwd,
+            entries: Vec::new(),
+            view: Vec::new(),
+            selected: BTreeSet::new(),
+            show_hidden: false,
+            filter: String::new(),
+            filtering: false,
+            state: TableState::default(),
+            sort: SortKey::default(),
+            descending: false,
+            status: None,
+            download: None,
+            colors: TableColors::new(&tailwind::EMERALD),
+        }
+    }
+
+    /// Lists the current directory again, keeping the highlighted entry where it is still listed.
+    pub fn refresh(&mut self) {
+        let highlighted = self.highlighted().map(|entry| entry.path.clone());
+        match read_entries(&self.cwd) {
+            Ok(entries) => {
+                self.entries = entries;
+                self.status = None;
+            }
+            Err(e) => {
+                self.entries.clear();
+                self.status = Some(format!("Could not list {}: {}", self.cwd.display(), e));
+            }
+        }
+        self.update_view(highlighted);
+    }
+
+    pub fn highlighted(&self) -> Option<&LocalEntry> {
+        self.state
+            .selected()
+            .and_then(|i| self.view.get(i))
+            .map(|&index| &self.entries[index])
+    }
+
+    pub fn next(&mut self) {
+        if self.view.is_empty() {
+            return;
+        }
+        let i = match self.state.selected() {
+            Some(i) if i + 1 < self.view.len() => i + 1,
+            _ => 0,
+        };
+        self.state.select(Some(i));
+    }
+
+    pub fn previous(&mut self) {
+        if self.view.is_empty() {
+            return;
+        }
+        let i = match self.state.selected() {
+            Some(0) | None => self.view.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.state.select(Some(i));
+    }
+
+    /// Marks the highlighted entry, or unmarks it if it already is.
+    pub fn toggle_selected(&mut self) {
+        let Some(path) = self.highlighted().map(|entry| entry.path.clone()) else {
+            return;
+        };
+        if !self.selected.remove(&path) {
+            self.selected.insert(path);
+        }
+    }
+
+    /// The paths an upload applies to: the marked ones, or the highlighted one if none are marked.
+    pub fn targets(&self) -> Vec<PathBuf> {
+        if self.selected.is_empty() {
+            return self
+                .highlighted()
+                .map(|entry| entry.path.clone())
+                .into_iter()
+                .collect();
+        }
+        self.selected.iter().cloned().collect()
+    }
+
+    /// Moves into the directory at `path`, clearing the filter.
+    pub fn enter(&mut self, path: PathBuf) {
+        self.cwd = path;
+        self.filter.clear();
+        self.filtering = false;
+        self.state.select(None);
+        self.refresh();
+    }
+
+    /// Moves to the parent of the listed directory, highlighting the directory it came from.
+    ///
+    /// # Returns
+    /// `false` if the file system root is already listed.
+    pub fn leave(&mut self) -> bool {
+        let Some(parent) = self.cwd.parent().map(PathBuf::from) else {
+            return false;
+        };
+        let child = std::mem::replace(&mut self.cwd, parent);
+        self.filter.clear();
+        self.filtering = false;
+        self.refresh();
+        self.update_view(Some(child));
+        true
+    }
+
+    pub fn toggle_hidden(&mut self) {
+        self.show_hidden = !self.show_hidden;
+        self.update_view(None);
+    }
+
+    pub fn push_filter(&mut self, c: char) {
+        self.filter.push(c);
+        self.update_view(None);
+    }
+
+    pub fn pop_filter(&mut self) {
+        self.filter.pop();
+        self.update_view(None);
+    }
+
+    pub fn clear_filter(&mut self) {
+        self.filter.clear();
+        self.filtering = false;
+        self.update_view(None);
+    }
+
+    /// Sorts by the next column, in ascending order.
+    pub fn cycle_sort(&mut self) {
+        self.sort = self.sort.next();
+        self.descending = false;
+        let highlighted = self.highlighted().map(|entry| entry.path.clone());
+        self.update_view(highlighted);
+    }
+
+    pub fn reverse_sort(&mut self) {
+        self.descending = !self.descending;
+        let highlighted = self.highlighted().map(|entry| entry.path.clone());
+        self.update_view(highlighted);
+    }
+
+    /// Recomputes the shown rows and highlights `highlight` if it is among them, the first row otherwise.
+    ///
+    /// Without a filter, directories come before files, each sorted by the current column; with one, the best matches come first.
+    fn update_view(&mut self, highlight: Option<PathBuf>) {
+        let mut scored: Vec<(i64, usize)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| self.show_hidden || !entry.name.starts_with('.'))
+            .filter_map(|(index, entry)| {
+                fuzzy_score(&self.filter, &entry.name).map(|score| (score, index))
+            })
+            .collect();
+        let entries = &self.entries;
+        let (sort, descending) = (self.sort, self.descending);
+        scored.sort_by(|(score_a, a), (score_b, b)| {
+            let (a, b) = (&entries[*a], &entries[*b]);
+            let by_column = match sort {
+                SortKey::Name => a.name.cmp(&b.name),
+                SortKey::Size => a.size.cmp(&b.size),
+                SortKey::Modified => a.mtime.cmp(&b.mtime),
+            };
+            let by_column = if descending {
+                by_column.reverse()
+            } else {
+                by_column
+            };
+            score_b
+                .cmp(score_a)
+                .then(b.is_dir.cmp(&a.is_dir))
+                .then(by_column)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        self.view = scored.into_iter().map(|(_, index)| index).collect();
+
+        let index = highlight
+            .and_then(|path| {
+                self.view
+                    .iter()
+                    .position(|&index| self.entries[index].path == path)
+            })
+            .unwrap_or(0);
+        self.state
+            .select((!self.view.is_empty()).then(|| index.min(self.view.len() - 1)));
+    }
+
+    fn header_label(&self, key: SortKey, label: &str) -> String {
+        if self.sort != key || !self.filter.is_empty() {
+            return label.to_string();
+        }
+        let arrow = if self.descending { "▼" } else { "▲" };
+        format!("{label} {arrow}")
+    }
+}
+
+/// Reads the entries of `dir`, following symlinks for their kind, size and modification time.
+fn read_entries(dir: &PathBuf) -> std::io::Result<Vec<LocalEntry>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = fs::metadata(&path).or_else(|_| entry.metadata())?;
+        entries.push(LocalEntry {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            is_dir: metadata.is_dir(),
+            size: metadata.len(),
+            mtime: metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|since| since.as_secs()),
+            path,
+        });
+    }
+    Ok(entries)
+}
+
+/// Scores how well `name` matches the fuzzy `pattern`, whose characters have to appear in `name` in order, ignoring case. Consecutive characters and characters at the start of a word score higher; every name matches an empty pattern with a score of 0.
+///
+/// # Returns
+/// `None` if `name` does not match.
+fn fuzzy_score(pattern: &str, name: &str) -> Option<i64> {
+    let mut score = 0;
+    let mut previous: Option<usize> = None;
+    let name: Vec<char> = name.chars().flat_map(char::to_lowercase).collect();
+    let mut from = 0;
+    for p in pattern.chars().flat_map(char::to_lowercase) {
+        let offset = name[from..].iter().position(|&c| c == p)?;
+        let index = from + offset;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == index) {
+            score += 5;
+        }
+        if index == 0 || matches!(name[index - 1], ' ' | '_' | '-' | '.') {
+            score += 3;
+        }
+        score -= offset as i64;
+        previous = Some(index);
+        from = index + 1;
+    }
+    Some(score)
+}
+
+fn format_mtime(mtime: Option<u64>) -> String {
+    mtime
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs as i64, 0))
+        .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+pub fn draw_file_browser(f: &mut Frame, browser: &mut FileBrowser) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(3),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .split(f.area());
+
+    let header_style = Style::default()
+        .fg(browser.colors.header_fg)
+        .bg(browser.colors.header_bg);
+    let selected_style = Style::default()
+        .add_modifier(Modifier::REVERSED)
+        .fg(browser.colors.selected_style_fg);
+    let header = [
+        String::new(),
+        browser.header_label(SortKey::Name, "Name"),
+        browser.header_label(SortKey::Size, "Size"),
+        browser.header_label(SortKey::Modified, "Modified"),
+    ]
+    .into_iter()
+    .map(Cell::from)
+    .collect::<Row>()
+    .style(header_style)
+    .height(1);
+
+    let rows = browser.view.iter().enumerate().map(|(i, &index)| {
+        let entry = &browser.entries[index];
+        let color = match i % 2 {
+            0 => browser.colors.normal_row_color,
+            _ => browser.colors.alt_row_color,
+        };
+        let mark = if browser.download.is_some() {
+            ""
+        } else if browser.selected.contains(&entry.path) {
+            "[x]"
+        } else {
+            "[ ]"
+        };
+        let (name, size) = if entry.is_dir {
+            (format!("{}/", entry.name), "-".to_string())
+        } else {
+            (entry.name.clone(), format_size(entry.size))
+        };
+        Row::new(vec![
+            Cell::from(mark),
+            Cell::from(name),
+            Cell::from(size),
+            Cell::from(format_mtime(entry.mtime)),
+        ])
+        .style(Style::new().fg(browser.colors.row_fg).bg(color))
+        .height(1)
+    });
+
+    let title = match &browser.download {
+        Some(targets) => format!(
+            " Download {} entries into: {} ",
+            targets.len(),
+            browser.cwd.display()
+        ),
+        None => format!(" Local files: {} ", browser.cwd.display()),
+    };
+    let bar = " █ ";
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(3),
+            Constraint::Min(20),
+            Constraint::Length(12),
+            Constraint::Length(18),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .title(Line::from(title).centered()),
+    )
+    .row_highlight_style(selected_style)
+    .highlight_symbol(Text::from(bar))
+    .bg(browser.colors.buffer_bg)
+    .highlight_spacing(HighlightSpacing::Always);
+    f.render_stateful_widget(table, chunks[0], &mut browser.state);
+
+    let status = if browser.filtering || !browser.filter.is_empty() {
+        let cursor = if browser.filtering { "_" } else { "" };
+        Line::from(format!(
+            "Filter: {}{} ({} matches)",
+            browser.filter,
+            cursor,
+            browser.view.len()
+        ))
+        .cyan()
+    } else {
+        let files = browser
+            .view
+            .iter()
+            .filter(|&&index| !browser.entries[index].is_dir)
+            .count();
+        let message = browser.status.clone().unwrap_or_else(|| {
+            let selected = match browser.download {
+                Some(_) => String::new(),
+                None => format!(", {} selected", browser.selected.len()),
+            };
+            format!(
+                "{} files, {} directories{}{}",
+                files,
+                browser.view.len() - files,
+                selected,
+                if browser.show_hidden {
+                    ", hidden shown"
+                } else {
+                    ""
+                }
+            )
+        });
+        Line::from(message.yellow())
+    };
+    f.render_widget(Paragraph::new(status), chunks[1]);
+
+    let commands = if browser.filtering {
+        "Type to filter | Enter: Keep filter | Esc: Clear filter"
+    } else if browser.download.is_some() {
+        "Esc: Cancel | Enter: Open | Backspace: Up | /: Filter | .: Hidden | s/S: Sort | g: Download here | p: Type a folder"
+    } else {
+        "Esc: Back | Space: Select | Enter: Open | Backspace: Up | /: Filter | .: Hidden | s/S: Sort | u: Upload"
+    };
+    f.render_widget(
+        Paragraph::new(Line::from(commands.dark_gray())).centered(),
+        chunks[2],
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn browser(names: &[&str]) -> (tempfile::TempDir, FileBrowser) {
+        let dir = tempfile::tempdir().unwrap();
+        for name in names {
+            match name.strip_suffix('/') {
+                Some(dir_name) => fs::create_dir(dir.path().join(dir_name)).unwrap(),
+                None => fs::write(dir.path().join(name), b"x").unwrap(),
+            }
+        }
+        let mut browser = FileBrowser::new();
+        browser.enter(dir.path().to_path_buf());
+        (dir, browser)
+    }
+
+    fn shown(browser: &FileBrowser) -> Vec<&str> {
+        browser
+            .view
+            .iter()
+            .map(|&index| browser.entries[index].name.as_str())
+            .collect()
+    }
+
+    fn filter(browser: &mut FileBrowser, pattern: &str) {
+        browser.clear_filter();
+        pattern.chars().for_each(|c| browser.push_filter(c));
+    }
+
+    #[test]
+    fn empty_patterns_match_everything() {
+        assert_eq!(fuzzy_score("", "anything"), Some(0));
+        assert_eq!(fuzzy_score("", ""), Some(0));
+    }
+
+    #[test]
+    fn characters_must_appear_in_order() {
+        assert!(fuzzy_score("rpt", "report.pdf").is_some());
+        assert!(fuzzy_score("tpr", "report.pdf").is_none());
+        assert!(fuzzy_score("rrr", "report.pdf").is_none());
+        assert!(fuzzy_score("x", "report.pdf").is_none());
+        assert!(fuzzy_score("report.pdfs", "report.pdf").is_none());
+    }
+
+    #[test]
+    fn matching_ignores_case() {
+        assert_eq!(
+            fuzzy_score("READ", "readme.md"),
+            fuzzy_score("read", "README.md")
+        );
+        assert!(fuzzy_score("Ä", "ärger.txt").is_some());
+    }
+
+    #[test]
+    fn consecutive_and_word_start_matches_score_higher() {
+        let consecutive = fuzzy_score("rep", "report.pdf").unwrap();
+        let scattered = fuzzy_score("rep", "rules_example.pdf").unwrap();
+        assert!(consecutive > scattered);
+
+        let word_start = fuzzy_score("f", "report_final.pdf").unwrap();
+        let inside = fuzzy_score("f", "reportxfinal.pdf").unwrap();
+        assert!(word_start > inside);
+
+        let early = fuzzy_score("a", "a.txt").unwrap();
+        let late = fuzzy_score("a", "zzzza.txt").unwrap();
+        assert!(early > late);
+    }
+
+    #[test]
+    fn filtering_shows_the_best_matches_first() {
+        let (_dir, mut browser) = browser(&[
+            "rules_example.pdf",
+            "report_final.pdf",
+            "repo.txt",
+            "readme.md",
+            "photos/",
+        ]);
+        filter(&mut browser, "rep");
+        assert_eq!(
+            shown(&browser),
+            ["repo.txt", "report_final.pdf", "rules_example.pdf"]
+        );
+        filter(&mut browser, "rpf");
+        assert_eq!(shown(&browser), ["report_final.pdf", "rules_example.pdf"]);
+        filter(&mut browser, "zzz");
+        assert!(shown(&browser).is_empty());
+        assert_eq!(browser.state.selected(), None);
+    }
+
+    #[test]
+    fn clearing_the_filter_restores_the_sorted_listing() {
+        let (_dir, mut browser) = browser(&["b.txt", "a.txt", "photos/"]);
+        assert_eq!(shown(&browser), ["photos", "a.txt", "b.txt"]);
+        filter(&mut browser, "b");
+        assert_eq!(shown(&browser), ["b.txt"]);
+        browser.pop_filter();
+        assert_eq!(shown(&browser), ["photos", "a.txt", "b.txt"]);
+        filter(&mut browser, "txt");
+        browser.clear_filter();
+        assert_eq!(shown(&browser), ["photos", "a.txt", "b.txt"]);
+        assert_eq!(browser.state.selected(), Some(0));
+    }
+
+    #[test]
+    fn filtering_leaves_hidden_entries_out() {
+        let (_dir, mut browser) = browser(&[".secret", "script.sh"]);
+        filter(&mut browser, "s");
+        assert_eq!(shown(&browser), ["script.sh"]);
+        browser.toggle_hidden();
+        assert_eq!(shown(&browser).len(), 2);
+    }
+}
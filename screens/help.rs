@@ -53,6 +53,48 @@ pub mod help_popup {
                         Style::default().fg(Color::Gray),
                     ),
                 ]),
+                Line::from(vec![
+                    Span::styled("o", Style::default().fg(Color::LightBlue)),
+                    Span::styled(
+                        ": This keybind is used to pick one or more files and send them to the connected server",
+                        Style::default().fg(Color::Gray),
+                    ),
+                ]),
+                Line::from(vec![
+                    Span::styled("f", Style::default().fg(Color::LightBlue)),
+                    Span::styled(
+                        ": This keybind is used to pick a folder and send it with everything inside it",
+                        Style::default().fg(Color::Gray),
+                    ),
+                ]),
+                Line::from(vec![
+                    Span::styled("b", Style::default().fg(Color::LightBlue)),
+                    Span::styled(
+                        ": This keybind is used to browse the local files and pick the ones to send, also opened by o and f when there is no desktop",
+                        Style::default().fg(Color::Gray),
+                    ),
+                ]),
+                Line::from(vec![
+                    Span::styled("p", Style::default().fg(Color::LightBlue)),
+                    Span::styled(
+                        ": This keybind is used to type or paste the paths of files to send, with Tab to complete them",
+                        Style::default().fg(Color::Gray),
+                    ),
+                ]),
+                Line::from(vec![
+                    Span::styled("r", Style::default().fg(Color::LightBlue)),
+                    Span::styled(
+                        ": This keybind is used to browse, download and delete the files stored on the connected server",
+                        Style::default().fg(Color::Gray),
+                    ),
+                ]),
+                Line::from(vec![
+                    Span::styled("c", Style::default().fg(Color::LightBlue)),
+                    Span::styled(
+                        ": This keybind is used to cancel the transfer highlighted on the Transfer screen",
+                        Style::default().fg(Color::Gray),
+                    ),
+                ]),
                 Line::from(vec![
                     Span::styled("Esc", Style::default().fg(Color::LightBlue)),
                     Span::styled(
@@ -1,18 +1,26 @@
+use super::dashboard::Data;
 use super::debug::DebugScreen;
 use super::host_type::HostTypePopup;
 use super::session::{Connection, Device, Transfer};
 use crate::core_mod::widgets::{Item, TableWidget};
 use crate::events::input::{
-    handle_backspace_key, handle_char_key, handle_d_key, handle_enter_key, handle_esc_key,
-    handle_help_key, handle_left_key, handle_n_key, handle_o_key, handle_q_key, handle_right_key,
+    handle_b_key, handle_backspace_key, handle_c_key, handle_char_key, handle_d_key,
+    handle_enter_key, handle_esc_key, handle_f_key, handle_file_browser_key, handle_help_key,
+    handle_left_key, handle_n_key, handle_o_key, handle_p_key, handle_path_entry_key, handle_q_key,
+    handle_r_key, handle_remote_key, handle_right_key, handle_s_key, stop_server_on_exit,
 };
 use crate::events::ui_update::UIUpdate;
 use crate::init::GLOBAL_RUNTIME;
-use crate::internal::session_store::load_sessions;
+use crate::internal::forward_port::get_local_ip;
+use crate::internal::remote_storage::show_listing;
+use crate::internal::session_store::{self, load_sessions};
+use crate::internal::transfer_queue::{QueueContext, TransferQueue};
+use crate::screens::connection_progress::ConnectionProgress;
 use crate::screens::{
-    error::error_widget::ErrorWidget, popup::InputBox, protocol_popup::ConnectionPopup,
+    error::error_widget::ErrorWidget, file_browser::FileBrowser, path_entry::PathEntry,
+    popup::InputBox, protocol_popup::ConnectionPopup, remote::RemoteBrowser,
 };
-use crate::state::state::ScreenState;
+use crate::state::state::{ConnectionState, ScreenState};
 use crossterm::event::{Event, KeyCode};
 use ratatui::layout::{Constraint, Layout};
 use ratatui::style::{Modifier, Style};
@@ -26,6 +34,10 @@ use ratatui::{
 use std::sync::mpsc::{Receiver, Sender, channel};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use tcp_client::app::Endpoint;
+use tcp_client::tls::tls::{TlsSettings, http_client};
+use tcp_server::protocol::codec::FramedStream;
+use tokio::task::AbortHandle;
 use tui_big_text::BigText;
 
 pub struct HomeDeps {
@@ -35,6 +47,9 @@ pub struct HomeDeps {
     pub error: Arc<Mutex<ErrorWidget>>,
     pub host: Arc<Mutex<HostTypePopup>>,
     pub debug_screen: Arc<Mutex<DebugScreen>>,
+    pub remote: Arc<Mutex<RemoteBrowser>>,
+    pub path_entry: Arc<Mutex<PathEntry>>,
+    pub file_browser: Arc<Mutex<FileBrowser>>,
     pub progress: Arc<Mutex<crate::screens::connection_progress::ConnectionProgress>>,
     pub state_snapshot: Arc<crate::state::state::StateSnapshot>,
 }
@@ -54,8 +69,16 @@ pub struct Home {
     pub ui_update_rx: Receiver<UIUpdate>,
     pub popup_message: Option<String>,
     pub current_screen: ScreenState,
-    // Changed tcp_stream from bool to Option<Arc<Mutex<TcpStream>>>
-    pub tcp_stream: Option<Arc<Mutex<tokio::net::TcpStream>>>,
+    // Changed tcp_stream from bool to Option<Arc<Mutex<FramedStream>>>
+    pub tcp_stream: Option<Arc<Mutex<FramedStream>>>,
+    /// TLS settings of the current connection, pinned to the server's certificate.
+    pub tls: Option<TlsSettings>,
+    /// Session token from pairing with the connected server.
+    pub session: Option<String>,
+    /// Where the connected server's TCP listener and HTTP routes are.
+    pub endpoint: Option<Endpoint>,
+    /// The connection attempt running in the background, which `Esc` cancels.
+    pub connecting: Option<AbortHandle>,
 }
 
 impl Home {
@@ -65,6 +88,44 @@ impl Home {
         deps: &mut HomeDeps,
     ) -> Result<(), Box<dyn std::error::Error>> {
         if let Event::Key(key) = event {
+            if self.current_screen == ScreenState::PathEntry {
+                let mut input_box = deps.input_box.lock().unwrap();
+                let mut path_entry = deps.path_entry.lock().unwrap();
+                let mut debug_screen = deps.debug_screen.lock().unwrap();
+                handle_path_entry_key(
+                    self,
+                    key.code,
+                    &mut input_box,
+                    &mut path_entry,
+                    &deps.state_snapshot,
+                    &mut debug_screen,
+                );
+                return Ok(());
+            }
+            if self.current_screen == ScreenState::Files {
+                let mut browser = deps.file_browser.lock().unwrap();
+                let mut debug_screen = deps.debug_screen.lock().unwrap();
+                handle_file_browser_key(
+                    self,
+                    key.code,
+                    &mut browser,
+                    &deps.state_snapshot,
+                    &mut debug_screen,
+                );
+                return Ok(());
+            }
+            if self.current_screen == ScreenState::Remote {
+                let mut remote = deps.remote.lock().unwrap();
+                let mut debug_screen = deps.debug_screen.lock().unwrap();
+                handle_remote_key(
+                    self,
+                    key.code,
+                    &mut remote,
+                    &deps.state_snapshot,
+                    &mut debug_screen,
+                );
+                return Ok(());
+            }
             match key.code {
                 KeyCode::Char('q') => {
                     let mut input_box = deps.input_box.lock().unwrap();
@@ -86,11 +147,12 @@ impl Home {
                 }
                 KeyCode::Esc => {
                     let mut input_box = deps.input_box.lock().unwrap();
-                    handle_esc_key(self, &mut input_box);
+                    handle_esc_key(self, &mut input_box, &deps.progress);
                 }
                 KeyCode::Char('o') => {
+                    let mut browser = deps.file_browser.lock().unwrap();
                     let mut debug_screen = deps.debug_screen.lock().unwrap();
-                    handle_o_key(self, &deps.state_snapshot, &mut debug_screen);
+                    handle_o_key(self, &mut browser, &deps.state_snapshot, &mut debug_screen);
                 }
                 KeyCode::Right => {
                     let mut input_box = deps.input_box.lock().unwrap();
@@ -132,6 +194,40 @@ impl Home {
                         handle_d_key(self, &mut debug_screen);
                     }
                     let mut input_box = deps.input_box.lock().unwrap();
+                    if c == 's' {
+                        handle_s_key(self, &input_box, &deps.progress);
+                    }
+                    if c == 'f' || c == 'b' {
+                        let mut browser = deps.file_browser.lock().unwrap();
+                        let mut debug_screen = deps.debug_screen.lock().unwrap();
+                        let handle = if c == 'f' { handle_f_key } else { handle_b_key };
+                        handle(
+                            self,
+                            &input_box,
+                            &mut browser,
+                            &deps.state_snapshot,
+                            &mut debug_screen,
+                        );
+                    }
+                    if c == 'p' {
+                        let mut path_entry = deps.path_entry.lock().unwrap();
+                        let mut debug_screen = deps.debug_screen.lock().unwrap();
+                        handle_p_key(
+                            self,
+                            &mut input_box,
+                            &mut path_entry,
+                            &deps.state_snapshot,
+                            &mut debug_screen,
+                        );
+                    }
+                    if c == 'c' {
+                        handle_c_key(self, &input_box, &deps.table, &deps.state_snapshot.queue);
+                    }
+                    if c == 'r' {
+                        let mut remote = deps.remote.lock().unwrap();
+                        let mut debug_screen = deps.debug_screen.lock().unwrap();
+                        handle_r_key(self, &input_box, &mut remote, &mut debug_screen);
+                    }
                     handle_char_key(c, &mut input_box);
                 }
                 KeyCode::Backspace => {
@@ -164,27 +260,54 @@ impl Home {
         let error = Arc::new(Mutex::new(ErrorWidget::new()));
         let host = Arc::new(Mutex::new(HostTypePopup::new()));
         let debug_screen = Arc::new(Mutex::new(DebugScreen::new()));
+        let remote = Arc::new(Mutex::new(RemoteBrowser::new()));
+        let path_entry = Arc::new(Mutex::new(PathEntry::default()));
+        let file_browser = Arc::new(Mutex::new(FileBrowser::new()));
+        let queue = TransferQueue::new(
+            crate::core_mod::core::configured_queue(),
+            table.clone(),
+            debug_screen.clone(),
+            self.ui_update_tx.clone(),
+        );
 
         {
             let records = load_sessions();
             let mut t = table.lock().unwrap();
             for rec in records {
-                t.items.push(Item::Device(Device {
-                    name: rec.name,
-                    ip: rec.ip,
-                    last_transfer: Transfer {
+                let last_transfer = match rec.transfers.last() {
+                    Some(last) => Transfer {
+                        status: last.status.clone(),
+                        size: format!("{} bytes", last.size),
+                        name: last.name.clone(),
+                    },
+                    None => Transfer {
                         status: rec.last_transfer,
                         size: "N/A".to_string(),
                         name: "N/A".to_string(),
                     },
+                };
+                let files = (!rec.transfers.is_empty())
+                    .then(|| rec.transfers.iter().map(Data::from).collect());
+                t.items.push(Item::Device(Device {
+                    name: rec.name,
+                    ip: rec.ip,
+                    last_transfer,
                     last_connection: Connection {
                         total: rec.last_connection.clone(),
                         format_date: rec.last_connection,
                     },
-                    files: None,
+                    files,
+                    server: None,
                 }));
             }
         }
+        let restored = queue.restore();
+        if restored > 0 {
+            debug_screen.lock().unwrap().push_line(format!(
+                "Restored {} unfinished transfers; they start once connected.",
+                restored
+            ));
+        }
 
         while self.running {
             while let Ok(update) = self.ui_update_rx.try_recv() {
@@ -196,6 +319,47 @@ impl Home {
                     UIUpdate::SwitchScreen(screen) => {
                         self.current_screen = screen;
                     }
+                    UIUpdate::TransferProgress { id, progress } => {
+                        let mut table = table.lock().unwrap();
+                        if let Some(data) = table.transfer_mut(id) {
+                            data.progress = Some(progress);
+                        }
+                    }
+                    UIUpdate::TransferFinished { id, status, digest } => {
+                        let mut table = table.lock().unwrap();
+                        if let Some(data) = table.transfer_mut(id) {
+                            data.progress = None;
+                            data.cancel = None;
+                            data.status = status;
+                            if digest.is_some() {
+                                data.digest = digest;
+                            }
+                        }
+                    }
+                    UIUpdate::TransferStatus { id, status } => {
+                        let mut table = table.lock().unwrap();
+                        if let Some(data) = table.transfer_mut(id) {
+                            data.progress = None;
+                            data.status = status;
+                        }
+                    }
+                    UIUpdate::Connected(connection) => {
+                        // A connection whose attempt was cancelled in the meantime is dropped.
+                        if self.connecting.take().is_some() {
+                            self.connected(*connection, &table, &progress, &queue);
+                        }
+                    }
+                    UIUpdate::ConnectionFailed(message) => {
+                        if self.connecting.take().is_some() {
+                            progress.lock().unwrap().state = ConnectionState::Failed(message);
+                        }
+                    }
+                    UIUpdate::RemoteListed { cwd, entries } => {
+                        show_listing(&mut remote.lock().unwrap(), cwd, entries);
+                    }
+                    UIUpdate::RemoteStatus(status) => {
+                        remote.lock().unwrap().status = Some(status);
+                    }
                 }
             }
 
@@ -207,7 +371,14 @@ impl Home {
                 host: host.clone(),
                 progress: progress.clone(),
                 debug_screen: debug_screen.clone(),
+                remote: remote.clone(),
+                path_entry: path_entry.clone(),
+                file_browser: file_browser.clone(),
+                queue: queue.clone(),
                 stream: self.tcp_stream.is_some(),
+                tls: self.tls.clone(),
+                session: self.session.clone(),
+                endpoint: self.endpoint.clone(),
             });
 
             let mut deps = HomeDeps {
@@ -217,6 +388,9 @@ impl Home {
                 error: error.clone(),
                 host: host.clone(),
                 debug_screen: debug_screen.clone(),
+                remote: remote.clone(),
+                path_entry: path_entry.clone(),
+                file_browser: file_browser.clone(),
                 progress: progress.clone(),
                 state_snapshot: state_snapshot.clone(),
             };
@@ -228,9 +402,75 @@ impl Home {
                 self.handle_event(event, &mut deps)?;
             }
         }
+        stop_server_on_exit(&progress);
         Ok(())
     }
 
+    /// Takes over a freshly paired `connection`, pinning its certificate or forgetting the previous one when it presents none, recording the session, showing what the server reported about itself in the session's row of `table` and letting `queue` run its transfers over it.
+    fn connected(
+        &mut self,
+        connection: tcp_client::app::Connection,
+        table: &Mutex<TableWidget>,
+        progress: &Mutex<ConnectionProgress>,
+        queue: &TransferQueue,
+    ) {
+        let address = connection.endpoint.address();
+        log::info!(
+            "Connected to {} ({}, zync {}), HTTP routes at {}",
+            address,
+            connection.server.name,
+            connection.server.version,
+            connection
+                .endpoint
+                .url("")
+                .unwrap_or_else(|| "none, uploading over TCP".to_string())
+        );
+        let server = connection
+            .fingerprint
+            .clone()
+            .unwrap_or_else(|| address.clone());
+        self.tls = connection.fingerprint.map(|fingerprint| {
+            log::info!("{} presented certificate {}", address, fingerprint);
+            session_store::pin_fingerprint(&address, &fingerprint);
+            TlsSettings::pinned(fingerprint)
+        });
+        progress.lock().unwrap().state = ConnectionState::Connected;
+        let hostname = whoami::username();
+        for item in table.lock().unwrap().items.iter_mut() {
+            let Item::Device(device) = item else {
+                continue;
+            };
+            if device.name == hostname {
+                device.server = Some(connection.server.clone());
+            }
+        }
+        let ip = get_local_ip().unwrap_or_else(|_e| "unknown".to_string());
+        let now = chrono::Utc::now().to_rfc3339();
+        session_store::update_session_record(session_store::SessionRecord {
+            name: hostname,
+            ip,
+            last_transfer: "N/A".to_string(),
+            last_connection: now,
+            transfers: Vec::new(),
+        });
+        let stream = Arc::new(Mutex::new(connection.stream));
+        let client = match (&self.tls, &connection.endpoint.http_port) {
+            (Some(tls), Some(_)) => http_client(Some(tls), Some(&connection.session))
+                .inspect_err(|e| log::error!("Failed to prepare HTTPS uploads: {}", e))
+                .ok(),
+            _ => None,
+        };
+        queue.connect(QueueContext {
+            stream: stream.clone(),
+            client,
+            endpoint: connection.endpoint.clone(),
+            server,
+        });
+        self.tcp_stream = Some(stream);
+        self.session = Some(connection.session);
+        self.endpoint = Some(connection.endpoint);
+    }
+
     fn create_big_text() -> (BigText<'static>, Vec<Line<'static>>) {
         let text = tui_big_text::BigTextBuilder::default()
             .pixel_size(tui_big_text::PixelSize::Quadrant)
@@ -338,6 +578,10 @@ impl Home {
             popup_message: None,
             // Initialize tcp_stream as None (it can be set later as needed)
             tcp_stream: None,
+            tls: None,
+            session: None,
+            endpoint: None,
+            connecting: None,
         }
     }
 }
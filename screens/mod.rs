@@ -2,9 +2,12 @@ pub mod connection_progress;
 pub mod dashboard;
 pub mod debug;
 pub mod error;
+pub mod file_browser;
 pub mod help;
 pub mod home;
 pub mod host_type;
+pub mod path_entry;
 pub mod popup;
 pub mod protocol_popup;
+pub mod remote;
 pub mod session;
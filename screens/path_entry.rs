@@ -0,0 +1,89 @@
+use crate::screens::popup::InputBox;
+use crate::utils::calculate::centered_rect;
+use ratatui::Frame;
+use ratatui::layout::{Alignment, Constraint, Direction, Layout};
+use ratatui::style::{Color, Style};
+use ratatui::widgets::{Block, Borders, Clear, Paragraph, Wrap};
+use tcp_server::protocol::messages::RemoteEntry;
+
+/// The path entry screen: local paths typed or pasted into the input box to be uploaded, or the folder to download into, for machines where no file dialog is available.
+#[derive(Debug, Default)]
+pub struct PathEntry {
+    /// What the last tab completion could still complete to.
+    pub candidates: Vec<String>,
+    /// Outcome of the last action, shown under the input.
+    pub status: Option<String>,
+    /// Remote entries waiting for the folder to download them into; while set, the screen asks for that folder instead of paths to upload.
+    pub download: Option<Vec<RemoteEntry>>,
+}
+
+impl PathEntry {
+    /// Forgets the completions, the status and the pending download of an earlier visit.
+    pub fn reset(&mut self) {
+        self.candidates.clear();
+        self.status = None;
+        self.download = None;
+    }
+}
+
+/// Draws the path entry screen with `input` holding the paths typed so far.
+pub fn draw_path_entry(frame: &mut Frame, path_entry: &PathEntry, input: &InputBox) {
+    let area = centered_rect(60, 40, frame.area());
+    frame.render_widget(Clear, area);
+    let (title, prompt, instructions) = match &path_entry.download {
+        Some(targets) => (
+            "Download Folder",
+            format!(
+                "Enter the folder to download {} entries into:",
+                targets.len()
+            ),
+            "Tab to complete • Enter to download • Esc to cancel",
+        ),
+        None => (
+            "Upload Paths",
+            "Enter the files or folders to upload, separated by spaces (paste dropped files here):"
+                .to_string(),
+            "Tab to complete • Enter to upload • Esc to cancel",
+        ),
+    };
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(Style::default().fg(Color::Cyan))
+        .style(Style::default().fg(Color::LightYellow));
+    frame.render_widget(block, area);
+
+    let inner_area = area.inner(ratatui::layout::Margin {
+        vertical: 1,
+        horizontal: 2,
+    });
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1), // prompt
+            Constraint::Length(3), // input box
+            Constraint::Length(1), // spacer
+            Constraint::Min(1),    // candidates or status
+            Constraint::Length(1), // instructions
+        ])
+        .split(inner_area);
+
+    let prompt = Paragraph::new(prompt).style(Style::default().fg(Color::White));
+    frame.render_widget(prompt, chunks[0]);
+
+    input.draw_in_popup(frame, chunks[1]);
+
+    let (details, color) = match &path_entry.status {
+        Some(status) => (status.clone(), Color::LightRed),
+        None => (path_entry.candidates.join("  "), Color::Gray),
+    };
+    let details = Paragraph::new(details)
+        .style(Style::default().fg(color))
+        .wrap(Wrap { trim: true });
+    frame.render_widget(details, chunks[3]);
+
+    let instructions = Paragraph::new(instructions)
+        .alignment(Alignment::Center)
+        .style(Style::default().fg(Color::DarkGray));
+    frame.render_widget(instructions, chunks[4]);
+}
@@ -210,6 +210,25 @@ impl InputBox {
         self.character_index = 0;
     }
 
+    /// The whole input, including the characters scrolled out of view.
+    pub fn text(&self) -> String {
+        self.removed_char
+            .iter()
+            .copied()
+            .chain(self.input.chars())
+            .collect()
+    }
+
+    /// Replaces the input with `text`, the cursor at its end.
+    pub fn set_text(&mut self, text: &str) {
+        self.input.clear();
+        self.removed_char.clear();
+        self.reset_cursor();
+        for c in text.chars() {
+            self.enter_char(c);
+        }
+    }
+
     pub fn submit_message(&mut self) -> Result<String, &'static str> {
         let mut input_msg = self.input.clone();
 
@@ -124,8 +124,14 @@ impl ConnectionPopup {
         f.render_widget(Clear, area);
 
         let (title, prompt_text) = match self.mode {
-            ConnectionInputMode::Server => ("Port Number", "Enter port number:"),
-            ConnectionInputMode::Client => ("TCP IP Address", "Enter IP Address:"),
+            ConnectionInputMode::Server => (
+                "Port Number",
+                "Enter port number (optionally followed by a storage directory):",
+            ),
+            ConnectionInputMode::Client => (
+                "TCP IP Address",
+                "Enter IP address followed by the pairing code shown on the receiver:",
+            ),
         };
 
         let block = Block::default()
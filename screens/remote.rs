@@ -0,0 +1,359 @@
+use crate::core_mod::widgets::TableColors;
+use crate::utils::format::format_size;
+use ratatui::{
+    Frame,
+    layout::{Constraint, Direction, Layout},
+    prelude::Stylize,
+    style::{Color, Modifier, Style, palette::tailwind},
+    text::{Line, Span, Text},
+    widgets::{
+        Block, BorderType, Borders, Cell, HighlightSpacing, Paragraph, Row, Table, TableState,
+    },
+};
+use std::collections::BTreeSet;
+use tcp_server::protocol::manifest::EntryKind;
+use tcp_server::protocol::messages::RemoteEntry;
+
+/// Column the remote listing is sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortKey {
+    #[default]
+    Name,
+    Size,
+    Modified,
+    Uploader,
+}
+
+impl SortKey {
+    fn next(self) -> Self {
+        match self {
+            SortKey::Name => SortKey::Size,
+            SortKey::Size => SortKey::Modified,
+            SortKey::Modified => SortKey::Uploader,
+            SortKey::Uploader => SortKey::Name,
+        }
+    }
+}
+
+/// The Remote screen: one directory of the connected server's storage, listed in a sortable table whose entries can be selected for download or deletion.
+#[derive(Debug)]
+pub struct RemoteBrowser {
+    /// The listed directory, relative to the storage root; empty for the root itself.
+    pub cwd: String,
+    pub entries: Vec<RemoteEntry>,
+    /// Paths of the entries marked for the next download or deletion.
+    pub selected: BTreeSet<String>,
+    pub state: TableState,
+    pub sort: SortKey,
+    pub descending: bool,
+    /// Set after a first delete key press; the next one deletes the targets.
+    pub confirm_delete: bool,
+    /// Outcome of the last action, shown under the table.
+    pub status: Option<String>,
+    pub colors: TableColors,
+}
+
+impl RemoteBrowser {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self {
+            cwd: String::new(),
+            entries: Vec::new(),
+            selected: BTreeSet::new(),
+            state: TableState::default(),
+            sort: SortKey::default(),
+            descending: false,
+            confirm_delete: false,
+            status: None,
+            colors: TableColors::new(&tailwind::CYAN),
+        }
+    }
+
+    /// Replaces the listing with `entries`, keeping the highlighted entry and the selections that are still listed.
+    pub fn set_entries(&mut self, entries: Vec<RemoteEntry>) {
+        let highlighted = self.highlighted().map(|entry| entry.path.clone());
+        self.entries = entries;
+        self.selected
+            .retain(|path| self.entries.iter().any(|entry| &entry.path == path));
+        self.sort_entries();
+        let index = highlighted
+            .and_then(|path| self.entries.iter().position(|entry| entry.path == path))
+            .unwrap_or(0);
+        self.state
+            .select((!self.entries.is_empty()).then_some(index.min(self.entries.len() - 1)));
+    }
+
+    pub fn highlighted(&self) -> Option<&RemoteEntry> {
+        self.state.selected().and_then(|i| self.entries.get(i))
+    }
+
+    pub fn next(&mut self) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let i = match self.state.selected() {
+            Some(i) if i + 1 < self.entries.len() => i + 1,
+            _ => 0,
+        };
+        self.state.select(Some(i));
+    }
+
+    pub fn previous(&mut self) {
+        if self.entries.is_empty() {
+            return;
+        }
+        let i = match self.state.selected() {
+            Some(0) | None => self.entries.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.state.select(Some(i));
+    }
+
+    /// Marks the highlighted entry, or unmarks it if it already is.
+    pub fn toggle_selected(&mut self) {
+        let Some(path) = self.highlighted().map(|entry| entry.path.clone()) else {
+            return;
+        };
+        if !self.selected.remove(&path) {
+            self.selected.insert(path);
+        }
+    }
+
+    /// The entries an action applies to: the marked ones, or the highlighted one if none are marked.
+    pub fn targets(&self) -> Vec<RemoteEntry> {
+        if self.selected.is_empty() {
+            return self.highlighted().cloned().into_iter().collect();
+        }
+        self.entries
+            .iter()
+            .filter(|entry| self.selected.contains(&entry.path))
+            .cloned()
+            .collect()
+    }
+
+    /// Sorts by the next column, in ascending order.
+    pub fn cycle_sort(&mut self) {
+        self.sort = self.sort.next();
+        self.descending = false;
+        self.resort();
+    }
+
+    pub fn reverse_sort(&mut self) {
+        self.descending = !self.descending;
+        self.resort();
+    }
+
+    /// Moves into the directory at `path`, dropping the current listing and selections until it is refreshed.
+    pub fn enter(&mut self, path: &str) {
+        self.cwd = path.to_string();
+        self.clear();
+    }
+
+    /// Moves to the parent of the listed directory.
+    ///
+    /// # Returns
+    /// `false` if the storage root is already listed.
+    pub fn leave(&mut self) -> bool {
+        if self.cwd.is_empty() {
+            return false;
+        }
+        self.cwd = self
+            .cwd
+            .rsplit_once('/')
+            .map(|(parent, _)| parent.to_string())
+            .unwrap_or_default();
+        self.clear();
+        true
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+        self.selected.clear();
+        self.state.select(None);
+        self.confirm_delete = false;
+    }
+
+    fn resort(&mut self) {
+        let highlighted = self.highlighted().map(|entry| entry.path.clone());
+        self.sort_entries();
+        if let Some(path) = highlighted {
+            let index = self.entries.iter().position(|entry| entry.path == path);
+            self.state.select(index);
+        }
+    }
+
+    /// Sorts directories before files, each by the current column, ties broken by path.
+    fn sort_entries(&mut self) {
+        let (sort, descending) = (self.sort, self.descending);
+        self.entries.sort_by(|a, b| {
+            let by_column = match sort {
+                SortKey::Name => a.path.cmp(&b.path),
+                SortKey::Size => a.size.cmp(&b.size),
+                SortKey::Modified => a.mtime.cmp(&b.mtime),
+                SortKey::Uploader => a.uploader.cmp(&b.uploader),
+            };
+            let by_column = if descending {
+                by_column.reverse()
+            } else {
+                by_column
+            };
+            is_file(a)
+                .cmp(&is_file(b))
+                .then(by_column)
+                .then_with(|| a.path.cmp(&b.path))
+        });
+    }
+
+    fn header_label(&self, key: SortKey, label: &str) -> String {
+        if self.sort != key {
+            return label.to_string();
+        }
+        let arrow = if self.descending { "▼" } else { "▲" };
+        format!("{label} {arrow}")
+    }
+
+    /// Name of `entry` relative to the listed directory, with a trailing `/` for directories.
+    fn display_name(&self, entry: &RemoteEntry) -> String {
+        let name = entry
+            .path
+            .strip_prefix(&self.cwd)
+            .map(|rest| rest.trim_start_matches('/'))
+            .unwrap_or(&entry.path);
+        match entry.kind {
+            EntryKind::Directory => format!("{name}/"),
+            EntryKind::File => name.to_string(),
+        }
+    }
+}
+
+fn is_file(entry: &RemoteEntry) -> bool {
+    entry.kind == EntryKind::File
+}
+
+fn format_mtime(mtime: Option<u64>) -> String {
+    mtime
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs as i64, 0))
+        .map(|time| time.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_else(|| "-".to_string())
+}
+
+pub fn draw_remote_browser(f: &mut Frame, browser: &mut RemoteBrowser) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(3),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .split(f.area());
+
+    let header_style = Style::default()
+        .fg(browser.colors.header_fg)
+        .bg(browser.colors.header_bg);
+    let selected_style = Style::default()
+        .add_modifier(Modifier::REVERSED)
+        .fg(browser.colors.selected_style_fg);
+    let header = [
+        String::new(),
+        browser.header_label(SortKey::Name, "Name"),
+        browser.header_label(SortKey::Size, "Size"),
+        browser.header_label(SortKey::Modified, "Modified"),
+        browser.header_label(SortKey::Uploader, "Uploader"),
+        "Digest".to_string(),
+    ]
+    .into_iter()
+    .map(Cell::from)
+    .collect::<Row>()
+    .style(header_style)
+    .height(1);
+
+    let rows = browser.entries.iter().enumerate().map(|(i, entry)| {
+        let color = match i % 2 {
+            0 => browser.colors.normal_row_color,
+            _ => browser.colors.alt_row_color,
+        };
+        let mark = if browser.selected.contains(&entry.path) {
+            "[x]"
+        } else {
+            "[ ]"
+        };
+        let size = match entry.kind {
+            EntryKind::Directory => "-".to_string(),
+            EntryKind::File => format_size(entry.size),
+        };
+        Row::new(vec![
+            Cell::from(mark),
+            Cell::from(browser.display_name(entry)),
+            Cell::from(size),
+            Cell::from(format_mtime(entry.mtime)),
+            Cell::from(entry.uploader.clone().unwrap_or_else(|| "-".to_string())),
+            Cell::from(
+                entry
+                    .digest
+                    .as_ref()
+                    .map(|digest| digest.short().to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+            ),
+        ])
+        .style(Style::new().fg(browser.colors.row_fg).bg(color))
+        .height(1)
+    });
+
+    let title = format!(" Remote storage: /{} ", browser.cwd);
+    let bar = " █ ";
+    let table = Table::new(
+        rows,
+        [
+            Constraint::Length(3),
+            Constraint::Min(20),
+            Constraint::Length(12),
+            Constraint::Length(18),
+            Constraint::Length(14),
+            Constraint::Length(18),
+        ],
+    )
+    .header(header)
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded)
+            .title(Line::from(title).centered()),
+    )
+    .row_highlight_style(selected_style)
+    .highlight_symbol(Text::from(bar))
+    .bg(browser.colors.buffer_bg)
+    .highlight_spacing(HighlightSpacing::Always);
+    f.render_stateful_widget(table, chunks[0], &mut browser.state);
+
+    let status = if browser.confirm_delete {
+        Line::from(Span::styled(
+            format!(
+                "Press x again to delete {} entries from the server, any other key to cancel",
+                browser.targets().len()
+            ),
+            Style::default().fg(Color::Red),
+        ))
+    } else {
+        let files = browser
+            .entries
+            .iter()
+            .filter(|entry| is_file(entry))
+            .count();
+        let message = browser.status.clone().unwrap_or_else(|| {
+            format!(
+                "{} files, {} directories, {} selected",
+                files,
+                browser.entries.len() - files,
+                browser.selected.len()
+            )
+        });
+        Line::from(message.yellow())
+    };
+    f.render_widget(Paragraph::new(status), chunks[1]);
+
+    let commands = "Esc: Back | r: Refresh | Space: Select | Enter: Open | Backspace: Up | s/S: Sort/Reverse | g: Download | x: Delete";
+    f.render_widget(
+        Paragraph::new(Line::from(commands.dark_gray())).centered(),
+        chunks[2],
+    );
+}
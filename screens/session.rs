@@ -12,6 +12,8 @@ use std::sync::{Arc, Mutex};
 use crate::screens::connection_progress::ConnectionProgress;
 use crate::screens::dashboard::Data;
 use crate::state::state::ConnectionState;
+use crate::utils::format::format_size;
+use tcp_server::protocol::messages::WelcomeReply;
 
 #[derive(Debug, Clone)]
 pub struct Device {
@@ -20,12 +22,15 @@ pub struct Device {
     pub last_transfer: Transfer,
     pub last_connection: Connection,
     pub files: Option<Vec<Data>>,
+    /// What the server this session connected to said about itself, shown in the details panel.
+    pub server: Option<WelcomeReply>,
 }
 
 impl Device {
     pub fn new_empty() -> Self {
         Self {
             files: None,
+            server: None,
             name: String::new(),
             ip: String::new(),
             last_transfer: Transfer {
@@ -57,7 +62,7 @@ pub struct Connection {
 pub fn session_details_ui(table: &mut TableWidget) -> Paragraph<'static> {
     let item_index = table.state.selected().unwrap_or(0);
     if let Some(Item::Device(device)) = table.items.get(item_index) {
-        let details = vec![
+        let mut details = vec![
             Line::from(vec!["Name: ".into(), device.name.clone().yellow()]),
             Line::from(vec!["IP Address: ".into(), device.ip.clone().green()]),
             Line::from(""),
@@ -83,6 +88,9 @@ pub fn session_details_ui(table: &mut TableWidget) -> Paragraph<'static> {
                 device.last_connection.format_date.clone().white(),
             ]),
         ];
+        if let Some(server) = &device.server {
+            details.extend(server_details(server));
+        }
 
         return Paragraph::new(Text::from(details))
             .block(Block::default().borders(Borders::ALL).title("Details"))
@@ -94,6 +102,37 @@ pub fn session_details_ui(table: &mut TableWidget) -> Paragraph<'static> {
         .alignment(Alignment::Center)
 }
 
+/// The details panel lines describing the server a session connected to.
+fn server_details(server: &WelcomeReply) -> Vec<Line<'static>> {
+    let features = server
+        .features
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    vec![
+        Line::from(""),
+        Line::from(vec!["Server:".bold()]),
+        Line::from(vec!["  Name: ".into(), server.name.clone().yellow()]),
+        Line::from(vec![
+            "  Version: ".into(),
+            format!("zync {} (protocol {})", server.version, server.protocol).white(),
+        ]),
+        Line::from(vec![
+            "  Free Space: ".into(),
+            server
+                .free_space
+                .map_or_else(|| "unknown".to_string(), format_size)
+                .green(),
+        ]),
+        Line::from(vec!["  Features: ".into(), features.cyan()]),
+        Line::from(vec![
+            "  Commands: ".into(),
+            server.commands.join(", ").white(),
+        ]),
+    ]
+}
+
 pub fn session_table_ui(table: &mut TableWidget) -> Table<'_> {
     let header_style = Style::default()
         .fg(table.colors.header_fg)